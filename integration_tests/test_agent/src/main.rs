@@ -1,3 +1,4 @@
+use smappservice_rs::LaunchdPlist;
 use std::io;
 use std::path::PathBuf;
 use std::process::{Command, Output};
@@ -79,25 +80,15 @@ fn get_test_app_path() -> io::Result<PathBuf> {
     println!("Creating plist file at: {:?}", plist_path);
 
     // Use the test app binary path for the ProgramArguments
-    let plist_content = format!(
-        r#"<?xml version="1.0" encoding="UTF-8"?>
-<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
-<plist version="1.0">
-<dict>
-    <key>Label</key>
-    <string>com.example.agent</string>
-    <key>ProgramArguments</key>
-    <array>
-        <string>{}</string> <!-- Path to the binary the agent launches -->
-    </array>
-    <key>RunAtLoad</key>
-    <true/> <!-- Launch at login -->
-</dict>
-</plist>"#,
-        test_app_path.to_str().unwrap()
-    );
-
-    std::fs::write(&plist_path, plist_content)?;
+    let plist = LaunchdPlist {
+        program_arguments: Some(vec![test_app_path.to_str().unwrap().to_string()]),
+        run_at_load: Some(true),
+        ..LaunchdPlist::new("com.example.agent")
+    };
+
+    plist
+        .write_to_file(&plist_path)
+        .map_err(|e| io::Error::other(e.to_string()))?;
     println!("Plist file created successfully.");
 
     Ok(test_app_path)
@@ -0,0 +1,341 @@
+//! The register, prompt and wait sequence for services that need the user's approval.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use thiserror::Error;
+
+use crate::{AppService, CancellationToken, ServiceManagementError, ServiceStatus, WaitError};
+
+/// How an [`ApprovalFlow`] ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApprovalOutcome {
+    /// The service was enabled before the flow started. Nothing was registered.
+    AlreadyEnabled,
+
+    /// The service was registered and is now enabled.
+    Approved,
+
+    /// The user denied the launch, so registration failed with
+    /// [`ServiceManagementError::LaunchDeniedByUser`].
+    Denied,
+
+    /// The service still required approval when the timeout passed.
+    TimedOut,
+
+    /// The [`CancellationToken`] was cancelled while waiting for approval.
+    Cancelled,
+}
+
+/// A step of an [`ApprovalFlow`] the UI may want to react to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApprovalEvent {
+    /// The service is registered and waiting for the user to enable it in System Settings. The
+    /// UI should show instructions.
+    ///
+    /// `settings_opened` is `false` when System Settings was already opened by an earlier run of
+    /// the same flow, or when [`ApprovalOptions::open_settings`] is off.
+    ApprovalRequired { settings_opened: bool },
+
+    /// The flow finished. Sent just before [`ApprovalFlow::run`] returns an outcome.
+    Finished(ApprovalOutcome),
+}
+
+/// An error that stopped an [`ApprovalFlow`] before it reached an [`ApprovalOutcome`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum ApprovalError {
+    /// The flow is already running on another thread.
+    #[error("the approval flow is already running")]
+    AlreadyRunning,
+
+    /// Registering the service failed for a reason other than the user denying it.
+    #[error("failed to register the service: {0}")]
+    Register(#[from] ServiceManagementError),
+
+    /// The service was neither enabled nor waiting for approval, for example because it was
+    /// unregistered elsewhere while the flow was waiting.
+    #[error("the service is {0}, not waiting for approval")]
+    UnexpectedStatus(ServiceStatus),
+}
+
+/// Settings for an [`ApprovalFlow`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ApprovalOptions {
+    /// How long to wait for the user to approve the service.
+    pub timeout: Duration,
+
+    /// Whether to open the Login Items section of System Settings when approval is required.
+    pub open_settings: bool,
+}
+
+impl Default for ApprovalOptions {
+    /// Waits up to five minutes and opens System Settings.
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(300),
+            open_settings: true,
+        }
+    }
+}
+
+/// Registers a service and walks the user through approving it.
+///
+/// [`run`](#method.run) goes through these steps:
+///
+/// 1. If the service is already [`ServiceStatus::Enabled`], finish with
+///    [`ApprovalOutcome::AlreadyEnabled`].
+/// 2. Register it. [`ServiceManagementError::AlreadyRegistered`] is not an error, and
+///    [`ServiceManagementError::LaunchDeniedByUser`] finishes with [`ApprovalOutcome::Denied`].
+/// 3. If the service requires approval, open System Settings, send
+///    [`ApprovalEvent::ApprovalRequired`] and wait until the status changes.
+///
+/// Keep one flow per service for the lifetime of the app. System Settings is opened at most once
+/// per flow, so running it again, for example each time an onboarding window reappears, doesn't
+/// keep pulling System Settings to the front. A flow can't run on two threads at once: the
+/// second call fails with [`ApprovalError::AlreadyRunning`].
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use smappservice_rs::{
+///     AppService, ApprovalEvent, ApprovalFlow, ApprovalOptions, ApprovalOutcome,
+///     CancellationToken, ServiceType,
+/// };
+///
+/// let flow = ApprovalFlow::new(
+///     AppService::new(ServiceType::Daemon {
+///         plist_name: "com.example.myapp.daemon.plist",
+///     }),
+///     ApprovalOptions::default(),
+/// );
+/// let outcome = flow.run(&CancellationToken::new(), |event| {
+///     if let ApprovalEvent::ApprovalRequired { .. } = event {
+///         println!("Turn on MyApp under \"Allow in the Background\" in System Settings");
+///     }
+/// });
+/// match outcome {
+///     Ok(ApprovalOutcome::Approved | ApprovalOutcome::AlreadyEnabled) => println!("Ready"),
+///     Ok(outcome) => println!("Not enabled: {:?}", outcome),
+///     Err(e) => eprintln!("{}", e),
+/// }
+/// ```
+pub struct ApprovalFlow {
+    service: AppService,
+    options: ApprovalOptions,
+    running: AtomicBool,
+    prompted: AtomicBool,
+}
+
+impl std::fmt::Debug for ApprovalFlow {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ApprovalFlow")
+            .field("service_type", &self.service.service_type())
+            .field("options", &self.options)
+            .field("running", &self.running)
+            .field("prompted", &self.prompted)
+            .finish()
+    }
+}
+
+/// Clears the running flag when a run ends, even by panic.
+struct Running<'f>(&'f AtomicBool);
+
+impl Drop for Running<'_> {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::Release);
+    }
+}
+
+impl ApprovalFlow {
+    /// Creates a flow for `service`.
+    pub fn new(service: AppService, options: ApprovalOptions) -> Self {
+        Self {
+            service,
+            options,
+            running: AtomicBool::new(false),
+            prompted: AtomicBool::new(false),
+        }
+    }
+
+    /// Returns the service the flow registers.
+    pub fn service(&self) -> &AppService {
+        &self.service
+    }
+
+    /// Returns whether [`run`](#method.run) is in progress.
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::Acquire)
+    }
+
+    /// Runs the flow, blocking until it finishes. `on_event` is called on the calling thread.
+    pub fn run(
+        &self,
+        cancel: &CancellationToken,
+        mut on_event: impl FnMut(ApprovalEvent),
+    ) -> Result<ApprovalOutcome, ApprovalError> {
+        if self
+            .running
+            .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
+            .is_err()
+        {
+            return Err(ApprovalError::AlreadyRunning);
+        }
+        let _running = Running(&self.running);
+
+        let outcome = self.run_steps(cancel, &mut on_event)?;
+        on_event(ApprovalEvent::Finished(outcome));
+        Ok(outcome)
+    }
+
+    fn run_steps(
+        &self,
+        cancel: &CancellationToken,
+        on_event: &mut impl FnMut(ApprovalEvent),
+    ) -> Result<ApprovalOutcome, ApprovalError> {
+        if self.service.status() == ServiceStatus::Enabled {
+            return Ok(ApprovalOutcome::AlreadyEnabled);
+        }
+        match self.service.register() {
+            Ok(()) | Err(ServiceManagementError::AlreadyRegistered) => {}
+            Err(ServiceManagementError::LaunchDeniedByUser) => return Ok(ApprovalOutcome::Denied),
+            Err(error) => return Err(error.into()),
+        }
+
+        match self.service.status() {
+            ServiceStatus::Enabled => return Ok(ApprovalOutcome::Approved),
+            ServiceStatus::RequiresApproval => {}
+            status => return Err(ApprovalError::UnexpectedStatus(status)),
+        }
+
+        let settings_opened =
+            self.options.open_settings && !self.prompted.swap(true, Ordering::AcqRel);
+        if settings_opened {
+            self.service.open_system_settings();
+        }
+        on_event(ApprovalEvent::ApprovalRequired { settings_opened });
+
+        match self.service.wait_for(
+            |status| status != ServiceStatus::RequiresApproval,
+            self.options.timeout,
+            cancel,
+        ) {
+            Ok(ServiceStatus::Enabled) => Ok(ApprovalOutcome::Approved),
+            Ok(status) => Err(ApprovalError::UnexpectedStatus(status)),
+            Err(WaitError::TimedOut { .. }) => Ok(ApprovalOutcome::TimedOut),
+            Err(WaitError::Cancelled { .. }) => Ok(ApprovalOutcome::Cancelled),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    use std::thread;
+
+    use super::*;
+    use crate::{FaultInjectingBackend, Operation, ServiceType, SimulatedBackend};
+
+    const DAEMON: ServiceType<'static> = ServiceType::Daemon {
+        plist_name: "com.example.daemon.plist",
+    };
+
+    fn flow(backend: impl crate::ServiceBackend + 'static, timeout: Duration) -> ApprovalFlow {
+        ApprovalFlow::new(
+            AppService::with_backend(DAEMON, backend),
+            ApprovalOptions {
+                timeout,
+                ..ApprovalOptions::default()
+            },
+        )
+    }
+
+    #[test]
+    fn test_approved() {
+        let backend = SimulatedBackend::new();
+        let flow = flow(backend.clone(), Duration::from_secs(10));
+
+        let mut events = Vec::new();
+        let (required, approval_required) = mpsc::channel();
+        let outcome = thread::scope(|scope| {
+            let run = scope.spawn(|| {
+                flow.run(&CancellationToken::new(), |event| {
+                    if let ApprovalEvent::ApprovalRequired { .. } = event {
+                        required.send(()).unwrap();
+                    }
+                    events.push(event);
+                })
+            });
+            // Approving earlier could let the flow see the service enabled right after
+            // registering, skipping the approval step.
+            approval_required
+                .recv_timeout(Duration::from_secs(10))
+                .unwrap();
+            assert!(backend.approve(&DAEMON));
+            run.join().unwrap()
+        });
+        assert_eq!(outcome, Ok(ApprovalOutcome::Approved));
+        assert_eq!(
+            events,
+            [
+                ApprovalEvent::ApprovalRequired {
+                    settings_opened: true
+                },
+                ApprovalEvent::Finished(ApprovalOutcome::Approved),
+            ]
+        );
+        assert_eq!(backend.settings_opened(), 1);
+
+        assert_eq!(
+            flow.run(&CancellationToken::new(), |_| {}),
+            Ok(ApprovalOutcome::AlreadyEnabled)
+        );
+        assert!(!flow.is_running());
+    }
+
+    #[test]
+    fn test_settings_opened_once() {
+        let backend = SimulatedBackend::new();
+        let flow = flow(backend.clone(), Duration::ZERO);
+
+        for settings_opened in [true, false] {
+            let mut events = Vec::new();
+            let outcome = flow.run(&CancellationToken::new(), |e| events.push(e));
+            assert_eq!(outcome, Ok(ApprovalOutcome::TimedOut));
+            assert_eq!(
+                events[0],
+                ApprovalEvent::ApprovalRequired { settings_opened }
+            );
+        }
+        assert_eq!(backend.settings_opened(), 1);
+    }
+
+    #[test]
+    fn test_denied_and_reentry() {
+        let backend = FaultInjectingBackend::new(SimulatedBackend::new());
+        backend.fail_next(
+            &DAEMON,
+            Operation::Register,
+            ServiceManagementError::LaunchDeniedByUser,
+        );
+        let flow = flow(backend.clone(), Duration::from_secs(60));
+        assert_eq!(
+            flow.run(&CancellationToken::new(), |_| {}),
+            Ok(ApprovalOutcome::Denied)
+        );
+
+        let cancel = CancellationToken::new();
+        let outcome = thread::scope(|scope| {
+            let run = scope.spawn(|| flow.run(&cancel, |_| {}));
+            while !flow.is_running() || backend.inner().settings_opened() == 0 {
+                thread::sleep(Duration::from_millis(1));
+            }
+            assert_eq!(
+                flow.run(&cancel, |_| {}),
+                Err(ApprovalError::AlreadyRunning)
+            );
+            cancel.cancel();
+            run.join().unwrap()
+        });
+        assert_eq!(outcome, Ok(ApprovalOutcome::Cancelled));
+    }
+}
@@ -0,0 +1,387 @@
+//! Team ID consistency checks across the executables in an app bundle.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::bundle::{bundle_executable, AppBundle, BundleError, ServiceDefinition};
+use crate::macho::MachOFile;
+use crate::{OwnedServiceType, ServiceType};
+
+/// The signing details of an executable that matter for service registration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SigningInfo {
+    /// The signing identifier.
+    pub identifier: String,
+
+    /// The Team ID of the signing certificate, `None` for ad-hoc signatures.
+    pub team_id: Option<String>,
+
+    /// Whether the signature is ad-hoc.
+    pub adhoc: bool,
+
+    /// Whether the hardened runtime is enabled.
+    pub hardened_runtime: bool,
+}
+
+/// How an audited executable is signed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Signing {
+    /// Every architecture carries the same signing identity.
+    Signed(SigningInfo),
+
+    /// The executable has no embedded signature.
+    Unsigned,
+
+    /// The architectures of a universal binary are signed with different identities.
+    Inconsistent,
+
+    /// The executable couldn't be read or parsed.
+    Unreadable(String),
+}
+
+impl Signing {
+    /// Returns the signing details if the executable is signed.
+    pub fn info(&self) -> Option<&SigningInfo> {
+        match self {
+            Signing::Signed(info) => Some(info),
+            Signing::Unsigned | Signing::Inconsistent | Signing::Unreadable(_) => None,
+        }
+    }
+}
+
+/// An executable checked by a [`TeamIdAudit`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditedComponent {
+    /// The service the executable belongs to, or `None` for the bundle's main executable.
+    pub service_type: Option<OwnedServiceType>,
+
+    /// The path of the executable.
+    pub path: PathBuf,
+
+    /// How the executable is signed.
+    pub signing: Signing,
+}
+
+/// A problem found by a [`TeamIdAudit`]. Each one would make registration fail with
+/// [`ServiceManagementError::InvalidSignature`](crate::ServiceManagementError::InvalidSignature).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuditFinding {
+    /// The executable's Team ID differs from the main executable's.
+    TeamIdMismatch {
+        /// The executable.
+        path: PathBuf,
+        /// The main executable's Team ID.
+        expected: Option<String>,
+        /// The executable's Team ID.
+        found: Option<String>,
+    },
+
+    /// The executable isn't signed.
+    Unsigned(PathBuf),
+
+    /// The executable couldn't be read or parsed.
+    Unreadable {
+        /// The executable.
+        path: PathBuf,
+        /// Why it couldn't be read.
+        reason: String,
+    },
+
+    /// The architectures of a universal binary are signed differently.
+    InconsistentArchitectures(PathBuf),
+}
+
+impl fmt::Display for AuditFinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let team = |team: &Option<String>| team.clone().unwrap_or_else(|| "none".to_string());
+        match self {
+            AuditFinding::TeamIdMismatch {
+                path,
+                expected,
+                found,
+            } => write!(
+                f,
+                "{}: Team ID {} does not match the main executable's {}",
+                path.display(),
+                team(found),
+                team(expected)
+            ),
+            AuditFinding::Unsigned(path) => write!(f, "{}: not signed", path.display()),
+            AuditFinding::Unreadable { path, reason } => {
+                write!(f, "{}: {reason}", path.display())
+            }
+            AuditFinding::InconsistentArchitectures(path) => write!(
+                f,
+                "{}: architectures are signed with different identities",
+                path.display()
+            ),
+        }
+    }
+}
+
+/// Checks that the main executable and every helper executable in a bundle are signed by the
+/// same team.
+///
+/// The executables checked are the ones the [`ServiceType`](crate::ServiceType) variants refer
+/// to: the main executable for `MainApp`, the executable of each helper in
+/// `Contents/Library/LoginItems` for `LoginItem`, and the `BundleProgram` of each property list
+/// in `Contents/Library/LaunchAgents` and `Contents/Library/LaunchDaemons` for `Agent` and
+/// `Daemon`.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use smappservice_rs::TeamIdAudit;
+///
+/// let audit = TeamIdAudit::for_bundle("/Applications/MyApp.app").unwrap();
+/// for finding in &audit.findings {
+///     eprintln!("{finding}");
+/// }
+/// assert!(audit.passed());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TeamIdAudit {
+    /// Every executable checked, starting with the main executable.
+    pub components: Vec<AuditedComponent>,
+
+    /// The problems found.
+    pub findings: Vec<AuditFinding>,
+}
+
+impl TeamIdAudit {
+    /// Opens the bundle at `path` and audits it.
+    pub fn for_bundle(path: impl AsRef<Path>) -> Result<Self, BundleError> {
+        Ok(Self::new(&AppBundle::open(path)?))
+    }
+
+    /// Audits an opened bundle.
+    pub fn new(bundle: &AppBundle) -> Self {
+        let mut components = vec![audit_component(None, bundle.executable())];
+        for service in bundle.services() {
+            let path = match service.definition() {
+                ServiceDefinition::Launchd(plist) => match &plist.bundle_program {
+                    Some(program) => bundle.path().join(program),
+                    None => continue,
+                },
+                ServiceDefinition::LoginItem(_) => bundle_executable(service.path()),
+                // A helper's executable can be found without its `Info.plist`; a broken launchd
+                // plist doesn't say which program it runs.
+                ServiceDefinition::Invalid(_) => match service.service_type() {
+                    ServiceType::LoginItem { .. } => bundle_executable(service.path()),
+                    _ => continue,
+                },
+            };
+            components.push(audit_component(Some(service.service_type().into()), path));
+        }
+
+        let expected = components[0]
+            .signing
+            .info()
+            .and_then(|info| info.team_id.clone());
+        let mut findings = Vec::new();
+        for (index, component) in components.iter().enumerate() {
+            let path = component.path.clone();
+            match &component.signing {
+                Signing::Signed(info) if index > 0 && info.team_id != expected => {
+                    findings.push(AuditFinding::TeamIdMismatch {
+                        path,
+                        expected: expected.clone(),
+                        found: info.team_id.clone(),
+                    });
+                }
+                Signing::Signed(_) => {}
+                Signing::Unsigned => findings.push(AuditFinding::Unsigned(path)),
+                Signing::Inconsistent => {
+                    findings.push(AuditFinding::InconsistentArchitectures(path));
+                }
+                Signing::Unreadable(reason) => findings.push(AuditFinding::Unreadable {
+                    path,
+                    reason: reason.clone(),
+                }),
+            }
+        }
+
+        Self {
+            components,
+            findings,
+        }
+    }
+
+    /// Returns whether no problems were found.
+    pub fn passed(&self) -> bool {
+        self.findings.is_empty()
+    }
+
+    /// Returns the main executable's Team ID.
+    pub fn team_id(&self) -> Option<&str> {
+        self.components.first()?.signing.info()?.team_id.as_deref()
+    }
+}
+
+fn audit_component(service_type: Option<OwnedServiceType>, path: PathBuf) -> AuditedComponent {
+    AuditedComponent {
+        signing: read_signing(&path),
+        service_type,
+        path,
+    }
+}
+
+fn read_signing(path: &Path) -> Signing {
+    let file = match MachOFile::from_file(path) {
+        Ok(file) => file,
+        Err(error) => return Signing::Unreadable(error.to_string()),
+    };
+    let mut signing = None;
+    for slice in file.slices() {
+        let slice_signing = match slice.code_signature() {
+            Ok(Some(signature)) => Signing::Signed(SigningInfo {
+                identifier: signature.identifier().to_string(),
+                team_id: signature.team_id().map(str::to_string),
+                adhoc: signature.is_adhoc(),
+                hardened_runtime: signature.has_hardened_runtime(),
+            }),
+            Ok(None) => Signing::Unsigned,
+            Err(error) => return Signing::Unreadable(error.to_string()),
+        };
+        match &signing {
+            None => signing = Some(slice_signing),
+            Some(first) if !same_identity(first, &slice_signing) => {
+                return Signing::Inconsistent;
+            }
+            Some(_) => {}
+        }
+    }
+    signing.unwrap_or(Signing::Unsigned)
+}
+
+fn same_identity(a: &Signing, b: &Signing) -> bool {
+    match (a.info(), b.info()) {
+        (Some(a), Some(b)) => a.identifier == b.identifier && a.team_id == b.team_id,
+        (None, None) => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bundle::fixture::FixtureBundle;
+    use crate::macho::fixture::{fat, FixtureMachO, FixtureSignature, CPU_TYPE_ARM64, CPU_TYPE_X86_64};
+    use crate::{InfoPlist, LaunchdPlist};
+
+    fn signed(identifier: &str, team_id: Option<&str>) -> Vec<u8> {
+        FixtureMachO {
+            signature: Some(FixtureSignature {
+                team_id: team_id.map(str::to_string),
+                ..FixtureSignature::new(identifier)
+            }),
+            ..FixtureMachO::new(CPU_TYPE_ARM64)
+        }
+        .build()
+    }
+
+    #[test]
+    fn test_audit_flags_mismatches() {
+        let bundle = FixtureBundle::new("Main");
+        bundle.write_file(
+            "Contents/MacOS/Main",
+            &signed("com.example.main", Some("TEAM000001")),
+        );
+        bundle.add_login_item(
+            "Helper",
+            &InfoPlist {
+                bundle_identifier: Some("com.example.helper".to_string()),
+                ..InfoPlist::default()
+            },
+        );
+        bundle.write_file(
+            "Contents/Library/LoginItems/Helper.app/Contents/MacOS/Helper",
+            &signed("com.example.helper", Some("TEAM000001")),
+        );
+        bundle.add_launchd_plist(
+            "LaunchAgents",
+            &LaunchdPlist {
+                bundle_program: Some("Contents/MacOS/agent".to_string()),
+                ..LaunchdPlist::new("com.example.agent")
+            },
+        );
+        bundle.write_file("Contents/MacOS/agent", &signed("agent", Some("TEAM000002")));
+        bundle.add_launchd_plist(
+            "LaunchDaemons",
+            &LaunchdPlist {
+                bundle_program: Some("Contents/MacOS/daemon".to_string()),
+                ..LaunchdPlist::new("com.example.daemon")
+            },
+        );
+        bundle.write_file(
+            "Contents/MacOS/daemon",
+            &FixtureMachO::new(CPU_TYPE_ARM64).build(),
+        );
+        // Broken definitions don't stop the audit; a helper is still found without its plist.
+        bundle.write_file(
+            "Contents/Library/LaunchAgents/com.example.broken.plist",
+            b"<plist>",
+        );
+        bundle.add_login_item("Unnamed", &InfoPlist::default());
+        bundle.write_file(
+            "Contents/Library/LoginItems/Unnamed.app/Contents/MacOS/Unnamed",
+            &signed("com.example.unnamed", Some("TEAM000001")),
+        );
+
+        let audit = TeamIdAudit::for_bundle(&bundle.path).unwrap();
+        assert_eq!(audit.team_id(), Some("TEAM000001"));
+        assert_eq!(audit.components.len(), 5);
+        assert_eq!(
+            audit.findings,
+            vec![
+                AuditFinding::TeamIdMismatch {
+                    path: bundle.path.join("Contents/MacOS/agent"),
+                    expected: Some("TEAM000001".to_string()),
+                    found: Some("TEAM000002".to_string()),
+                },
+                AuditFinding::Unsigned(bundle.path.join("Contents/MacOS/daemon")),
+            ]
+        );
+        assert!(!audit.passed());
+    }
+
+    #[test]
+    fn test_audit_universal_and_missing_executables() {
+        let bundle = FixtureBundle::new("Main");
+        let x86 = FixtureMachO {
+            signature: Some(FixtureSignature {
+                team_id: Some("TEAM000001".to_string()),
+                ..FixtureSignature::new("com.example.main")
+            }),
+            ..FixtureMachO::new(CPU_TYPE_X86_64)
+        }
+        .build();
+        bundle.write_file(
+            "Contents/MacOS/Main",
+            &fat(&[x86.clone(), signed("com.example.main", Some("TEAM000001"))]),
+        );
+        let audit = TeamIdAudit::for_bundle(&bundle.path).unwrap();
+        assert!(audit.passed());
+        assert!(!audit.components[0].signing.info().unwrap().adhoc);
+
+        bundle.write_file(
+            "Contents/MacOS/Main",
+            &fat(&[x86, signed("com.example.main", None)]),
+        );
+        bundle.add_launchd_plist(
+            "LaunchAgents",
+            &LaunchdPlist {
+                bundle_program: Some("Contents/MacOS/missing".to_string()),
+                ..LaunchdPlist::new("com.example.agent")
+            },
+        );
+        let audit = TeamIdAudit::for_bundle(&bundle.path).unwrap();
+        assert!(matches!(
+            audit.findings.as_slice(),
+            [
+                AuditFinding::InconsistentArchitectures(_),
+                AuditFinding::Unreadable { .. }
+            ]
+        ));
+    }
+}
@@ -0,0 +1,194 @@
+//! Backends that perform the ServiceManagement calls on behalf of an [`AppService`](crate::AppService).
+
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{ServiceManagementError, ServiceStatus, ServiceType};
+
+#[cfg(target_os = "macos")]
+use objc2::rc::Retained;
+#[cfg(target_os = "macos")]
+use objc2_foundation::{NSError, NSString};
+#[cfg(target_os = "macos")]
+use objc2_service_management::SMAppService;
+
+/// The operations `AppService` delegates to the underlying service manager.
+///
+/// The default backend talks to the ServiceManagement framework. Other implementations
+/// can be plugged in with [`AppService::with_backend`](crate::AppService::with_backend),
+/// which allows code built on top of `AppService` to run and be tested without macOS.
+///
+/// # Examples
+///
+/// ```rust
+/// use smappservice_rs::{AppService, ServiceBackend, ServiceManagementError, ServiceStatus, ServiceType};
+///
+/// struct AlwaysEnabled;
+///
+/// impl ServiceBackend for AlwaysEnabled {
+///     fn register(&self, _service_type: &ServiceType) -> Result<(), ServiceManagementError> {
+///         Ok(())
+///     }
+///
+///     fn unregister(&self, _service_type: &ServiceType) -> Result<(), ServiceManagementError> {
+///         Ok(())
+///     }
+///
+///     fn status(&self, _service_type: &ServiceType) -> ServiceStatus {
+///         ServiceStatus::Enabled
+///     }
+///
+///     fn open_system_settings_login_items(&self) {}
+/// }
+///
+/// let service = AppService::with_backend(ServiceType::MainApp, AlwaysEnabled);
+/// assert_eq!(service.status(), ServiceStatus::Enabled);
+/// ```
+pub trait ServiceBackend: Send + Sync {
+    /// Registers the service described by `service_type`.
+    fn register(&self, service_type: &ServiceType) -> Result<(), ServiceManagementError>;
+
+    /// Unregisters the service described by `service_type`.
+    fn unregister(&self, service_type: &ServiceType) -> Result<(), ServiceManagementError>;
+
+    /// Returns the current status of the service described by `service_type`.
+    fn status(&self, service_type: &ServiceType) -> ServiceStatus;
+
+    /// Opens the Login Items section in System Settings.
+    fn open_system_settings_login_items(&self);
+}
+
+/// The calls a [`ServiceBackend`] can receive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Operation {
+    /// [`ServiceBackend::register`].
+    Register,
+
+    /// [`ServiceBackend::unregister`].
+    Unregister,
+
+    /// [`ServiceBackend::status`].
+    Status,
+
+    /// [`ServiceBackend::open_system_settings_login_items`].
+    OpenSystemSettings,
+}
+
+impl std::fmt::Display for Operation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Operation::Register => write!(f, "register"),
+            Operation::Unregister => write!(f, "unregister"),
+            Operation::Status => write!(f, "status"),
+            Operation::OpenSystemSettings => write!(f, "open system settings"),
+        }
+    }
+}
+
+impl<T: ServiceBackend + ?Sized> ServiceBackend for Arc<T> {
+    fn register(&self, service_type: &ServiceType) -> Result<(), ServiceManagementError> {
+        (**self).register(service_type)
+    }
+
+    fn unregister(&self, service_type: &ServiceType) -> Result<(), ServiceManagementError> {
+        (**self).unregister(service_type)
+    }
+
+    fn status(&self, service_type: &ServiceType) -> ServiceStatus {
+        (**self).status(service_type)
+    }
+
+    fn open_system_settings_login_items(&self) {
+        (**self).open_system_settings_login_items()
+    }
+}
+
+/// The backend used by [`AppService::new`](crate::AppService::new) on the current platform.
+#[cfg(target_os = "macos")]
+pub type DefaultBackend = SMAppServiceBackend;
+
+/// The backend used by [`AppService::new`](crate::AppService::new) on the current platform.
+#[cfg(not(target_os = "macos"))]
+pub type DefaultBackend = UnsupportedBackend;
+
+/// Backend that calls into the ServiceManagement framework through `SMAppService`.
+#[cfg(target_os = "macos")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SMAppServiceBackend;
+
+#[cfg(target_os = "macos")]
+impl SMAppServiceBackend {
+    fn service(service_type: &ServiceType) -> Retained<SMAppService> {
+        match *service_type {
+            ServiceType::MainApp => unsafe { SMAppService::mainAppService() },
+            ServiceType::Agent { plist_name } => unsafe {
+                let input_arg = NSString::from_str(plist_name);
+                SMAppService::agentServiceWithPlistName(&input_arg)
+            },
+            ServiceType::Daemon { plist_name } => unsafe {
+                let input_arg = NSString::from_str(plist_name);
+                SMAppService::daemonServiceWithPlistName(&input_arg)
+            },
+            ServiceType::LoginItem { identifier } => unsafe {
+                let input_arg = NSString::from_str(identifier);
+                SMAppService::loginItemServiceWithIdentifier(&input_arg)
+            },
+        }
+    }
+
+    fn map_error(error: Retained<NSError>) -> ServiceManagementError {
+        let error_code = error.code() as u32;
+        ServiceManagementError::try_from(error_code)
+            .unwrap_or(ServiceManagementError::Unknown(error_code))
+    }
+}
+
+#[cfg(target_os = "macos")]
+impl ServiceBackend for SMAppServiceBackend {
+    fn register(&self, service_type: &ServiceType) -> Result<(), ServiceManagementError> {
+        let service = Self::service(service_type);
+        unsafe { service.registerAndReturnError() }.map_err(Self::map_error)
+    }
+
+    fn unregister(&self, service_type: &ServiceType) -> Result<(), ServiceManagementError> {
+        let service = Self::service(service_type);
+        unsafe { service.unregisterAndReturnError() }.map_err(Self::map_error)
+    }
+
+    fn status(&self, service_type: &ServiceType) -> ServiceStatus {
+        let status = unsafe { Self::service(service_type).status() };
+        match ServiceStatus::try_from(status.0) {
+            Ok(status) => status,
+            Err(_) => ServiceStatus::NotFound,
+        }
+    }
+
+    fn open_system_settings_login_items(&self) {
+        unsafe { SMAppService::openSystemSettingsLoginItems() }
+    }
+}
+
+/// Backend used on platforms without the ServiceManagement framework.
+///
+/// Every service reports [`ServiceStatus::NotFound`], registering and unregistering fail with
+/// [`ServiceManagementError::ServiceUnavailable`] and opening System Settings does nothing.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UnsupportedBackend;
+
+impl ServiceBackend for UnsupportedBackend {
+    fn register(&self, _service_type: &ServiceType) -> Result<(), ServiceManagementError> {
+        Err(ServiceManagementError::ServiceUnavailable)
+    }
+
+    fn unregister(&self, _service_type: &ServiceType) -> Result<(), ServiceManagementError> {
+        Err(ServiceManagementError::ServiceUnavailable)
+    }
+
+    fn status(&self, _service_type: &ServiceType) -> ServiceStatus {
+        ServiceStatus::NotFound
+    }
+
+    fn open_system_settings_login_items(&self) {}
+}
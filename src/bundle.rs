@@ -0,0 +1,490 @@
+//! Discovery of the services packaged inside an app bundle.
+
+use std::path::{Path, PathBuf};
+
+use thiserror::Error;
+
+use crate::plist::PlistError;
+use crate::{InfoPlist, LaunchdPlist, OwnedServiceType, ServiceType};
+
+/// Errors that can occur while reading an app bundle.
+#[derive(Debug, Error)]
+pub enum BundleError {
+    /// The path isn't a directory with a `Contents` subdirectory.
+    #[error("`{0}` is not an app bundle")]
+    NotABundle(PathBuf),
+
+    /// A directory inside the bundle couldn't be listed.
+    #[error("failed to read `{path}`: {source}")]
+    Io {
+        /// The directory that couldn't be read.
+        path: PathBuf,
+        /// The underlying error.
+        source: std::io::Error,
+    },
+
+    /// A property list inside the bundle couldn't be parsed.
+    #[error("failed to parse `{path}`: {source}")]
+    Plist {
+        /// The property list that couldn't be parsed.
+        path: PathBuf,
+        /// The underlying error.
+        source: PlistError,
+    },
+
+    /// A login item's `Info.plist` has no `CFBundleIdentifier`.
+    #[error("`{0}` has no CFBundleIdentifier")]
+    MissingBundleIdentifier(PathBuf),
+}
+
+/// The definition a bundled service was discovered from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ServiceDefinition {
+    /// The launchd property list of an agent or daemon.
+    Launchd(Box<LaunchdPlist>),
+
+    /// The `Info.plist` of a login item's helper app.
+    LoginItem(Box<InfoPlist>),
+
+    /// The definition couldn't be read, for the reason given. The bundle's other services are
+    /// still discovered.
+    Invalid(String),
+}
+
+/// A service found inside an [`AppBundle`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct BundledService {
+    service_type: OwnedServiceType,
+    path: PathBuf,
+    definition: ServiceDefinition,
+}
+
+impl BundledService {
+    /// Returns the service type to pass to [`AppService::new`](crate::AppService::new).
+    ///
+    /// A login item whose `Info.plist` couldn't be read is identified by the helper's name, since
+    /// its bundle identifier is unknown.
+    pub fn service_type(&self) -> ServiceType<'_> {
+        self.service_type.as_service_type()
+    }
+
+    /// Returns the path of the launchd property list or the helper app.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the parsed definition of the service.
+    pub fn definition(&self) -> &ServiceDefinition {
+        &self.definition
+    }
+
+    /// Returns the launchd property list of an agent or daemon.
+    pub fn launchd_plist(&self) -> Option<&LaunchdPlist> {
+        match &self.definition {
+            ServiceDefinition::Launchd(plist) => Some(plist.as_ref()),
+            ServiceDefinition::LoginItem(_) | ServiceDefinition::Invalid(_) => None,
+        }
+    }
+
+    /// Returns the helper `Info.plist` of a login item.
+    pub fn info_plist(&self) -> Option<&InfoPlist> {
+        match &self.definition {
+            ServiceDefinition::LoginItem(info) => Some(info.as_ref()),
+            ServiceDefinition::Launchd(_) | ServiceDefinition::Invalid(_) => None,
+        }
+    }
+
+    /// Returns why the service's definition couldn't be read, if it couldn't.
+    pub fn error(&self) -> Option<&str> {
+        match &self.definition {
+            ServiceDefinition::Invalid(reason) => Some(reason),
+            ServiceDefinition::Launchd(_) | ServiceDefinition::LoginItem(_) => None,
+        }
+    }
+}
+
+/// An app bundle on disk and the services it packages.
+///
+/// Services are discovered from the same locations the ServiceManagement framework looks in:
+/// property lists in `Contents/Library/LaunchAgents` and `Contents/Library/LaunchDaemons`, and
+/// helper apps in `Contents/Library/LoginItems`. Missing directories simply contribute no
+/// services, and a service whose definition can't be read is kept with a
+/// [`ServiceDefinition::Invalid`] definition.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use smappservice_rs::{AppBundle, AppService};
+///
+/// let bundle = AppBundle::open("/Applications/MyApp.app").unwrap();
+/// for service in bundle.services() {
+///     let status = AppService::new(service.service_type()).status();
+///     println!("{}: {status}", service.path().display());
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct AppBundle {
+    path: PathBuf,
+    info: Option<InfoPlist>,
+    services: Vec<BundledService>,
+}
+
+impl AppBundle {
+    /// Reads the bundle at `path` and parses the definition of every service in it.
+    ///
+    /// Agents come first, then daemons, then login items, each sorted by file name.
+    ///
+    /// Fails if `path` isn't a bundle or its own `Info.plist` or service directories can't be
+    /// read. Problems with individual services are recorded on the service instead.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, BundleError> {
+        let path = path.as_ref().to_path_buf();
+        let contents = path.join("Contents");
+        if !contents.is_dir() {
+            return Err(BundleError::NotABundle(path));
+        }
+        let info_path = contents.join("Info.plist");
+        let info = if info_path.is_file() {
+            Some(
+                InfoPlist::from_file(&info_path).map_err(|source| BundleError::Plist {
+                    path: info_path,
+                    source,
+                })?,
+            )
+        } else {
+            None
+        };
+        let library = contents.join("Library");
+
+        let mut services = Vec::new();
+        for (directory, is_daemon) in [("LaunchAgents", false), ("LaunchDaemons", true)] {
+            for plist_path in list_dir(&library.join(directory), "plist")? {
+                let definition = match LaunchdPlist::from_file(&plist_path) {
+                    Ok(plist) => ServiceDefinition::Launchd(Box::new(plist)),
+                    Err(source) => ServiceDefinition::Invalid(
+                        BundleError::Plist {
+                            path: plist_path.clone(),
+                            source,
+                        }
+                        .to_string(),
+                    ),
+                };
+                let plist_name = file_name(&plist_path);
+                let service_type = if is_daemon {
+                    OwnedServiceType::Daemon { plist_name }
+                } else {
+                    OwnedServiceType::Agent { plist_name }
+                };
+                services.push(BundledService {
+                    service_type,
+                    path: plist_path,
+                    definition,
+                });
+            }
+        }
+        for helper_path in list_dir(&library.join("LoginItems"), "app")? {
+            let (identifier, definition) = match read_login_item(&helper_path) {
+                Ok(info) => (
+                    info.bundle_identifier.clone().unwrap_or_default(),
+                    ServiceDefinition::LoginItem(Box::new(info)),
+                ),
+                Err(error) => (
+                    file_stem(&helper_path),
+                    ServiceDefinition::Invalid(error.to_string()),
+                ),
+            };
+            services.push(BundledService {
+                service_type: OwnedServiceType::LoginItem { identifier },
+                path: helper_path,
+                definition,
+            });
+        }
+
+        Ok(Self {
+            path,
+            info,
+            services,
+        })
+    }
+
+    /// Returns the path of the bundle.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the bundle's own `Contents/Info.plist`, if it has one.
+    pub fn info_plist(&self) -> Option<&InfoPlist> {
+        self.info.as_ref()
+    }
+
+    /// Returns the path of the bundle's main executable in `Contents/MacOS`.
+    pub fn executable(&self) -> PathBuf {
+        executable_in(
+            &self.path,
+            self.info.as_ref().and_then(|info| info.executable.clone()),
+        )
+    }
+
+    /// Returns every service found in the bundle.
+    pub fn services(&self) -> &[BundledService] {
+        &self.services
+    }
+
+    /// Returns the service types of every service found in the bundle.
+    pub fn service_types(&self) -> Vec<ServiceType<'_>> {
+        self.services
+            .iter()
+            .map(BundledService::service_type)
+            .collect()
+    }
+
+    /// Returns the bundled service matching `service_type`, if the bundle contains it.
+    pub fn service(&self, service_type: &ServiceType) -> Option<&BundledService> {
+        self.services
+            .iter()
+            .find(|service| service.service_type() == *service_type)
+    }
+}
+
+/// Reads a login item helper's `Info.plist`, which has to name the helper's bundle identifier.
+fn read_login_item(helper: &Path) -> Result<InfoPlist, BundleError> {
+    let info = InfoPlist::for_bundle(helper).map_err(|source| BundleError::Plist {
+        path: helper.join("Contents").join("Info.plist"),
+        source,
+    })?;
+    if info.bundle_identifier.is_none() {
+        return Err(BundleError::MissingBundleIdentifier(helper.to_path_buf()));
+    }
+    Ok(info)
+}
+
+/// Returns the path of the bundle's main executable, named by `CFBundleExecutable` or, failing
+/// that, after the bundle itself.
+pub(crate) fn bundle_executable(bundle: &Path) -> PathBuf {
+    let executable = InfoPlist::for_bundle(bundle)
+        .ok()
+        .and_then(|info| info.executable);
+    executable_in(bundle, executable)
+}
+
+fn executable_in(bundle: &Path, name: Option<String>) -> PathBuf {
+    let name = name.unwrap_or_else(|| file_stem(bundle));
+    bundle.join("Contents").join("MacOS").join(name)
+}
+
+/// Lists the entries of `dir` with the given extension, sorted by name. A missing directory is
+/// treated as empty.
+fn list_dir(dir: &Path, extension: &str) -> Result<Vec<PathBuf>, BundleError> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(source) => {
+            return Err(BundleError::Io {
+                path: dir.to_path_buf(),
+                source,
+            });
+        }
+    };
+    let mut paths = Vec::new();
+    for entry in entries {
+        let entry = entry.map_err(|source| BundleError::Io {
+            path: dir.to_path_buf(),
+            source,
+        })?;
+        let path = entry.path();
+        if path.extension().is_some_and(|ext| ext == extension) {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+fn file_stem(path: &Path) -> String {
+    path.file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Builders for throwaway app bundles used by tests across the crate.
+#[cfg(test)]
+pub(crate) mod fixture {
+    use std::path::{Path, PathBuf};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use crate::{InfoPlist, LaunchdPlist};
+
+    /// A bundle under the system temporary directory, removed on drop.
+    pub(crate) struct FixtureBundle {
+        pub(crate) path: PathBuf,
+    }
+
+    impl FixtureBundle {
+        pub(crate) fn new(name: &str) -> Self {
+            static COUNTER: AtomicUsize = AtomicUsize::new(0);
+            let root = std::env::temp_dir().join(format!(
+                "smappservice-rs-{}-{}",
+                std::process::id(),
+                COUNTER.fetch_add(1, Ordering::Relaxed)
+            ));
+            let path = root.join(format!("{name}.app"));
+            std::fs::create_dir_all(path.join("Contents/MacOS")).unwrap();
+            Self { path }
+        }
+
+        pub(crate) fn contents(&self) -> PathBuf {
+            self.path.join("Contents")
+        }
+
+        pub(crate) fn add_launchd_plist(&self, directory: &str, plist: &LaunchdPlist) -> PathBuf {
+            let dir = self.contents().join("Library").join(directory);
+            std::fs::create_dir_all(&dir).unwrap();
+            let path = dir.join(format!("{}.plist", plist.label));
+            plist.write_to_file(&path).unwrap();
+            path
+        }
+
+        pub(crate) fn add_login_item(&self, name: &str, info: &InfoPlist) -> PathBuf {
+            let helper = self
+                .contents()
+                .join("Library/LoginItems")
+                .join(format!("{name}.app"));
+            std::fs::create_dir_all(helper.join("Contents/MacOS")).unwrap();
+            info.write_to_file(helper.join("Contents/Info.plist"))
+                .unwrap();
+            helper
+        }
+
+        pub(crate) fn write_file(&self, relative: impl AsRef<Path>, contents: &[u8]) -> PathBuf {
+            let path = self.path.join(relative);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, contents).unwrap();
+            path
+        }
+    }
+
+    impl Drop for FixtureBundle {
+        fn drop(&mut self) {
+            if let Some(root) = self.path.parent() {
+                let _ = std::fs::remove_dir_all(root);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::fixture::FixtureBundle;
+    use super::*;
+
+    #[test]
+    fn test_open_discovers_services() {
+        let bundle = FixtureBundle::new("Test");
+        let agent = LaunchdPlist {
+            bundle_program: Some("Contents/MacOS/agent".to_string()),
+            ..LaunchdPlist::new("com.example.agent")
+        };
+        bundle.add_launchd_plist("LaunchAgents", &agent);
+        bundle.add_launchd_plist(
+            "LaunchDaemons",
+            &LaunchdPlist {
+                bundle_program: Some("Contents/MacOS/daemon".to_string()),
+                ..LaunchdPlist::new("com.example.daemon")
+            },
+        );
+        let info = InfoPlist {
+            bundle_identifier: Some("com.example.helper".to_string()),
+            ..InfoPlist::default()
+        };
+        bundle.add_login_item("Helper", &info);
+        bundle.write_file("Contents/Library/LaunchAgents/README.txt", b"ignored");
+
+        let app = AppBundle::open(&bundle.path).unwrap();
+        assert_eq!(
+            app.service_types(),
+            vec![
+                ServiceType::Agent {
+                    plist_name: "com.example.agent.plist"
+                },
+                ServiceType::Daemon {
+                    plist_name: "com.example.daemon.plist"
+                },
+                ServiceType::LoginItem {
+                    identifier: "com.example.helper"
+                },
+            ]
+        );
+
+        let service = app
+            .service(&ServiceType::Agent {
+                plist_name: "com.example.agent.plist",
+            })
+            .unwrap();
+        assert_eq!(service.launchd_plist(), Some(&agent));
+        let helper = app.services().last().unwrap();
+        assert!(
+            helper
+                .path()
+                .ends_with("Contents/Library/LoginItems/Helper.app")
+        );
+        assert_eq!(helper.info_plist(), Some(&info));
+        assert_eq!(app.info_plist(), None);
+    }
+
+    #[test]
+    fn test_open_errors() {
+        let bundle = FixtureBundle::new("Empty");
+        assert!(AppBundle::open(&bundle.path).unwrap().services().is_empty());
+        assert!(matches!(
+            AppBundle::open(bundle.path.join("Contents/MacOS")),
+            Err(BundleError::NotABundle(_))
+        ));
+    }
+
+    #[test]
+    fn test_open_invalid_services() {
+        let bundle = FixtureBundle::new("Test");
+        bundle.write_file(
+            "Contents/Library/LaunchAgents/com.example.broken.plist",
+            b"<plist>",
+        );
+        bundle.add_launchd_plist("LaunchAgents", &LaunchdPlist::new("com.example.valid"));
+        bundle.add_login_item("Helper", &InfoPlist::default());
+
+        let app = AppBundle::open(&bundle.path).unwrap();
+        assert_eq!(
+            app.service_types(),
+            vec![
+                ServiceType::Agent {
+                    plist_name: "com.example.broken.plist"
+                },
+                ServiceType::Agent {
+                    plist_name: "com.example.valid.plist"
+                },
+                ServiceType::LoginItem {
+                    identifier: "Helper"
+                },
+            ]
+        );
+        let services = app.services();
+        assert!(
+            services[0]
+                .error()
+                .unwrap()
+                .contains("com.example.broken.plist")
+        );
+        assert_eq!(services[1].error(), None);
+        assert!(
+            services[2]
+                .error()
+                .unwrap()
+                .contains("no CFBundleIdentifier")
+        );
+        assert_eq!(services[2].info_plist(), None);
+    }
+}
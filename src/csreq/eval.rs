@@ -0,0 +1,452 @@
+//! Offline evaluation of requirements against signing details.
+
+use std::cmp::Ordering;
+use std::path::Path;
+
+use crate::bundle::bundle_executable;
+use crate::macho::{Certificate, CodeSignature, MachOError, MachOFile};
+use crate::plist::{Dictionary, Value};
+
+use super::{CertificatePosition, Match, Requirement};
+
+/// The SHA-1 hash of the Apple Root CA certificate, the anchor of every Apple-issued chain.
+const APPLE_ROOT_CA_SHA1: [u8; 20] = [
+    0x61, 0x1e, 0x5b, 0x66, 0x2c, 0x59, 0x3a, 0x08, 0xff, 0x58, 0xd1, 0x4a, 0xe2, 0x24, 0x52, 0xd1,
+    0x98, 0xdf, 0x6c, 0x60,
+];
+
+/// The result of evaluating a requirement offline.
+///
+/// Some clauses depend on state only the system has, such as notarization tickets and the local
+/// trust settings. Those evaluate to `Unknown`, which propagates through `and`, `or` and `!` the
+/// way SQL's `NULL` does: `Unknown and Unsatisfied` is `Unsatisfied`, `Unknown or Satisfied` is
+/// `Satisfied`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Evaluation {
+    /// The requirement holds.
+    Satisfied,
+
+    /// The requirement doesn't hold.
+    Unsatisfied,
+
+    /// The requirement can't be decided from the information available.
+    Unknown,
+}
+
+impl Evaluation {
+    fn and(self, other: Self) -> Self {
+        match (self, other) {
+            (Evaluation::Unsatisfied, _) | (_, Evaluation::Unsatisfied) => Evaluation::Unsatisfied,
+            (Evaluation::Satisfied, Evaluation::Satisfied) => Evaluation::Satisfied,
+            _ => Evaluation::Unknown,
+        }
+    }
+
+    fn or(self, other: Self) -> Self {
+        self.not().and(other.not()).not()
+    }
+
+    fn not(self) -> Self {
+        match self {
+            Evaluation::Satisfied => Evaluation::Unsatisfied,
+            Evaluation::Unsatisfied => Evaluation::Satisfied,
+            Evaluation::Unknown => Evaluation::Unknown,
+        }
+    }
+}
+
+impl From<bool> for Evaluation {
+    fn from(value: bool) -> Self {
+        if value {
+            Evaluation::Satisfied
+        } else {
+            Evaluation::Unsatisfied
+        }
+    }
+}
+
+/// The signing details a requirement is evaluated against.
+///
+/// Fields left as `None` or empty are treated as unknown, so clauses that need them evaluate to
+/// [`Evaluation::Unknown`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SigningContext {
+    /// The signing identifier.
+    pub identifier: Option<String>,
+
+    /// The Team ID recorded in the code directory. Used for `certificate leaf[subject.OU]` when
+    /// the certificate chain isn't available.
+    pub team_id: Option<String>,
+
+    /// The CDHash of every code directory.
+    pub cdhashes: Vec<Vec<u8>>,
+
+    /// The platform identifier, non-zero only for Apple platform binaries.
+    pub platform: u8,
+
+    /// Whether the signature is ad-hoc, which means there is no certificate chain.
+    pub adhoc: bool,
+
+    /// The certificate chain, from the leaf to the root.
+    pub certificates: Vec<Certificate>,
+
+    /// The code's `Info.plist`.
+    pub info: Option<Dictionary>,
+
+    /// The code's entitlements.
+    pub entitlements: Option<Dictionary>,
+}
+
+impl SigningContext {
+    /// Collects the signing details from a parsed signature.
+    pub fn from_signature(signature: &CodeSignature) -> Result<Self, MachOError> {
+        let code_directory = signature.code_directory();
+        Ok(Self {
+            identifier: Some(code_directory.identifier.clone()),
+            team_id: code_directory.team_id.clone(),
+            cdhashes: signature
+                .code_directories
+                .iter()
+                .filter_map(|cd| cd.cdhash.clone())
+                .collect(),
+            platform: code_directory.platform,
+            adhoc: signature.is_adhoc(),
+            certificates: signature.certificates()?,
+            info: None,
+            entitlements: Some(signature.entitlements.clone().unwrap_or_default()),
+        })
+    }
+
+    /// Reads the signing details of the executable at `path`. For universal binaries the first
+    /// architecture is used.
+    pub fn for_executable(path: impl AsRef<Path>) -> Result<Self, MachOError> {
+        let file = MachOFile::from_file(path)?;
+        let slice = file
+            .slices()
+            .first()
+            .ok_or_else(|| MachOError::Malformed("no architectures".to_string()))?;
+        let signature = slice.code_signature()?.ok_or(MachOError::Unsigned)?;
+        Self::from_signature(&signature)
+    }
+
+    /// Reads the signing details of the bundle at `bundle`: those of its main executable, plus
+    /// its `Info.plist` when it can be read.
+    pub fn for_bundle(bundle: impl AsRef<Path>) -> Result<Self, MachOError> {
+        let bundle = bundle.as_ref();
+        let mut context = Self::for_executable(bundle_executable(bundle))?;
+        context.info = match Value::from_file(bundle.join("Contents").join("Info.plist")) {
+            Ok(Value::Dictionary(info)) => Some(info),
+            _ => None,
+        };
+        Ok(context)
+    }
+
+    /// Returns whether the certificate chain is known, either because it was read or because the
+    /// signature is ad-hoc and has none.
+    fn chain_known(&self) -> bool {
+        self.adhoc || !self.certificates.is_empty()
+    }
+
+    fn certificate(&self, position: CertificatePosition) -> Option<&Certificate> {
+        let count = self.certificates.len();
+        let index = match position {
+            CertificatePosition::Leaf => 0,
+            CertificatePosition::Root => count.checked_sub(1)?,
+            CertificatePosition::Index(index) if index >= 0 => index as usize,
+            CertificatePosition::Index(index) => count.checked_sub(index.unsigned_abs() as usize)?,
+        };
+        self.certificates.get(index)
+    }
+}
+
+pub(super) fn evaluate(requirement: &Requirement, context: &SigningContext) -> Evaluation {
+    let known = |value: Option<bool>| value.map_or(Evaluation::Unknown, Evaluation::from);
+    // Clauses that need state only the system has can still be ruled out for ad-hoc code.
+    let system_only = || {
+        if context.adhoc {
+            Evaluation::Unsatisfied
+        } else {
+            Evaluation::Unknown
+        }
+    };
+    let chain = |check: &dyn Fn() -> bool| known(context.chain_known().then(check));
+
+    match requirement {
+        Requirement::Always => Evaluation::Satisfied,
+        Requirement::Never => Evaluation::Unsatisfied,
+        Requirement::Not(inner) => evaluate(inner, context).not(),
+        Requirement::And(left, right) => evaluate(left, context).and(evaluate(right, context)),
+        Requirement::Or(left, right) => evaluate(left, context).or(evaluate(right, context)),
+        Requirement::Identifier(identifier) => {
+            known(context.identifier.as_ref().map(|found| found == identifier))
+        }
+        Requirement::CdHash(hash) => known(
+            (!context.cdhashes.is_empty()).then(|| context.cdhashes.iter().any(|cd| cd == hash)),
+        ),
+        Requirement::Platform(platform) => (i64::from(context.platform) == *platform).into(),
+        Requirement::Notarized | Requirement::Legacy | Requirement::CertificateTrusted(_) => {
+            system_only()
+        }
+        Requirement::AnchorAppleGeneric => chain(&|| is_apple_root(context)),
+        Requirement::AnchorApple => {
+            if context.platform != 0 {
+                return Evaluation::Satisfied;
+            }
+            chain(&|| {
+                let leaf = context.certificate(CertificatePosition::Leaf);
+                is_apple_root(context)
+                    && leaf.and_then(|leaf| leaf.subject_attribute("O")) == Some("Apple Inc.")
+                    && leaf.and_then(|leaf| leaf.subject_attribute("CN"))
+                        == Some("Software Signing")
+            })
+        }
+        Requirement::CertificateHash(position, hash) => chain(&|| {
+            context
+                .certificate(*position)
+                .is_some_and(|certificate| certificate.sha1().as_slice() == hash.as_slice())
+        }),
+        Requirement::CertificateField(position, field, matcher) => {
+            if let Some(attribute) = field.strip_prefix("subject.") {
+                if context.chain_known() {
+                    let value = context
+                        .certificate(*position)
+                        .and_then(|certificate| certificate.subject_attribute(attribute));
+                    return apply(matcher, value).into();
+                }
+                // The Team ID in the code directory is copied from the leaf's organizational unit.
+                if *position == CertificatePosition::Leaf
+                    && attribute == "OU"
+                    && let Some(team_id) = &context.team_id
+                {
+                    return apply(matcher, Some(team_id)).into();
+                }
+                return Evaluation::Unknown;
+            }
+            match (field.strip_prefix("field."), matcher) {
+                (Some(oid), Match::Exists | Match::Absent) => chain(&|| {
+                    let present = context
+                        .certificate(*position)
+                        .is_some_and(|certificate| certificate.has_extension(oid));
+                    present == (*matcher == Match::Exists)
+                }),
+                _ => Evaluation::Unknown,
+            }
+        }
+        Requirement::Info(key, matcher) => known(
+            context
+                .info
+                .as_ref()
+                .map(|info| apply_value(matcher, info.get(key))),
+        ),
+        Requirement::Entitlement(key, matcher) => known(
+            context
+                .entitlements
+                .as_ref()
+                .map(|entitlements| apply_value(matcher, entitlements.get(key))),
+        ),
+    }
+}
+
+fn is_apple_root(context: &SigningContext) -> bool {
+    context
+        .certificate(CertificatePosition::Root)
+        .is_some_and(|root| root.sha1() == APPLE_ROOT_CA_SHA1)
+}
+
+fn apply_value(matcher: &Match, value: Option<&Value>) -> bool {
+    let Some(value) = value else {
+        return apply(matcher, None);
+    };
+    let scalar = match value {
+        Value::String(value) => value.clone(),
+        Value::Integer(value) => value.to_string(),
+        Value::Real(value) => value.to_string(),
+        Value::Boolean(value) => value.to_string(),
+        // Arrays, dictionaries, dates and data can only be tested for presence.
+        _ => return *matcher == Match::Exists,
+    };
+    apply(matcher, Some(&scalar))
+}
+
+fn apply(matcher: &Match, value: Option<&str>) -> bool {
+    let Some(value) = value else {
+        return *matcher == Match::Absent;
+    };
+    match matcher {
+        Match::Exists => true,
+        Match::Absent => false,
+        Match::Equal(expected) => value == expected,
+        Match::Contains(expected) => value.contains(expected.as_str()),
+        Match::Prefix(expected) => value.starts_with(expected.as_str()),
+        Match::Suffix(expected) => value.ends_with(expected.as_str()),
+        Match::Less(expected) => compare(value, expected) == Ordering::Less,
+        Match::Greater(expected) => compare(value, expected) == Ordering::Greater,
+        Match::LessOrEqual(expected) => compare(value, expected) != Ordering::Greater,
+        Match::GreaterOrEqual(expected) => compare(value, expected) != Ordering::Less,
+    }
+}
+
+/// Compares two values the way version strings are compared: runs of digits numerically, so
+/// `"1.10"` sorts after `"1.9"`, and everything else character by character.
+fn compare(a: &str, b: &str) -> Ordering {
+    #[derive(PartialEq, Eq, PartialOrd, Ord)]
+    enum Part<'a> {
+        Number(u128),
+        Text(&'a str),
+    }
+    fn parts(value: &str) -> Vec<Part<'_>> {
+        let mut parts = Vec::new();
+        let mut rest = value;
+        while let Some(first) = rest.chars().next() {
+            let digit = first.is_ascii_digit();
+            let end = rest
+                .find(|c: char| c.is_ascii_digit() != digit)
+                .unwrap_or(rest.len());
+            let (part, tail) = rest.split_at(end);
+            parts.push(if digit {
+                Part::Number(part.parse().unwrap_or(u128::MAX))
+            } else {
+                Part::Text(part)
+            });
+            rest = tail;
+        }
+        parts
+    }
+    parts(a).cmp(&parts(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bundle::fixture::FixtureBundle;
+    use crate::macho::certificate::fixture::certificate;
+    use crate::macho::fixture::{FixtureMachO, FixtureSignature, CPU_TYPE_ARM64};
+    use crate::InfoPlist;
+
+    fn requirement(source: &str) -> Requirement {
+        Requirement::parse(source).unwrap()
+    }
+
+    #[test]
+    fn test_three_valued_logic() {
+        let adhoc = SigningContext {
+            identifier: Some("com.example.helper".to_string()),
+            adhoc: true,
+            ..SigningContext::default()
+        };
+        let unknown = SigningContext::default();
+
+        let notarized = requirement("identifier \"com.example.helper\" and notarized");
+        assert_eq!(notarized.evaluate(&adhoc), Evaluation::Unsatisfied);
+        assert_eq!(notarized.evaluate(&unknown), Evaluation::Unknown);
+        assert_eq!(
+            requirement("notarized or identifier \"com.example.helper\"").evaluate(&adhoc),
+            Evaluation::Satisfied
+        );
+        assert_eq!(
+            requirement("!notarized and never").evaluate(&unknown),
+            Evaluation::Unsatisfied
+        );
+        assert_eq!(
+            requirement("anchor apple generic").evaluate(&adhoc),
+            Evaluation::Unsatisfied
+        );
+        assert_eq!(
+            requirement("anchor apple").evaluate(&SigningContext {
+                platform: 1,
+                ..SigningContext::default()
+            }),
+            Evaluation::Satisfied
+        );
+    }
+
+    #[test]
+    fn test_matches() {
+        assert!(apply(
+            &Match::GreaterOrEqual("1.9".to_string()),
+            Some("1.10")
+        ));
+        assert!(apply(&Match::Less("2.0b10".to_string()), Some("2.0b9")));
+        assert!(apply(
+            &Match::Contains("exam".to_string()),
+            Some("com.example")
+        ));
+        assert!(apply(&Match::Absent, None));
+        assert!(!apply(&Match::Equal("x".to_string()), None));
+        assert!(apply_value(
+            &Match::Equal("true".to_string()),
+            Some(&Value::Boolean(true))
+        ));
+        assert!(apply_value(&Match::Exists, Some(&Value::Array(Vec::new()))));
+    }
+
+    #[test]
+    fn test_evaluate_signed_bundle() {
+        let root_name = [("2.5.4.3", "Example Root")];
+        let leaf_name = [("2.5.4.3", "Example Leaf"), ("2.5.4.11", "TEAM000001")];
+        let root = certificate(&root_name, &root_name, &[]);
+        let leaf = certificate(&leaf_name, &root_name, &["1.2.840.113635.100.6.1.13"]);
+
+        let bundle = FixtureBundle::new("Main");
+        InfoPlist {
+            bundle_identifier: Some("com.example.main".to_string()),
+            bundle_version: Some("42".to_string()),
+            executable: Some("Main".to_string()),
+            ..InfoPlist::default()
+        }
+        .write_to_file(bundle.contents().join("Info.plist"))
+        .unwrap();
+        bundle.write_file(
+            "Contents/MacOS/Main",
+            &FixtureMachO {
+                signature: Some(FixtureSignature {
+                    team_id: Some("TEAM000001".to_string()),
+                    certificates: vec![root.clone(), leaf],
+                    entitlements: Some(
+                        "<plist><dict><key>com.apple.security.app-sandbox</key><true/></dict></plist>"
+                            .to_string(),
+                    ),
+                    ..FixtureSignature::new("com.example.main")
+                }),
+                ..FixtureMachO::new(CPU_TYPE_ARM64)
+            }
+            .build(),
+        );
+
+        let context = SigningContext::for_bundle(&bundle.path).unwrap();
+        assert_eq!(context.certificates.len(), 2);
+        let satisfied = format!(
+            "identifier \"com.example.main\" and certificate leaf[subject.OU] = \"TEAM000001\" \
+             and certificate leaf[field.1.2.840.113635.100.6.1.13] and info[CFBundleVersion] >= 9 \
+             and entitlement[\"com.apple.security.app-sandbox\"] = true \
+             and certificate root = H\"{}\" and cdhash H\"{}\"",
+            crate::digest::hex(&crate::digest::sha1(&root)),
+            crate::digest::hex(&context.cdhashes[0]),
+        );
+        assert_eq!(
+            requirement(&satisfied).evaluate(&context),
+            Evaluation::Satisfied
+        );
+        assert_eq!(
+            requirement("anchor apple generic").evaluate(&context),
+            Evaluation::Unsatisfied
+        );
+        assert_eq!(
+            requirement("certificate 1[subject.CN] = \"Example Root\"").evaluate(&context),
+            Evaluation::Satisfied
+        );
+        assert_eq!(
+            requirement("certificate -1[subject.CN] = \"Example Root\"").evaluate(&context),
+            Evaluation::Satisfied
+        );
+
+        bundle.write_file(
+            "Contents/MacOS/Main",
+            &FixtureMachO::new(CPU_TYPE_ARM64).build(),
+        );
+        assert!(matches!(
+            SigningContext::for_bundle(&bundle.path),
+            Err(MachOError::Unsigned)
+        ));
+    }
+}
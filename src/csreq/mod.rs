@@ -0,0 +1,315 @@
+//! The code requirement language used by `SMAuthorizedClients`, `SMPrivilegedExecutables` and
+//! `codesign -r`.
+//!
+//! [`Requirement::parse`] turns requirement text into an AST, and [`Requirement::evaluate`]
+//! checks it against the signing details of a binary, read offline by [`SigningContext`]. This
+//! makes it possible to verify on any platform that an app and its helpers name each other
+//! correctly.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use smappservice_rs::csreq::{Evaluation, Requirement, SigningContext};
+//!
+//! let requirement = Requirement::parse(
+//!     r#"identifier "com.example.helper" and certificate leaf[subject.OU] = "TEAM000001""#,
+//! )
+//! .unwrap();
+//!
+//! let context = SigningContext {
+//!     identifier: Some("com.example.helper".to_string()),
+//!     team_id: Some("TEAM000001".to_string()),
+//!     ..SigningContext::default()
+//! };
+//! assert_eq!(requirement.evaluate(&context), Evaluation::Satisfied);
+//! ```
+
+mod eval;
+mod parser;
+
+use std::fmt;
+use std::str::FromStr;
+
+use thiserror::Error;
+
+use crate::digest::hex;
+
+pub use eval::{Evaluation, SigningContext};
+
+/// A syntax error in requirement text.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("invalid requirement at offset {offset}: {message}")]
+pub struct RequirementError {
+    /// The byte offset in the source text where the error was detected.
+    pub offset: usize,
+
+    /// What was wrong.
+    pub message: String,
+}
+
+/// A certificate in the signing chain, as selected by `certificate <position>`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CertificatePosition {
+    /// The signing certificate, `leaf`.
+    Leaf,
+
+    /// The anchor certificate, `root` or `anchor`.
+    Root,
+
+    /// A certificate by index: 0 is the leaf and counts upwards, -1 is the root and counts
+    /// downwards.
+    Index(i32),
+}
+
+impl fmt::Display for CertificatePosition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CertificatePosition::Leaf => f.write_str("leaf"),
+            CertificatePosition::Root => f.write_str("root"),
+            CertificatePosition::Index(index) => write!(f, "{index}"),
+        }
+    }
+}
+
+/// A comparison applied to an `info`, `entitlement` or certificate field value.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Match {
+    /// The value is present.
+    Exists,
+    /// The value is not present.
+    Absent,
+    /// `= "value"`
+    Equal(String),
+    /// `= *"value"*`
+    Contains(String),
+    /// `= "value"*`
+    Prefix(String),
+    /// `= *"value"`
+    Suffix(String),
+    /// `< "value"`
+    Less(String),
+    /// `> "value"`
+    Greater(String),
+    /// `<= "value"`
+    LessOrEqual(String),
+    /// `>= "value"`
+    GreaterOrEqual(String),
+}
+
+impl fmt::Display for Match {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Match::Exists => f.write_str("exists"),
+            Match::Absent => f.write_str("absent"),
+            Match::Equal(value) => write!(f, "= {}", quote(value)),
+            Match::Contains(value) => write!(f, "= *{}*", quote(value)),
+            Match::Prefix(value) => write!(f, "= {}*", quote(value)),
+            Match::Suffix(value) => write!(f, "= *{}", quote(value)),
+            Match::Less(value) => write!(f, "< {}", quote(value)),
+            Match::Greater(value) => write!(f, "> {}", quote(value)),
+            Match::LessOrEqual(value) => write!(f, "<= {}", quote(value)),
+            Match::GreaterOrEqual(value) => write!(f, ">= {}", quote(value)),
+        }
+    }
+}
+
+/// A parsed code requirement.
+///
+/// `Display` prints the requirement back in canonical form, which parses to the same value.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Requirement {
+    /// `always` or `true`.
+    Always,
+    /// `never` or `false`.
+    Never,
+    /// `! requirement`
+    Not(Box<Requirement>),
+    /// `requirement and requirement`
+    And(Box<Requirement>, Box<Requirement>),
+    /// `requirement or requirement`
+    Or(Box<Requirement>, Box<Requirement>),
+    /// `identifier "com.example.app"`: the signing identifier.
+    Identifier(String),
+    /// `cdhash H"..."`: the CDHash of the code.
+    CdHash(Vec<u8>),
+    /// `platform = N`: the platform identifier of Apple platform binaries.
+    Platform(i64),
+    /// `notarized`: the code was notarized by Apple.
+    Notarized,
+    /// `legacy`: the code predates notarization requirements.
+    Legacy,
+    /// `anchor apple`: code signed by Apple itself.
+    AnchorApple,
+    /// `anchor apple generic`: code signed with any certificate issued by Apple.
+    AnchorAppleGeneric,
+    /// `certificate <position> = H"..."`: the SHA-1 hash of a certificate in the chain.
+    CertificateHash(CertificatePosition, Vec<u8>),
+    /// `certificate <position> trusted`: a certificate trusted in the local trust settings.
+    CertificateTrusted(CertificatePosition),
+    /// `certificate <position>[field] match`: a subject attribute such as `subject.OU`, or an
+    /// extension such as `field.1.2.840.113635.100.6.2.6`.
+    CertificateField(CertificatePosition, String, Match),
+    /// `info [key] match`: a value in the code's `Info.plist`.
+    Info(String, Match),
+    /// `entitlement [key] match`: a value in the code's entitlements.
+    Entitlement(String, Match),
+}
+
+impl Requirement {
+    /// Parses requirement text.
+    pub fn parse(source: &str) -> Result<Self, RequirementError> {
+        parser::parse(source)
+    }
+
+    /// Returns whether the requirement is satisfied by `context`, if that can be decided offline.
+    pub fn evaluate(&self, context: &SigningContext) -> Evaluation {
+        eval::evaluate(self, context)
+    }
+
+    /// Returns whether the requirement is known to be satisfied by `context`.
+    pub fn is_satisfied_by(&self, context: &SigningContext) -> bool {
+        self.evaluate(context) == Evaluation::Satisfied
+    }
+
+    fn precedence(&self) -> u8 {
+        match self {
+            Requirement::Or(..) => 0,
+            Requirement::And(..) => 1,
+            _ => 2,
+        }
+    }
+
+    fn fmt_operand(&self, f: &mut fmt::Formatter<'_>, min_precedence: u8) -> fmt::Result {
+        if self.precedence() < min_precedence {
+            write!(f, "({self})")
+        } else {
+            write!(f, "{self}")
+        }
+    }
+}
+
+impl FromStr for Requirement {
+    type Err = RequirementError;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        Self::parse(source)
+    }
+}
+
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+impl fmt::Display for Requirement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Requirement::Always => f.write_str("always"),
+            Requirement::Never => f.write_str("never"),
+            Requirement::Not(inner) => {
+                f.write_str("!")?;
+                inner.fmt_operand(f, 2)
+            }
+            Requirement::And(left, right) => {
+                left.fmt_operand(f, 1)?;
+                f.write_str(" and ")?;
+                right.fmt_operand(f, 2)
+            }
+            Requirement::Or(left, right) => {
+                left.fmt_operand(f, 0)?;
+                f.write_str(" or ")?;
+                right.fmt_operand(f, 1)
+            }
+            Requirement::Identifier(identifier) => write!(f, "identifier {}", quote(identifier)),
+            Requirement::CdHash(hash) => write!(f, "cdhash H\"{}\"", hex(hash)),
+            Requirement::Platform(platform) => write!(f, "platform = {platform}"),
+            Requirement::Notarized => f.write_str("notarized"),
+            Requirement::Legacy => f.write_str("legacy"),
+            Requirement::AnchorApple => f.write_str("anchor apple"),
+            Requirement::AnchorAppleGeneric => f.write_str("anchor apple generic"),
+            Requirement::CertificateHash(position, hash) => {
+                write!(f, "certificate {position} = H\"{}\"", hex(hash))
+            }
+            Requirement::CertificateTrusted(position) => {
+                write!(f, "certificate {position} trusted")
+            }
+            Requirement::CertificateField(position, field, matcher) => {
+                write!(f, "certificate {position}[{field}] {matcher}")
+            }
+            Requirement::Info(key, matcher) => write!(f, "info [{}] {matcher}", quote(key)),
+            Requirement::Entitlement(key, matcher) => {
+                write!(f, "entitlement [{}] {matcher}", quote(key))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_codesign_output() {
+        let source = r#"identifier "com.example.helper" and anchor apple generic and
+            certificate 1[field.1.2.840.113635.100.6.2.6] /* exists */ and
+            certificate leaf[field.1.2.840.113635.100.6.1.13] /* exists */ and
+            certificate leaf[subject.OU] = TEAM000001"#;
+        let requirement = Requirement::parse(source).unwrap();
+        assert_eq!(
+            requirement.to_string(),
+            "identifier \"com.example.helper\" and anchor apple generic and \
+             certificate 1[field.1.2.840.113635.100.6.2.6] exists and \
+             certificate leaf[field.1.2.840.113635.100.6.1.13] exists and \
+             certificate leaf[subject.OU] = \"TEAM000001\""
+        );
+        assert_eq!(
+            Requirement::parse(&requirement.to_string()).unwrap(),
+            requirement
+        );
+    }
+
+    #[test]
+    fn test_precedence_and_matchers() {
+        let requirement: Requirement =
+            "!always or (never or cdhash H\"00ff\") and info[CFBundleVersion] >= \"2.0\" \
+             and entitlement[\"a.b\"] = *\"x\"*"
+                .parse()
+                .unwrap();
+        let Requirement::Or(left, right) = &requirement else {
+            panic!("`or` must bind loosest: {requirement:?}");
+        };
+        assert_eq!(**left, Requirement::Not(Box::new(Requirement::Always)));
+        assert!(matches!(**right, Requirement::And(..)));
+        assert_eq!(
+            requirement.to_string(),
+            "!always or (never or cdhash H\"00ff\") and info [\"CFBundleVersion\"] >= \"2.0\" \
+             and entitlement [\"a.b\"] = *\"x\"*"
+        );
+        assert_eq!(
+            Requirement::parse(&requirement.to_string()),
+            Ok(requirement)
+        );
+    }
+
+    #[test]
+    fn test_syntax_errors() {
+        let error = Requirement::parse("identifier \"a\" and").unwrap_err();
+        assert_eq!(error.offset, 18);
+        assert!(Requirement::parse("anchor apple generic )").is_err());
+        assert!(Requirement::parse("cdhash H\"abc\"").is_err());
+        assert!(Requirement::parse("bogus").is_err());
+        assert!(Requirement::parse("identifier \"unterminated").is_err());
+    }
+
+    #[test]
+    fn test_nesting_limit() {
+        assert!(Requirement::parse(&format!("{}always", "!".repeat(100))).is_ok());
+        for source in [
+            format!("{}always", "!".repeat(500_000)),
+            format!("{}always{}", "(".repeat(500_000), ")".repeat(500_000)),
+            format!("always{}", " and always".repeat(500_000)),
+        ] {
+            let error = Requirement::parse(&source).unwrap_err();
+            assert_eq!(error.message, "the requirement is nested too deeply");
+        }
+    }
+}
@@ -0,0 +1,396 @@
+//! Tokenizer and recursive-descent parser for the code requirement language.
+
+use super::{CertificatePosition, Match, Requirement, RequirementError};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    String(String),
+    Hash(Vec<u8>),
+    LeftParen,
+    RightParen,
+    LeftBracket,
+    RightBracket,
+    Equal,
+    Less,
+    Greater,
+    LessOrEqual,
+    GreaterOrEqual,
+    Not,
+    Star,
+}
+
+/// The most operators and parentheses a requirement may contain. Each one can nest the
+/// expression a level deeper, and parsing, evaluating and dropping it recurse through that
+/// nesting, so anything longer is rejected rather than risking a stack overflow.
+const MAX_NESTING: usize = 256;
+
+fn error(offset: usize, message: impl Into<String>) -> RequirementError {
+    RequirementError {
+        offset,
+        message: message.into(),
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-' | '/' | ':' | '@' | '+')
+}
+
+fn decode_hex(hex: &str, offset: usize) -> Result<Vec<u8>, RequirementError> {
+    if !hex.len().is_multiple_of(2) || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(error(
+            offset,
+            "hash literals must be an even number of hex digits",
+        ));
+    }
+    Ok((0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+        .collect())
+}
+
+fn tokenize(source: &str) -> Result<Vec<(usize, Token)>, RequirementError> {
+    let mut tokens = Vec::new();
+    let mut chars = source.char_indices().peekable();
+    while let Some(&(offset, c)) = chars.peek() {
+        let rest = &source[offset..];
+        if c.is_whitespace() {
+            chars.next();
+        } else if rest.starts_with("/*") {
+            let end = rest
+                .find("*/")
+                .ok_or_else(|| error(offset, "unterminated comment"))?;
+            while chars.peek().is_some_and(|&(i, _)| i < offset + end + 2) {
+                chars.next();
+            }
+        } else if rest.starts_with("//") || c == '#' {
+            while chars.peek().is_some_and(|&(_, c)| c != '\n') {
+                chars.next();
+            }
+        } else if c == '"' || (c == 'H' && rest[1..].starts_with('"')) {
+            let is_hash = c == 'H';
+            if is_hash {
+                chars.next();
+            }
+            chars.next();
+            let mut value = String::new();
+            loop {
+                match chars.next() {
+                    Some((_, '"')) => break,
+                    Some((_, '\\')) => match chars.next() {
+                        Some((_, escaped)) => value.push(escaped),
+                        None => return Err(error(offset, "unterminated string")),
+                    },
+                    Some((_, c)) => value.push(c),
+                    None => return Err(error(offset, "unterminated string")),
+                }
+            }
+            tokens.push((
+                offset,
+                if is_hash {
+                    Token::Hash(decode_hex(&value, offset)?)
+                } else {
+                    Token::String(value)
+                },
+            ));
+        } else if is_word_char(c) {
+            let mut word = String::new();
+            while let Some(&(_, c)) = chars.peek().filter(|&&(_, c)| is_word_char(c)) {
+                word.push(c);
+                chars.next();
+            }
+            tokens.push((offset, Token::Word(word)));
+        } else {
+            chars.next();
+            let next_is_equal = chars.peek().is_some_and(|&(_, c)| c == '=');
+            let token = match c {
+                '(' => Token::LeftParen,
+                ')' => Token::RightParen,
+                '[' => Token::LeftBracket,
+                ']' => Token::RightBracket,
+                '=' => Token::Equal,
+                '!' => Token::Not,
+                '*' => Token::Star,
+                '<' if next_is_equal => Token::LessOrEqual,
+                '>' if next_is_equal => Token::GreaterOrEqual,
+                '<' => Token::Less,
+                '>' => Token::Greater,
+                other => return Err(error(offset, format!("unexpected character `{other}`"))),
+            };
+            if matches!(token, Token::LessOrEqual | Token::GreaterOrEqual) {
+                chars.next();
+            }
+            tokens.push((offset, token));
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    position: usize,
+    end: usize,
+    /// How many operators and parentheses have been parsed so far.
+    nesting: usize,
+}
+
+impl Parser {
+    fn offset(&self) -> usize {
+        self.tokens
+            .get(self.position)
+            .map_or(self.end, |(offset, _)| *offset)
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position).map(|(_, token)| token)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self
+            .tokens
+            .get(self.position)
+            .map(|(_, token)| token.clone());
+        self.position += 1;
+        token
+    }
+
+    fn peek_word(&self, word: &str) -> bool {
+        matches!(self.peek(), Some(Token::Word(candidate)) if candidate == word)
+    }
+
+    fn eat_word(&mut self, word: &str) -> bool {
+        let found = self.peek_word(word);
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        let found = self.peek() == Some(token);
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, token: &Token, what: &str) -> Result<(), RequirementError> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(error(self.offset(), format!("expected {what}")))
+        }
+    }
+
+    /// Counts an operator or parenthesis, failing once there are too many.
+    fn nest(&mut self, offset: usize) -> Result<(), RequirementError> {
+        self.nesting += 1;
+        if self.nesting > MAX_NESTING {
+            return Err(error(offset, "the requirement is nested too deeply"));
+        }
+        Ok(())
+    }
+
+    fn or(&mut self) -> Result<Requirement, RequirementError> {
+        let mut left = self.and()?;
+        while self.eat_word("or") {
+            self.nest(self.offset())?;
+            left = Requirement::Or(Box::new(left), Box::new(self.and()?));
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Requirement, RequirementError> {
+        let mut left = self.unary()?;
+        while self.eat_word("and") {
+            self.nest(self.offset())?;
+            left = Requirement::And(Box::new(left), Box::new(self.unary()?));
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Requirement, RequirementError> {
+        if self.eat(&Token::Not) {
+            self.nest(self.offset())?;
+            return Ok(Requirement::Not(Box::new(self.unary()?)));
+        }
+        self.primary()
+    }
+
+    fn string(&mut self, what: &str) -> Result<String, RequirementError> {
+        match self.next() {
+            Some(Token::String(value) | Token::Word(value)) => Ok(value),
+            _ => {
+                self.position -= 1;
+                Err(error(self.offset(), format!("expected {what}")))
+            }
+        }
+    }
+
+    fn hash(&mut self) -> Result<Vec<u8>, RequirementError> {
+        self.eat(&Token::Equal);
+        match self.next() {
+            Some(Token::Hash(hash)) => Ok(hash),
+            _ => {
+                self.position -= 1;
+                Err(error(
+                    self.offset(),
+                    "expected a hash literal such as H\"...\"",
+                ))
+            }
+        }
+    }
+
+    fn key(&mut self) -> Result<String, RequirementError> {
+        self.expect(&Token::LeftBracket, "`[`")?;
+        let key = self.string("a key")?;
+        self.expect(&Token::RightBracket, "`]`")?;
+        Ok(key)
+    }
+
+    /// Parses a match operation. A missing operation means `exists`, which is how `codesign`
+    /// prints it (as a comment).
+    fn matcher(&mut self) -> Result<Match, RequirementError> {
+        if self.eat_word("exists") {
+            return Ok(Match::Exists);
+        }
+        if self.eat_word("absent") {
+            return Ok(Match::Absent);
+        }
+        let operator = match self.peek() {
+            Some(Token::Equal) => Token::Equal,
+            Some(Token::Less) => Token::Less,
+            Some(Token::Greater) => Token::Greater,
+            Some(Token::LessOrEqual) => Token::LessOrEqual,
+            Some(Token::GreaterOrEqual) => Token::GreaterOrEqual,
+            _ => return Ok(Match::Exists),
+        };
+        self.position += 1;
+        let leading = operator == Token::Equal && self.eat(&Token::Star);
+        let value = self.string("a value")?;
+        let trailing = operator == Token::Equal && self.eat(&Token::Star);
+        Ok(match (operator, leading, trailing) {
+            (Token::Equal, true, true) => Match::Contains(value),
+            (Token::Equal, true, false) => Match::Suffix(value),
+            (Token::Equal, false, true) => Match::Prefix(value),
+            (Token::Equal, false, false) => Match::Equal(value),
+            (Token::Less, ..) => Match::Less(value),
+            (Token::Greater, ..) => Match::Greater(value),
+            (Token::LessOrEqual, ..) => Match::LessOrEqual(value),
+            _ => Match::GreaterOrEqual(value),
+        })
+    }
+
+    fn position(&mut self) -> Result<CertificatePosition, RequirementError> {
+        let offset = self.offset();
+        match self.next() {
+            Some(Token::Word(word)) => match word.as_str() {
+                "leaf" => Ok(CertificatePosition::Leaf),
+                "root" | "anchor" => Ok(CertificatePosition::Root),
+                number => number
+                    .parse()
+                    .map(CertificatePosition::Index)
+                    .map_err(|_| error(offset, "expected `leaf`, `root` or a certificate index")),
+            },
+            _ => Err(error(
+                offset,
+                "expected `leaf`, `root` or a certificate index",
+            )),
+        }
+    }
+
+    fn primary(&mut self) -> Result<Requirement, RequirementError> {
+        let offset = self.offset();
+        let word = match self.next() {
+            Some(Token::LeftParen) => {
+                self.nest(offset)?;
+                let inner = self.or()?;
+                self.expect(&Token::RightParen, "`)`")?;
+                return Ok(inner);
+            }
+            Some(Token::Word(word)) => word,
+            Some(_) => return Err(error(offset, "expected a requirement")),
+            None => return Err(error(offset, "unexpected end of requirement")),
+        };
+        self.clause(&word, offset)
+    }
+
+    /// Parses the rest of the clause starting with `word`. Kept out of `primary`, which recurses
+    /// for parentheses, so its locals don't grow every level's stack frame.
+    #[inline(never)]
+    fn clause(&mut self, word: &str, offset: usize) -> Result<Requirement, RequirementError> {
+        match word {
+            "always" | "true" => Ok(Requirement::Always),
+            "never" | "false" => Ok(Requirement::Never),
+            "identifier" => {
+                self.eat(&Token::Equal);
+                Ok(Requirement::Identifier(self.string("an identifier")?))
+            }
+            "cdhash" => Ok(Requirement::CdHash(self.hash()?)),
+            "platform" => {
+                self.eat(&Token::Equal);
+                let offset = self.offset();
+                self.string("a platform number")?
+                    .parse()
+                    .map(Requirement::Platform)
+                    .map_err(|_| error(offset, "expected a platform number"))
+            }
+            "notarized" => Ok(Requirement::Notarized),
+            "legacy" => Ok(Requirement::Legacy),
+            "anchor" => {
+                if self.eat_word("apple") {
+                    if self.eat_word("generic") {
+                        Ok(Requirement::AnchorAppleGeneric)
+                    } else {
+                        Ok(Requirement::AnchorApple)
+                    }
+                } else if self.eat_word("trusted") {
+                    Ok(Requirement::CertificateTrusted(CertificatePosition::Root))
+                } else {
+                    Ok(Requirement::CertificateHash(
+                        CertificatePosition::Root,
+                        self.hash()?,
+                    ))
+                }
+            }
+            "info" => {
+                let key = self.key()?;
+                Ok(Requirement::Info(key, self.matcher()?))
+            }
+            "entitlement" => {
+                let key = self.key()?;
+                Ok(Requirement::Entitlement(key, self.matcher()?))
+            }
+            "certificate" | "cert" => {
+                let position = self.position()?;
+                if self.eat_word("trusted") {
+                    Ok(Requirement::CertificateTrusted(position))
+                } else if self.peek() == Some(&Token::LeftBracket) {
+                    let field = self.key()?;
+                    Ok(Requirement::CertificateField(
+                        position,
+                        field,
+                        self.matcher()?,
+                    ))
+                } else {
+                    Ok(Requirement::CertificateHash(position, self.hash()?))
+                }
+            }
+            other => Err(error(offset, format!("unknown requirement `{other}`"))),
+        }
+    }
+}
+
+pub(super) fn parse(source: &str) -> Result<Requirement, RequirementError> {
+    let mut parser = Parser {
+        tokens: tokenize(source)?,
+        position: 0,
+        end: source.len(),
+        nesting: 0,
+    };
+    let requirement = parser.or()?;
+    if parser.peek().is_some() {
+        return Err(error(parser.offset(), "unexpected trailing input"));
+    }
+    Ok(requirement)
+}
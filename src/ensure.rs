@@ -0,0 +1,152 @@
+//! Idempotent registration, for startup code that runs on every launch.
+
+use crate::{AppService, ServiceManagementError, ServiceStatus};
+
+/// What [`AppService::ensure_registered`] or [`AppService::ensure_unregistered`] did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EnsureReport {
+    /// Whether the service was registered or unregistered by the call.
+    pub changed: bool,
+
+    /// The status before the call.
+    pub before: ServiceStatus,
+
+    /// The status after the call.
+    pub after: ServiceStatus,
+}
+
+impl EnsureReport {
+    /// Returns whether the user still has to enable the service in System Settings.
+    pub fn requires_user_action(&self) -> bool {
+        self.after == ServiceStatus::RequiresApproval
+    }
+}
+
+impl AppService {
+    /// Registers the service unless it's already registered.
+    ///
+    /// A service that is [`ServiceStatus::Enabled`] or [`ServiceStatus::RequiresApproval`] is left
+    /// alone, and [`ServiceManagementError::AlreadyRegistered`] counts as success. Other errors
+    /// from [`register`](#method.register) are returned.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use smappservice_rs::{AppService, ServiceType};
+    ///
+    /// let daemon = AppService::new(ServiceType::Daemon {
+    ///     plist_name: "com.example.myapp.daemon.plist",
+    /// });
+    /// let report = daemon.ensure_registered().unwrap();
+    /// if report.requires_user_action() {
+    ///     AppService::open_system_settings_login_items();
+    /// }
+    /// ```
+    pub fn ensure_registered(&self) -> Result<EnsureReport, ServiceManagementError> {
+        let before = self.status();
+        let changed = match before {
+            ServiceStatus::Enabled | ServiceStatus::RequiresApproval => false,
+            _ => match self.register() {
+                Ok(()) => true,
+                Err(ServiceManagementError::AlreadyRegistered) => false,
+                Err(error) => return Err(error),
+            },
+        };
+        Ok(self.report(changed, before))
+    }
+
+    /// Unregisters the service if it's registered.
+    ///
+    /// A service that is [`ServiceStatus::NotRegistered`] or [`ServiceStatus::NotFound`] is left
+    /// alone, and [`ServiceManagementError::JobNotFound`] counts as success. Other errors from
+    /// [`unregister`](#method.unregister) are returned.
+    pub fn ensure_unregistered(&self) -> Result<EnsureReport, ServiceManagementError> {
+        let before = self.status();
+        let changed = match before {
+            ServiceStatus::NotRegistered | ServiceStatus::NotFound => false,
+            _ => match self.unregister() {
+                Ok(()) => true,
+                Err(ServiceManagementError::JobNotFound) => false,
+                Err(error) => return Err(error),
+            },
+        };
+        Ok(self.report(changed, before))
+    }
+
+    fn report(&self, changed: bool, before: ServiceStatus) -> EnsureReport {
+        EnsureReport {
+            changed,
+            before,
+            after: self.status(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FaultInjectingBackend, Operation, ServiceType, SimulatedBackend};
+
+    const DAEMON: ServiceType<'static> = ServiceType::Daemon {
+        plist_name: "com.example.daemon.plist",
+    };
+
+    #[test]
+    fn test_ensure_registered() {
+        let backend = SimulatedBackend::new();
+        let daemon = AppService::with_backend(DAEMON, backend.clone());
+
+        let report = daemon.ensure_registered().unwrap();
+        assert_eq!(
+            report,
+            EnsureReport {
+                changed: true,
+                before: ServiceStatus::NotRegistered,
+                after: ServiceStatus::RequiresApproval,
+            }
+        );
+        assert!(report.requires_user_action());
+
+        let report = daemon.ensure_registered().unwrap();
+        assert!(!report.changed);
+        assert!(report.requires_user_action());
+
+        backend.approve(&DAEMON);
+        let report = daemon.ensure_registered().unwrap();
+        assert!(!report.changed);
+        assert!(!report.requires_user_action());
+    }
+
+    #[test]
+    fn test_ensure_unregistered() {
+        let backend = FaultInjectingBackend::new(SimulatedBackend::new());
+        let daemon = AppService::with_backend(DAEMON, backend.clone());
+
+        assert!(!daemon.ensure_unregistered().unwrap().changed);
+        assert_eq!(backend.call_count(&DAEMON, Operation::Unregister), 0);
+
+        daemon.register().unwrap();
+        let report = daemon.ensure_unregistered().unwrap();
+        assert!(report.changed);
+        assert_eq!(report.after, ServiceStatus::NotRegistered);
+
+        // The status can lag behind the framework; the benign error still counts as success.
+        backend.inner().set_status(&DAEMON, ServiceStatus::Enabled);
+        backend.fail_next(
+            &DAEMON,
+            Operation::Unregister,
+            ServiceManagementError::JobNotFound,
+        );
+        assert!(!daemon.ensure_unregistered().unwrap().changed);
+
+        backend.fail_next(
+            &DAEMON,
+            Operation::Unregister,
+            ServiceManagementError::InternalFailure,
+        );
+        assert_eq!(
+            daemon.ensure_unregistered(),
+            Err(ServiceManagementError::InternalFailure)
+        );
+    }
+}
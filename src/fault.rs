@@ -0,0 +1,375 @@
+//! A backend wrapper that injects scripted `ServiceManagementError`s.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::{
+    Operation, OwnedServiceType, ServiceBackend, ServiceManagementError, ServiceStatus,
+    ServiceType, SimulatedBackend,
+};
+
+/// A call received by a [`FaultInjectingBackend`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BackendCall {
+    /// The service the call was made for, `None` for [`Operation::OpenSystemSettings`].
+    pub service_type: Option<OwnedServiceType>,
+
+    /// The operation that was called.
+    pub operation: Operation,
+
+    /// The error injected into the call, if any.
+    pub injected: Option<ServiceManagementError>,
+}
+
+/// A step in the fault plan of a service operation.
+#[derive(Debug, Clone, Copy)]
+enum Step {
+    Pass,
+    Fail(ServiceManagementError),
+}
+
+#[derive(Debug, Default)]
+struct Plan {
+    steps: VecDeque<Step>,
+    always: Option<ServiceManagementError>,
+}
+
+#[derive(Debug, Default)]
+struct FaultState {
+    plans: HashMap<(OwnedServiceType, Operation), Plan>,
+    calls: Vec<BackendCall>,
+}
+
+/// A [`ServiceBackend`] that wraps another backend and fails scripted calls.
+///
+/// Faults are planned per service and per operation. Scripted steps are consumed in order, one
+/// per call, and new steps are queued behind the ones already scripted. Once they run out the
+/// call is forwarded to the wrapped backend, unless a permanent fault was set with
+/// [`fail_always`](#method.fail_always). Only [`Operation::Register`] and
+/// [`Operation::Unregister`] can fail, so planning a fault for any other operation panics.
+///
+/// Every call is recorded so tests can assert on which calls happened. Clones share the plan
+/// and the call log.
+///
+/// # Examples
+///
+/// ```rust
+/// use smappservice_rs::{
+///     AppService, FaultInjectingBackend, Operation, ServiceManagementError, ServiceType,
+///     SimulatedBackend,
+/// };
+///
+/// let backend = FaultInjectingBackend::new(SimulatedBackend::new());
+/// let agent_type = ServiceType::Agent {
+///     plist_name: "com.example.myapp.agent.plist",
+/// };
+/// backend.fail_times(
+///     &agent_type,
+///     Operation::Register,
+///     2,
+///     ServiceManagementError::ServiceUnavailable,
+/// );
+///
+/// let agent = AppService::with_backend(agent_type, backend.clone());
+/// assert_eq!(agent.register(), Err(ServiceManagementError::ServiceUnavailable));
+/// assert_eq!(agent.register(), Err(ServiceManagementError::ServiceUnavailable));
+/// assert_eq!(agent.register(), Ok(()));
+/// assert_eq!(backend.call_count(&agent_type, Operation::Register), 3);
+/// ```
+pub struct FaultInjectingBackend<B = SimulatedBackend> {
+    inner: Arc<B>,
+    state: Arc<Mutex<FaultState>>,
+}
+
+impl<B> Clone for FaultInjectingBackend<B> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: Arc::clone(&self.inner),
+            state: Arc::clone(&self.state),
+        }
+    }
+}
+
+impl<B: ServiceBackend> FaultInjectingBackend<B> {
+    /// Wraps `inner` with an empty fault plan.
+    pub fn new(inner: B) -> Self {
+        Self {
+            inner: Arc::new(inner),
+            state: Arc::default(),
+        }
+    }
+
+    /// Returns the wrapped backend.
+    pub fn inner(&self) -> &B {
+        &self.inner
+    }
+
+    /// Queues a step that fails one call of `operation` on `service_type` with `error`.
+    ///
+    /// The step runs after any already scripted for the same service and operation, so with
+    /// nothing scripted it fails the next call.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `operation` is neither [`Operation::Register`] nor [`Operation::Unregister`].
+    pub fn fail_next(
+        &self,
+        service_type: &ServiceType,
+        operation: Operation,
+        error: ServiceManagementError,
+    ) -> &Self {
+        self.fail_times(service_type, operation, 1, error)
+    }
+
+    /// Queues `times` steps that each fail a call of `operation` on `service_type` with `error`,
+    /// after any already scripted.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `operation` is neither [`Operation::Register`] nor [`Operation::Unregister`].
+    pub fn fail_times(
+        &self,
+        service_type: &ServiceType,
+        operation: Operation,
+        times: usize,
+        error: ServiceManagementError,
+    ) -> &Self {
+        self.push_steps(service_type, operation, times, Step::Fail(error))
+    }
+
+    /// Queues a step that lets one call of `operation` on `service_type` through to the wrapped
+    /// backend, after any already scripted, so later failures can be scheduled after a success.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `operation` is neither [`Operation::Register`] nor [`Operation::Unregister`].
+    pub fn pass_next(&self, service_type: &ServiceType, operation: Operation) -> &Self {
+        self.push_steps(service_type, operation, 1, Step::Pass)
+    }
+
+    /// Fails every call of `operation` on `service_type` with `error` once the scripted steps
+    /// are consumed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `operation` is neither [`Operation::Register`] nor [`Operation::Unregister`].
+    pub fn fail_always(
+        &self,
+        service_type: &ServiceType,
+        operation: Operation,
+        error: ServiceManagementError,
+    ) -> &Self {
+        assert_fallible(operation);
+        self.lock()
+            .plans
+            .entry((OwnedServiceType::from(service_type), operation))
+            .or_default()
+            .always = Some(error);
+        self
+    }
+
+    /// Removes every planned fault. The call log is kept.
+    pub fn clear_faults(&self) {
+        self.lock().plans.clear();
+    }
+
+    /// Returns every call received so far, in order.
+    pub fn calls(&self) -> Vec<BackendCall> {
+        self.lock().calls.clone()
+    }
+
+    /// Returns how many times `operation` was called on `service_type`.
+    pub fn call_count(&self, service_type: &ServiceType, operation: Operation) -> usize {
+        let service_type = OwnedServiceType::from(service_type);
+        self.lock()
+            .calls
+            .iter()
+            .filter(|call| {
+                call.operation == operation && call.service_type.as_ref() == Some(&service_type)
+            })
+            .count()
+    }
+
+    fn push_steps(
+        &self,
+        service_type: &ServiceType,
+        operation: Operation,
+        times: usize,
+        step: Step,
+    ) -> &Self {
+        assert_fallible(operation);
+        self.lock()
+            .plans
+            .entry((OwnedServiceType::from(service_type), operation))
+            .or_default()
+            .steps
+            .extend(std::iter::repeat_n(step, times));
+        self
+    }
+
+    /// Records the call and returns the error to inject, if any.
+    fn intercept(
+        &self,
+        service_type: &ServiceType,
+        operation: Operation,
+    ) -> Option<ServiceManagementError> {
+        let service_type = OwnedServiceType::from(service_type);
+        let mut state = self.lock();
+        let injected = match state.plans.get_mut(&(service_type.clone(), operation)) {
+            Some(plan) => match plan.steps.pop_front() {
+                Some(Step::Fail(error)) => Some(error),
+                Some(Step::Pass) => None,
+                None => plan.always,
+            },
+            None => None,
+        };
+        state.calls.push(BackendCall {
+            service_type: Some(service_type),
+            operation,
+            injected,
+        });
+        injected
+    }
+
+    fn lock(&self) -> MutexGuard<'_, FaultState> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Rejects plans for operations that can't fail, which would otherwise never be consulted.
+fn assert_fallible(operation: Operation) {
+    assert!(
+        matches!(operation, Operation::Register | Operation::Unregister),
+        "only register and unregister calls can fail, not {operation}"
+    );
+}
+
+impl<B: ServiceBackend> ServiceBackend for FaultInjectingBackend<B> {
+    fn register(&self, service_type: &ServiceType) -> Result<(), ServiceManagementError> {
+        match self.intercept(service_type, Operation::Register) {
+            Some(error) => Err(error),
+            None => self.inner.register(service_type),
+        }
+    }
+
+    fn unregister(&self, service_type: &ServiceType) -> Result<(), ServiceManagementError> {
+        match self.intercept(service_type, Operation::Unregister) {
+            Some(error) => Err(error),
+            None => self.inner.unregister(service_type),
+        }
+    }
+
+    fn status(&self, service_type: &ServiceType) -> ServiceStatus {
+        self.lock().calls.push(BackendCall {
+            service_type: Some(OwnedServiceType::from(service_type)),
+            operation: Operation::Status,
+            injected: None,
+        });
+        self.inner.status(service_type)
+    }
+
+    fn open_system_settings_login_items(&self) {
+        self.lock().calls.push(BackendCall {
+            service_type: None,
+            operation: Operation::OpenSystemSettings,
+            injected: None,
+        });
+        self.inner.open_system_settings_login_items()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AppService;
+
+    const DAEMON: ServiceType<'static> = ServiceType::Daemon {
+        plist_name: "com.example.daemon.plist",
+    };
+
+    #[test]
+    fn test_fail_first_register() {
+        let backend = FaultInjectingBackend::new(SimulatedBackend::new());
+        backend.fail_next(
+            &ServiceType::MainApp,
+            Operation::Register,
+            ServiceManagementError::InvalidSignature,
+        );
+        let service = AppService::with_backend(ServiceType::MainApp, backend.clone());
+
+        assert_eq!(
+            service.register(),
+            Err(ServiceManagementError::InvalidSignature)
+        );
+        assert_eq!(service.status(), ServiceStatus::NotRegistered);
+        assert_eq!(service.register(), Ok(()));
+        assert_eq!(service.status(), ServiceStatus::Enabled);
+
+        let calls = backend.calls();
+        assert_eq!(calls.len(), 4);
+        assert_eq!(
+            calls[0].injected,
+            Some(ServiceManagementError::InvalidSignature)
+        );
+        assert!(calls[1..].iter().all(|call| call.injected.is_none()));
+    }
+
+    #[test]
+    fn test_scripted_sequence_and_always() {
+        let backend = FaultInjectingBackend::new(SimulatedBackend::new());
+        backend
+            .pass_next(&DAEMON, Operation::Register)
+            .fail_next(
+                &DAEMON,
+                Operation::Register,
+                ServiceManagementError::AuthorizationFailure,
+            )
+            .fail_always(
+                &DAEMON,
+                Operation::Register,
+                ServiceManagementError::LaunchDeniedByUser,
+            );
+        let service = AppService::with_backend(DAEMON, backend.clone());
+
+        assert_eq!(service.register(), Ok(()));
+        assert_eq!(
+            service.register(),
+            Err(ServiceManagementError::AuthorizationFailure)
+        );
+        assert_eq!(
+            service.register(),
+            Err(ServiceManagementError::LaunchDeniedByUser)
+        );
+        assert_eq!(
+            service.register(),
+            Err(ServiceManagementError::LaunchDeniedByUser)
+        );
+
+        // Faults are scoped to the service and operation they were planned for.
+        assert_eq!(service.unregister(), Ok(()));
+        let agent = AppService::with_backend(
+            ServiceType::Agent {
+                plist_name: "com.example.daemon.plist",
+            },
+            backend.clone(),
+        );
+        assert_eq!(agent.register(), Ok(()));
+
+        backend.clear_faults();
+        assert_eq!(service.register(), Ok(()));
+        assert_eq!(backend.call_count(&DAEMON, Operation::Register), 5);
+        assert_eq!(backend.call_count(&DAEMON, Operation::Unregister), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "only register and unregister calls can fail, not status")]
+    fn test_status_cannot_fail() {
+        FaultInjectingBackend::new(SimulatedBackend::new()).fail_next(
+            &DAEMON,
+            Operation::Status,
+            ServiceManagementError::InternalFailure,
+        );
+    }
+}
@@ -0,0 +1,321 @@
+//! Non-blocking versions of the [`AppService`] calls, enabled by the `async` feature.
+//!
+//! ServiceManagement calls block, sometimes for a noticeable time when daemons are involved.
+//! The futures here hand each call to a dedicated worker thread and complete when it returns,
+//! so they can be awaited on any executor without blocking it.
+
+use std::collections::VecDeque;
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+
+use futures_core::Stream;
+
+use crate::{
+    AppService, OwnedServiceType, ServiceManagementError, ServiceStatus, StatusWatcher,
+    WatchOptions,
+};
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Returns the sender of the worker thread that runs every offloaded call, starting it on first
+/// use. Calls run one at a time in the order they were made.
+fn worker() -> &'static Mutex<Sender<Job>> {
+    static WORKER: OnceLock<Mutex<Sender<Job>>> = OnceLock::new();
+    WORKER.get_or_init(|| {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        thread::Builder::new()
+            .name("smappservice-worker".to_string())
+            .spawn(move || {
+                for job in receiver {
+                    job();
+                }
+            })
+            .expect("failed to spawn the ServiceManagement worker thread");
+        Mutex::new(sender)
+    })
+}
+
+struct Completion<T> {
+    result: Option<thread::Result<T>>,
+    waker: Option<Waker>,
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// A call running on the worker thread. Resolves to the call's result.
+///
+/// The call starts as soon as the future is created, whether or not it is polled. Dropping the
+/// future doesn't cancel it. A panic in the backend is resumed in the task that polls the future.
+#[must_use = "the call runs regardless, but its result is lost unless the future is awaited"]
+pub struct ServiceFuture<T> {
+    completion: Arc<Mutex<Completion<T>>>,
+}
+
+impl<T: Send + 'static> ServiceFuture<T> {
+    fn spawn(call: impl FnOnce() -> T + Send + 'static) -> Self {
+        let completion = Arc::new(Mutex::new(Completion {
+            result: None,
+            waker: None,
+        }));
+        let job = {
+            let completion = completion.clone();
+            Box::new(move || {
+                // Catching the panic keeps the worker alive for later calls.
+                let result = panic::catch_unwind(AssertUnwindSafe(call));
+                let waker = {
+                    let mut completion = lock(&completion);
+                    completion.result = Some(result);
+                    completion.waker.take()
+                };
+                if let Some(waker) = waker {
+                    waker.wake();
+                }
+            })
+        };
+        lock(worker())
+            .send(job)
+            .expect("the ServiceManagement worker thread has exited");
+        Self { completion }
+    }
+}
+
+impl<T> Future for ServiceFuture<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        let mut completion = lock(&self.completion);
+        match completion.result.take() {
+            Some(Ok(value)) => Poll::Ready(value),
+            Some(Err(payload)) => panic::resume_unwind(payload),
+            None => {
+                completion.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl<T> std::fmt::Debug for ServiceFuture<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ServiceFuture")
+            .field("ready", &lock(&self.completion).result.is_some())
+            .finish()
+    }
+}
+
+#[derive(Default)]
+struct StreamState {
+    statuses: VecDeque<ServiceStatus>,
+    waker: Option<Waker>,
+}
+
+/// A [`Stream`] of a service's statuses, yielding the new status each time it changes.
+///
+/// Backed by a [`StatusWatcher`], which stops when the stream is dropped. The stream never ends
+/// on its own.
+pub struct StatusStream {
+    state: Arc<Mutex<StreamState>>,
+    _watcher: StatusWatcher,
+}
+
+impl Stream for StatusStream {
+    type Item = ServiceStatus;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<ServiceStatus>> {
+        let mut state = lock(&self.state);
+        match state.statuses.pop_front() {
+            Some(status) => Poll::Ready(Some(status)),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl std::fmt::Debug for StatusStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StatusStream")
+            .field("pending", &lock(&self.state).statuses.len())
+            .finish()
+    }
+}
+
+impl AppService {
+    /// Like [`register`](#method.register), but runs on the worker thread.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use smappservice_rs::{AppService, ServiceType};
+    ///
+    /// async fn enable_daemon() {
+    ///     let daemon = AppService::new(ServiceType::Daemon {
+    ///         plist_name: "com.example.myapp.daemon.plist",
+    ///     });
+    ///     if let Err(e) = daemon.register_async().await {
+    ///         eprintln!("Failed to register daemon: {}", e);
+    ///     }
+    /// }
+    /// ```
+    pub fn register_async(&self) -> ServiceFuture<Result<(), ServiceManagementError>> {
+        let (backend, service_type) = self.detached();
+        ServiceFuture::spawn(move || backend.register(&service_type.as_service_type()))
+    }
+
+    /// Like [`unregister`](#method.unregister), but runs on the worker thread.
+    pub fn unregister_async(&self) -> ServiceFuture<Result<(), ServiceManagementError>> {
+        let (backend, service_type) = self.detached();
+        ServiceFuture::spawn(move || backend.unregister(&service_type.as_service_type()))
+    }
+
+    /// Like [`status`](#method.status), but runs on the worker thread.
+    pub fn status_async(&self) -> ServiceFuture<ServiceStatus> {
+        let (backend, service_type) = self.detached();
+        ServiceFuture::spawn(move || backend.status(&service_type.as_service_type()))
+    }
+
+    /// Returns a stream that yields the service's status each time it changes, polled as
+    /// configured by `options`.
+    pub fn status_stream(&self, options: WatchOptions) -> StatusStream {
+        let (backend, service_type) = self.detached();
+        let state = Arc::new(Mutex::new(StreamState::default()));
+        let watcher = StatusWatcher::spawn(backend, [service_type], options, {
+            let state = state.clone();
+            move |change| {
+                let waker = {
+                    let mut state = lock(&state);
+                    state.statuses.push_back(change.new);
+                    state.waker.take()
+                };
+                if let Some(waker) = waker {
+                    waker.wake();
+                }
+            }
+        });
+        StatusStream {
+            state,
+            _watcher: watcher,
+        }
+    }
+
+    fn detached(&self) -> (Arc<dyn crate::ServiceBackend>, OwnedServiceType) {
+        (self.backend.clone(), self.service_type.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Condvar;
+    use std::task::Wake;
+    use std::time::{Duration, Instant};
+
+    use super::*;
+    use crate::{ServiceType, SimulatedBackend};
+
+    /// Wakes a thread parked in [`block_on`].
+    #[derive(Default)]
+    struct Signal {
+        woken: Mutex<bool>,
+        condvar: Condvar,
+    }
+
+    impl Wake for Signal {
+        fn wake(self: Arc<Self>) {
+            *lock(&self.woken) = true;
+            self.condvar.notify_one();
+        }
+    }
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// A minimal executor: polls `future` on the current thread until it completes. Panics if the
+    /// future isn't woken within [`TIMEOUT`], rather than hanging the test.
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let signal = Arc::new(Signal::default());
+        let waker = Waker::from(signal.clone());
+        let mut context = Context::from_waker(&waker);
+        let mut future = std::pin::pin!(future);
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut context) {
+                return output;
+            }
+            let deadline = Instant::now() + TIMEOUT;
+            let mut woken = lock(&signal.woken);
+            while !*woken {
+                let remaining = deadline
+                    .checked_duration_since(Instant::now())
+                    .expect("the future was never woken");
+                woken = signal.condvar.wait_timeout(woken, remaining).unwrap().0;
+            }
+            *woken = false;
+        }
+    }
+
+    fn next<S: Stream + Unpin>(stream: &mut S) -> Option<S::Item> {
+        block_on(std::future::poll_fn(|cx| {
+            Pin::new(&mut *stream).poll_next(cx)
+        }))
+    }
+
+    #[test]
+    fn test_calls_run_on_worker() {
+        let backend = SimulatedBackend::new();
+        let daemon = AppService::with_backend(
+            ServiceType::Daemon {
+                plist_name: "com.example.daemon.plist",
+            },
+            backend.clone(),
+        );
+        assert_eq!(
+            block_on(daemon.status_async()),
+            ServiceStatus::NotRegistered
+        );
+        block_on(daemon.register_async()).unwrap();
+        assert_eq!(
+            block_on(daemon.register_async()),
+            Err(ServiceManagementError::AlreadyRegistered)
+        );
+        assert_eq!(
+            block_on(daemon.status_async()),
+            ServiceStatus::RequiresApproval
+        );
+
+        // Calls run in order, so awaiting only the last one is enough.
+        let unregister = daemon.unregister_async();
+        let status = daemon.status_async();
+        assert_eq!(block_on(status), ServiceStatus::NotRegistered);
+        assert_eq!(block_on(unregister), Ok(()));
+    }
+
+    #[test]
+    fn test_status_stream() {
+        let backend = SimulatedBackend::new();
+        let service = AppService::with_backend(ServiceType::MainApp, backend.clone());
+        let mut statuses = service.status_stream(WatchOptions {
+            interval: Duration::from_millis(5),
+            max_interval: Duration::from_millis(5),
+            backoff: 1.0,
+        });
+
+        // Wait for the watcher to take its baseline before changing anything.
+        let deadline = Instant::now() + TIMEOUT;
+        while statuses._watcher.status(&ServiceType::MainApp).is_none() {
+            assert!(Instant::now() < deadline, "the watcher never polled");
+            thread::sleep(Duration::from_millis(1));
+        }
+        service.register().unwrap();
+        assert_eq!(next(&mut statuses), Some(ServiceStatus::Enabled));
+        service.unregister().unwrap();
+        assert_eq!(next(&mut statuses), Some(ServiceStatus::NotRegistered));
+    }
+}
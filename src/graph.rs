@@ -0,0 +1,457 @@
+//! Registering services that depend on each other.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+use thiserror::Error;
+
+use crate::{
+    AppService, DefaultBackend, OwnedServiceType, ServiceBackend, ServiceManagementError,
+    ServiceStatus,
+};
+
+/// A service of a [`ServiceGraph`] and the services it needs to be enabled first.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServiceNode {
+    /// The service.
+    pub service_type: OwnedServiceType,
+
+    /// The services that must be [`ServiceStatus::Enabled`] before this one is registered.
+    pub depends_on: Vec<OwnedServiceType>,
+}
+
+impl ServiceNode {
+    /// Creates a node for `service_type` that depends on `depends_on`.
+    pub fn new<S: Into<OwnedServiceType>>(
+        service_type: impl Into<OwnedServiceType>,
+        depends_on: impl IntoIterator<Item = S>,
+    ) -> Self {
+        Self {
+            service_type: service_type.into(),
+            depends_on: depends_on.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+/// An error in the dependencies passed to [`ServiceGraph::new`].
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum GraphError {
+    /// The same service was declared twice.
+    #[error("{0} is declared more than once")]
+    Duplicate(OwnedServiceType),
+
+    /// A service depends on a service that isn't part of the graph.
+    #[error("{service} depends on {dependency}, which is not in the graph")]
+    UnknownDependency {
+        service: OwnedServiceType,
+        dependency: OwnedServiceType,
+    },
+
+    /// The dependencies form a cycle. Lists the services on the cycle, starting and ending with
+    /// the same one.
+    #[error("dependency cycle: {}", .0.iter().map(ToString::to_string).collect::<Vec<_>>().join(" -> "))]
+    Cycle(Vec<OwnedServiceType>),
+}
+
+/// What happened to one service of a [`ServiceGraph`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NodeOutcome {
+    /// The service was registered by this call.
+    Registered,
+
+    /// The service was unregistered by this call.
+    Unregistered,
+
+    /// The service was already in the requested state, so it was left alone.
+    Unchanged,
+
+    /// The call failed for this service.
+    Failed(ServiceManagementError),
+
+    /// The service wasn't registered because a service it depends on isn't enabled yet.
+    Held {
+        /// The first dependency that isn't enabled.
+        prerequisite: OwnedServiceType,
+
+        /// The dependency's status, for example [`ServiceStatus::RequiresApproval`] while the
+        /// user hasn't approved it.
+        status: ServiceStatus,
+    },
+}
+
+impl fmt::Display for NodeOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NodeOutcome::Registered => write!(f, "registered"),
+            NodeOutcome::Unregistered => write!(f, "unregistered"),
+            NodeOutcome::Unchanged => write!(f, "unchanged"),
+            NodeOutcome::Failed(error) => write!(f, "failed: {}", error),
+            NodeOutcome::Held {
+                prerequisite,
+                status,
+            } => write!(f, "held: {} is {}", prerequisite, status),
+        }
+    }
+}
+
+/// The outcome of every service touched by [`ServiceGraph::register`] or
+/// [`ServiceGraph::unregister`], in the order they were processed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GraphReport {
+    /// Each service with its outcome.
+    pub outcomes: Vec<(OwnedServiceType, NodeOutcome)>,
+}
+
+impl GraphReport {
+    /// Returns whether every service reached the requested state.
+    pub fn is_complete(&self) -> bool {
+        self.outcomes.iter().all(|(_, outcome)| {
+            matches!(
+                outcome,
+                NodeOutcome::Registered | NodeOutcome::Unregistered | NodeOutcome::Unchanged
+            )
+        })
+    }
+
+    /// Returns the services that were held back, with the reason.
+    pub fn held(&self) -> impl Iterator<Item = (&OwnedServiceType, &NodeOutcome)> {
+        self.outcomes
+            .iter()
+            .filter(|(_, outcome)| matches!(outcome, NodeOutcome::Held { .. }))
+            .map(|(service_type, outcome)| (service_type, outcome))
+    }
+}
+
+impl fmt::Display for GraphReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (service_type, outcome) in &self.outcomes {
+            writeln!(f, "{}: {}", service_type, outcome)?;
+        }
+        Ok(())
+    }
+}
+
+/// A set of services registered in dependency order.
+///
+/// [`register`](#method.register) registers services after the ones they depend on, and only
+/// once those are [`ServiceStatus::Enabled`]. A dependent of a daemon that is waiting for
+/// approval is held back with [`NodeOutcome::Held`]; calling `register` again after the user
+/// approves it picks up where it left off. [`unregister`](#method.unregister) goes in reverse
+/// order, so dependents are removed before what they depend on.
+///
+/// # Examples
+///
+/// ```rust
+/// use smappservice_rs::{ServiceGraph, ServiceNode, ServiceType, SimulatedBackend};
+///
+/// let daemon = ServiceType::Daemon { plist_name: "com.example.myapp.daemon.plist" };
+/// let agent = ServiceType::Agent { plist_name: "com.example.myapp.agent.plist" };
+/// let helper = ServiceType::LoginItem { identifier: "com.example.myapp.helper" };
+///
+/// let backend = SimulatedBackend::new();
+/// let graph = ServiceGraph::with_backend(
+///     [
+///         ServiceNode::new(helper, [agent]),
+///         ServiceNode::new(agent, [daemon]),
+///         ServiceNode::new(daemon, Vec::<ServiceType>::new()),
+///     ],
+///     backend.clone(),
+/// )
+/// .unwrap();
+///
+/// // The daemon needs approval, so the agent and the helper wait.
+/// let report = graph.register();
+/// assert_eq!(report.held().count(), 2);
+///
+/// backend.approve(&daemon);
+/// assert!(graph.register().is_complete());
+/// ```
+pub struct ServiceGraph {
+    /// The nodes in topological order: every node comes after its dependencies.
+    nodes: Vec<ServiceNode>,
+    backend: Arc<dyn ServiceBackend>,
+}
+
+impl fmt::Debug for ServiceGraph {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ServiceGraph")
+            .field("nodes", &self.nodes)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ServiceGraph {
+    /// Builds a graph from `nodes` that uses the platform's [`DefaultBackend`].
+    ///
+    /// Fails if a service is declared twice, depends on a service that isn't declared, or is
+    /// part of a dependency cycle.
+    pub fn new(nodes: impl IntoIterator<Item = ServiceNode>) -> Result<Self, GraphError> {
+        Self::with_backend(nodes, DefaultBackend::default())
+    }
+
+    /// Builds a graph from `nodes` that goes through `backend`.
+    pub fn with_backend(
+        nodes: impl IntoIterator<Item = ServiceNode>,
+        backend: impl ServiceBackend + 'static,
+    ) -> Result<Self, GraphError> {
+        Ok(Self {
+            nodes: sort(nodes.into_iter().collect())?,
+            backend: Arc::new(backend),
+        })
+    }
+
+    /// Returns the services in registration order.
+    pub fn order(&self) -> impl DoubleEndedIterator<Item = &OwnedServiceType> {
+        self.nodes.iter().map(|node| &node.service_type)
+    }
+
+    /// Registers every service whose dependencies are enabled, in dependency order.
+    ///
+    /// A service is held back if any of its dependencies isn't [`ServiceStatus::Enabled`],
+    /// including when registering the dependency failed. Independent services are still
+    /// registered.
+    pub fn register(&self) -> GraphReport {
+        let mut statuses: HashMap<&OwnedServiceType, ServiceStatus> = HashMap::new();
+        let mut outcomes = Vec::with_capacity(self.nodes.len());
+        for node in &self.nodes {
+            let service = self.service(&node.service_type);
+            let held = node.depends_on.iter().find_map(|dependency| {
+                let status = statuses[dependency];
+                (status != ServiceStatus::Enabled).then(|| NodeOutcome::Held {
+                    prerequisite: dependency.clone(),
+                    status,
+                })
+            });
+            let outcome = held.unwrap_or_else(|| match service.ensure_registered() {
+                Ok(report) if report.changed => NodeOutcome::Registered,
+                Ok(_) => NodeOutcome::Unchanged,
+                Err(error) => NodeOutcome::Failed(error),
+            });
+            statuses.insert(&node.service_type, service.status());
+            outcomes.push((node.service_type.clone(), outcome));
+        }
+        GraphReport { outcomes }
+    }
+
+    /// Unregisters every service in reverse dependency order, continuing past failures.
+    pub fn unregister(&self) -> GraphReport {
+        let outcomes = self
+            .nodes
+            .iter()
+            .rev()
+            .map(|node| {
+                let outcome = match self.service(&node.service_type).ensure_unregistered() {
+                    Ok(report) if report.changed => NodeOutcome::Unregistered,
+                    Ok(_) => NodeOutcome::Unchanged,
+                    Err(error) => NodeOutcome::Failed(error),
+                };
+                (node.service_type.clone(), outcome)
+            })
+            .collect();
+        GraphReport { outcomes }
+    }
+
+    fn service(&self, service_type: &OwnedServiceType) -> AppService {
+        AppService::with_backend(service_type.as_service_type(), self.backend.clone())
+    }
+}
+
+/// Sorts `nodes` so that each comes after its dependencies, keeping the declaration order where
+/// the dependencies allow it.
+fn sort(nodes: Vec<ServiceNode>) -> Result<Vec<ServiceNode>, GraphError> {
+    let mut index = HashMap::new();
+    for (i, node) in nodes.iter().enumerate() {
+        if index.insert(&node.service_type, i).is_some() {
+            return Err(GraphError::Duplicate(node.service_type.clone()));
+        }
+    }
+    let mut dependencies = Vec::with_capacity(nodes.len());
+    for node in &nodes {
+        let mut indices = Vec::with_capacity(node.depends_on.len());
+        for dependency in &node.depends_on {
+            let Some(&i) = index.get(dependency) else {
+                return Err(GraphError::UnknownDependency {
+                    service: node.service_type.clone(),
+                    dependency: dependency.clone(),
+                });
+            };
+            indices.push(i);
+        }
+        dependencies.push(indices);
+    }
+
+    // Depth-first, visiting dependencies before the node itself.
+    #[derive(Clone, Copy, PartialEq)]
+    enum Mark {
+        New,
+        Visiting,
+        Done,
+    }
+    fn visit(
+        i: usize,
+        dependencies: &[Vec<usize>],
+        marks: &mut [Mark],
+        path: &mut Vec<usize>,
+        order: &mut Vec<usize>,
+    ) -> Result<(), Vec<usize>> {
+        match marks[i] {
+            Mark::Done => return Ok(()),
+            Mark::Visiting => {
+                let start = path.iter().position(|&p| p == i).unwrap_or(0);
+                let mut cycle = path[start..].to_vec();
+                cycle.push(i);
+                return Err(cycle);
+            }
+            Mark::New => {}
+        }
+        marks[i] = Mark::Visiting;
+        path.push(i);
+        for &dependency in &dependencies[i] {
+            visit(dependency, dependencies, marks, path, order)?;
+        }
+        path.pop();
+        marks[i] = Mark::Done;
+        order.push(i);
+        Ok(())
+    }
+
+    let mut marks = vec![Mark::New; nodes.len()];
+    let mut order = Vec::with_capacity(nodes.len());
+    for i in 0..nodes.len() {
+        visit(i, &dependencies, &mut marks, &mut Vec::new(), &mut order).map_err(|cycle| {
+            GraphError::Cycle(
+                cycle
+                    .into_iter()
+                    .map(|i| nodes[i].service_type.clone())
+                    .collect(),
+            )
+        })?;
+    }
+
+    let mut nodes: Vec<Option<ServiceNode>> = nodes.into_iter().map(Some).collect();
+    Ok(order.into_iter().filter_map(|i| nodes[i].take()).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FaultInjectingBackend, Operation, ServiceType, SimulatedBackend};
+
+    const DAEMON: ServiceType<'static> = ServiceType::Daemon {
+        plist_name: "com.example.daemon.plist",
+    };
+    const AGENT: ServiceType<'static> = ServiceType::Agent {
+        plist_name: "com.example.agent.plist",
+    };
+    const HELPER: ServiceType<'static> = ServiceType::LoginItem {
+        identifier: "com.example.helper",
+    };
+    const NONE: [ServiceType<'static>; 0] = [];
+
+    fn owned(service_types: &[ServiceType]) -> Vec<OwnedServiceType> {
+        service_types.iter().map(OwnedServiceType::from).collect()
+    }
+
+    #[test]
+    fn test_build() {
+        let graph = ServiceGraph::with_backend(
+            [
+                ServiceNode::new(HELPER, [AGENT]),
+                ServiceNode::new(ServiceType::MainApp, NONE),
+                ServiceNode::new(AGENT, [DAEMON]),
+                ServiceNode::new(DAEMON, NONE),
+            ],
+            SimulatedBackend::new(),
+        )
+        .unwrap();
+        assert_eq!(
+            graph.order().cloned().collect::<Vec<_>>(),
+            owned(&[DAEMON, AGENT, HELPER, ServiceType::MainApp])
+        );
+
+        let error = ServiceGraph::new([
+            ServiceNode::new(DAEMON, [HELPER]),
+            ServiceNode::new(AGENT, [DAEMON]),
+            ServiceNode::new(HELPER, [AGENT]),
+        ])
+        .unwrap_err();
+        assert_eq!(
+            error,
+            GraphError::Cycle(owned(&[DAEMON, HELPER, AGENT, DAEMON]))
+        );
+        assert_eq!(
+            error.to_string(),
+            "dependency cycle: daemon com.example.daemon.plist -> login item com.example.helper \
+             -> agent com.example.agent.plist -> daemon com.example.daemon.plist"
+        );
+
+        assert_eq!(
+            ServiceGraph::new([ServiceNode::new(AGENT, [DAEMON])]).unwrap_err(),
+            GraphError::UnknownDependency {
+                service: AGENT.into(),
+                dependency: DAEMON.into(),
+            }
+        );
+        assert_eq!(
+            ServiceGraph::new([ServiceNode::new(AGENT, NONE), ServiceNode::new(AGENT, NONE)])
+                .unwrap_err(),
+            GraphError::Duplicate(AGENT.into())
+        );
+    }
+
+    #[test]
+    fn test_register_in_order() {
+        let backend = FaultInjectingBackend::new(SimulatedBackend::new());
+        let graph = ServiceGraph::with_backend(
+            [
+                ServiceNode::new(DAEMON, NONE),
+                ServiceNode::new(AGENT, [DAEMON]),
+                ServiceNode::new(HELPER, [AGENT]),
+                ServiceNode::new(ServiceType::MainApp, NONE),
+            ],
+            backend.clone(),
+        )
+        .unwrap();
+
+        let report = graph.register();
+        assert!(!report.is_complete());
+        assert_eq!(
+            report.outcomes[1].1,
+            NodeOutcome::Held {
+                prerequisite: DAEMON.into(),
+                status: ServiceStatus::RequiresApproval,
+            }
+        );
+        assert_eq!(
+            report.outcomes[2].1,
+            NodeOutcome::Held {
+                prerequisite: AGENT.into(),
+                status: ServiceStatus::NotRegistered,
+            }
+        );
+        assert_eq!(report.outcomes[3].1, NodeOutcome::Registered);
+        assert_eq!(backend.call_count(&AGENT, Operation::Register), 0);
+
+        backend.inner().approve(&DAEMON);
+        let report = graph.register();
+        assert!(report.is_complete());
+        assert_eq!(
+            report.to_string(),
+            "daemon com.example.daemon.plist: unchanged\n\
+             agent com.example.agent.plist: registered\n\
+             login item com.example.helper: registered\n\
+             main app: unchanged\n"
+        );
+
+        let report = graph.unregister();
+        assert!(report.is_complete());
+        let calls: Vec<_> = backend
+            .calls()
+            .into_iter()
+            .filter(|call| call.operation == Operation::Unregister)
+            .filter_map(|call| call.service_type)
+            .collect();
+        assert_eq!(calls, owned(&[ServiceType::MainApp, HELPER, AGENT, DAEMON]));
+    }
+}
@@ -0,0 +1,367 @@
+//! Registering several services as a unit.
+
+use std::fmt;
+use std::sync::Arc;
+
+use thiserror::Error;
+
+use crate::{
+    AppService, DefaultBackend, OwnedServiceType, ServiceBackend, ServiceManagementError,
+    ServiceStatus,
+};
+
+/// What happened to one service of a [`ServiceGroup`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemberOutcome {
+    /// The service was registered by this call.
+    Registered,
+
+    /// The service was unregistered by this call.
+    Unregistered,
+
+    /// The service was already in the requested state, so it was left alone.
+    Unchanged,
+
+    /// The call failed for this service.
+    Failed(ServiceManagementError),
+
+    /// The service was registered, then unregistered again after a later service failed.
+    RolledBack,
+
+    /// The service was registered, but unregistering it after a later service failed also failed.
+    /// It is still registered.
+    RollbackFailed(ServiceManagementError),
+
+    /// The service wasn't attempted because an earlier service failed.
+    Skipped,
+}
+
+impl fmt::Display for MemberOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MemberOutcome::Registered => write!(f, "registered"),
+            MemberOutcome::Unregistered => write!(f, "unregistered"),
+            MemberOutcome::Unchanged => write!(f, "unchanged"),
+            MemberOutcome::Failed(error) => write!(f, "failed: {}", error),
+            MemberOutcome::RolledBack => write!(f, "rolled back"),
+            MemberOutcome::RollbackFailed(error) => write!(f, "rollback failed: {}", error),
+            MemberOutcome::Skipped => write!(f, "skipped"),
+        }
+    }
+}
+
+/// A service of a [`ServiceGroup`] and what happened to it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Member {
+    /// The service.
+    pub service_type: OwnedServiceType,
+
+    /// What happened to it.
+    pub outcome: MemberOutcome,
+}
+
+/// A [`ServiceGroup`] operation that failed for at least one service.
+///
+/// Lists the outcome of every service in the group, in group order, so callers can see what was
+/// rolled back and whether anything is left registered.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("{}", describe(.members))]
+pub struct GroupError {
+    /// Every service of the group with its outcome.
+    pub members: Vec<Member>,
+}
+
+impl GroupError {
+    /// Returns the first service that failed and its error.
+    pub fn first_failure(&self) -> Option<(&OwnedServiceType, ServiceManagementError)> {
+        self.members.iter().find_map(|member| match member.outcome {
+            MemberOutcome::Failed(error) => Some((&member.service_type, error)),
+            _ => None,
+        })
+    }
+
+    /// Returns whether a failed rollback left services registered.
+    pub fn is_partial(&self) -> bool {
+        self.members
+            .iter()
+            .any(|member| matches!(member.outcome, MemberOutcome::RollbackFailed(_)))
+    }
+}
+
+fn describe(members: &[Member]) -> String {
+    let outcomes: Vec<String> = members
+        .iter()
+        .map(|member| format!("{} {}", member.service_type, member.outcome))
+        .collect();
+    format!("service group operation failed ({})", outcomes.join(", "))
+}
+
+/// A set of services registered and unregistered together, for example an agent, a login item
+/// and a daemon that only work as a whole.
+///
+/// [`register`](#method.register) registers the services in order. If one fails, the services
+/// registered by that call are unregistered again in reverse order, so a bad signature on the
+/// last service doesn't leave the product half-installed. Services that were already registered
+/// beforehand are left alone.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use smappservice_rs::{ServiceGroup, ServiceStatus, ServiceType};
+///
+/// let group = ServiceGroup::new([
+///     ServiceType::Agent { plist_name: "com.example.myapp.agent.plist" },
+///     ServiceType::LoginItem { identifier: "com.example.myapp.helper" },
+///     ServiceType::Daemon { plist_name: "com.example.myapp.daemon.plist" },
+/// ]);
+/// match group.register() {
+///     Ok(_) if group.status() == ServiceStatus::RequiresApproval => {
+///         println!("Approve MyApp in System Settings");
+///     }
+///     Ok(_) => println!("Installed"),
+///     Err(e) => eprintln!("{}", e),
+/// }
+/// ```
+pub struct ServiceGroup {
+    services: Vec<OwnedServiceType>,
+    backend: Arc<dyn ServiceBackend>,
+}
+
+impl fmt::Debug for ServiceGroup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ServiceGroup")
+            .field("services", &self.services)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ServiceGroup {
+    /// Creates a group of `services` that uses the platform's [`DefaultBackend`].
+    pub fn new<S: Into<OwnedServiceType>>(services: impl IntoIterator<Item = S>) -> Self {
+        Self::with_backend(services, DefaultBackend::default())
+    }
+
+    /// Creates a group of `services` that goes through `backend`.
+    pub fn with_backend<S: Into<OwnedServiceType>>(
+        services: impl IntoIterator<Item = S>,
+        backend: impl ServiceBackend + 'static,
+    ) -> Self {
+        Self {
+            services: services.into_iter().map(Into::into).collect(),
+            backend: Arc::new(backend),
+        }
+    }
+
+    /// Returns the services of the group, in registration order.
+    pub fn services(&self) -> &[OwnedServiceType] {
+        &self.services
+    }
+
+    /// Registers every service, rolling back the ones it registered if any fails.
+    ///
+    /// Services that are already registered count as success and are never rolled back.
+    pub fn register(&self) -> Result<Vec<Member>, GroupError> {
+        let mut outcomes = Vec::with_capacity(self.services.len());
+        for service_type in &self.services {
+            match self.service(service_type).ensure_registered() {
+                Ok(report) if report.changed => outcomes.push(MemberOutcome::Registered),
+                Ok(_) => outcomes.push(MemberOutcome::Unchanged),
+                Err(error) => {
+                    outcomes.push(MemberOutcome::Failed(error));
+                    break;
+                }
+            }
+        }
+
+        if let Some(MemberOutcome::Failed(_)) = outcomes.last() {
+            for (service_type, outcome) in self.services.iter().zip(outcomes.iter_mut()).rev() {
+                if *outcome == MemberOutcome::Registered {
+                    *outcome = match self.service(service_type).ensure_unregistered() {
+                        Ok(_) => MemberOutcome::RolledBack,
+                        Err(error) => MemberOutcome::RollbackFailed(error),
+                    };
+                }
+            }
+            outcomes.resize(self.services.len(), MemberOutcome::Skipped);
+            return Err(GroupError {
+                members: self.members(outcomes),
+            });
+        }
+        Ok(self.members(outcomes))
+    }
+
+    /// Unregisters every service, in reverse order.
+    ///
+    /// Keeps going past failures, so as much as possible is removed, and fails if any service
+    /// couldn't be unregistered. Services that aren't registered count as success.
+    pub fn unregister(&self) -> Result<Vec<Member>, GroupError> {
+        let mut outcomes: Vec<MemberOutcome> = self
+            .services
+            .iter()
+            .rev()
+            .map(
+                |service_type| match self.service(service_type).ensure_unregistered() {
+                    Ok(report) if report.changed => MemberOutcome::Unregistered,
+                    Ok(_) => MemberOutcome::Unchanged,
+                    Err(error) => MemberOutcome::Failed(error),
+                },
+            )
+            .collect();
+        outcomes.reverse();
+
+        let failed = outcomes
+            .iter()
+            .any(|outcome| matches!(outcome, MemberOutcome::Failed(_)));
+        let members = self.members(outcomes);
+        if failed {
+            Err(GroupError { members })
+        } else {
+            Ok(members)
+        }
+    }
+
+    /// Returns the status of every service, in group order.
+    pub fn statuses(&self) -> Vec<(OwnedServiceType, ServiceStatus)> {
+        self.services
+            .iter()
+            .map(|service_type| {
+                let status = self.backend.status(&service_type.as_service_type());
+                (service_type.clone(), status)
+            })
+            .collect()
+    }
+
+    /// Returns the status of the group as a whole: the least ready status of its services.
+    ///
+    /// From least to most ready: [`ServiceStatus::NotFound`], [`ServiceStatus::NotRegistered`],
+    /// [`ServiceStatus::RequiresApproval`], [`ServiceStatus::Enabled`]. So the group is only
+    /// `Enabled` when every service is. An empty group is `Enabled`.
+    pub fn status(&self) -> ServiceStatus {
+        self.statuses()
+            .into_iter()
+            .map(|(_, status)| status)
+            .min_by_key(|status| readiness(*status))
+            .unwrap_or(ServiceStatus::Enabled)
+    }
+
+    fn service(&self, service_type: &OwnedServiceType) -> AppService {
+        AppService::with_backend(service_type.as_service_type(), self.backend.clone())
+    }
+
+    fn members(&self, outcomes: Vec<MemberOutcome>) -> Vec<Member> {
+        self.services
+            .iter()
+            .cloned()
+            .zip(outcomes)
+            .map(|(service_type, outcome)| Member {
+                service_type,
+                outcome,
+            })
+            .collect()
+    }
+}
+
+fn readiness(status: ServiceStatus) -> u8 {
+    match status {
+        ServiceStatus::NotFound => 0,
+        ServiceStatus::NotRegistered => 1,
+        ServiceStatus::RequiresApproval => 2,
+        ServiceStatus::Enabled => 3,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FaultInjectingBackend, Operation, ServiceType, SimulatedBackend};
+
+    const AGENT: ServiceType<'static> = ServiceType::Agent {
+        plist_name: "com.example.agent.plist",
+    };
+    const HELPER: ServiceType<'static> = ServiceType::LoginItem {
+        identifier: "com.example.helper",
+    };
+    const DAEMON: ServiceType<'static> = ServiceType::Daemon {
+        plist_name: "com.example.daemon.plist",
+    };
+
+    fn outcomes(members: &[Member]) -> Vec<MemberOutcome> {
+        members.iter().map(|member| member.outcome).collect()
+    }
+
+    #[test]
+    fn test_register_and_unregister() {
+        let backend = SimulatedBackend::new();
+        backend.set_status(&HELPER, ServiceStatus::Enabled);
+        let group = ServiceGroup::with_backend([AGENT, HELPER, DAEMON], backend.clone());
+        assert_eq!(group.status(), ServiceStatus::NotRegistered);
+
+        let members = group.register().unwrap();
+        assert_eq!(
+            outcomes(&members),
+            [
+                MemberOutcome::Registered,
+                MemberOutcome::Unchanged,
+                MemberOutcome::Registered
+            ]
+        );
+        assert_eq!(group.status(), ServiceStatus::RequiresApproval);
+        backend.approve(&DAEMON);
+        assert_eq!(group.status(), ServiceStatus::Enabled);
+
+        let members = group.unregister().unwrap();
+        assert!(
+            outcomes(&members)
+                .iter()
+                .all(|outcome| *outcome == MemberOutcome::Unregistered)
+        );
+        assert_eq!(group.status(), ServiceStatus::NotRegistered);
+    }
+
+    #[test]
+    fn test_rollback() {
+        let backend = FaultInjectingBackend::new(SimulatedBackend::new());
+        backend.inner().set_status(&AGENT, ServiceStatus::Enabled);
+        backend.fail_next(
+            &DAEMON,
+            Operation::Register,
+            ServiceManagementError::InvalidSignature,
+        );
+        let extra = ServiceType::Agent {
+            plist_name: "com.example.extra.plist",
+        };
+        let group = ServiceGroup::with_backend([AGENT, HELPER, DAEMON, extra], backend.clone());
+
+        let error = group.register().unwrap_err();
+        assert_eq!(
+            outcomes(&error.members),
+            [
+                MemberOutcome::Unchanged,
+                MemberOutcome::RolledBack,
+                MemberOutcome::Failed(ServiceManagementError::InvalidSignature),
+                MemberOutcome::Skipped,
+            ]
+        );
+        assert_eq!(
+            error.first_failure(),
+            Some((
+                &OwnedServiceType::from(DAEMON),
+                ServiceManagementError::InvalidSignature
+            ))
+        );
+        assert!(!error.is_partial());
+        assert!(
+            error
+                .to_string()
+                .contains("login item com.example.helper rolled back")
+        );
+
+        // The agent was registered beforehand, so it stays.
+        assert_eq!(backend.inner().status(&AGENT), ServiceStatus::Enabled);
+        assert_eq!(
+            backend.inner().status(&HELPER),
+            ServiceStatus::NotRegistered
+        );
+        assert_eq!(backend.call_count(&extra, Operation::Register), 0);
+    }
+}
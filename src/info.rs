@@ -0,0 +1,282 @@
+//! A typed model of bundle `Info.plist` files.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::launchd::{invalid_type, take_dictionary, take_string, take_string_array};
+use crate::plist::{Dictionary, PlistError, Value};
+
+/// The information property list of an app bundle, read from `Contents/Info.plist`.
+///
+/// The same type describes the main app and the helper apps in `Contents/Library/LoginItems`,
+/// whose `CFBundleIdentifier` is what [`ServiceType::LoginItem`](crate::ServiceType::LoginItem)
+/// refers to. Keys relevant to service registration are exposed as typed fields and every other
+/// key is kept in [`other`](#structfield.other).
+///
+/// # Examples
+///
+/// ```rust
+/// use smappservice_rs::InfoPlist;
+///
+/// let info = InfoPlist::from_xml(r#"<plist version="1.0"><dict>
+///     <key>CFBundleIdentifier</key><string>com.example.helper</string>
+///     <key>LSUIElement</key><string>1</string>
+/// </dict></plist>"#).unwrap();
+///
+/// assert_eq!(info.bundle_identifier.as_deref(), Some("com.example.helper"));
+/// assert_eq!(info.ui_element, Some(true));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct InfoPlist {
+    /// `CFBundleIdentifier`: the bundle's unique identifier.
+    pub bundle_identifier: Option<String>,
+
+    /// `CFBundleVersion`: the build version.
+    pub bundle_version: Option<String>,
+
+    /// `CFBundleShortVersionString`: the user-visible release version.
+    pub short_version_string: Option<String>,
+
+    /// `CFBundleExecutable`: the name of the executable in `Contents/MacOS`.
+    pub executable: Option<String>,
+
+    /// `CFBundleDisplayName`: the user-visible name of the bundle.
+    pub display_name: Option<String>,
+
+    /// `LSUIElement`: whether the app runs without a Dock icon or menu bar.
+    pub ui_element: Option<bool>,
+
+    /// `LSBackgroundOnly`: whether the app runs without any user interface.
+    pub background_only: Option<bool>,
+
+    /// `LSMinimumSystemVersion`: the oldest macOS version the app runs on.
+    pub minimum_system_version: Option<String>,
+
+    /// `SMPrivilegedExecutables`: the code requirement of each privileged helper, keyed by label.
+    pub privileged_executables: Option<BTreeMap<String, String>>,
+
+    /// `SMAuthorizedClients`: the code requirements of the apps allowed to install this helper.
+    pub authorized_clients: Option<Vec<String>>,
+
+    /// Every key not covered by the fields above.
+    pub other: Dictionary,
+}
+
+/// Reads a Launch Services flag, which is a boolean in modern bundles but is often written as the
+/// string `"1"` or `"YES"`, or as an integer.
+fn take_flag(dict: &mut Dictionary, key: &str) -> Result<Option<bool>, PlistError> {
+    match dict.remove(key) {
+        None => Ok(None),
+        Some(Value::Boolean(value)) => Ok(Some(value)),
+        Some(Value::Integer(value)) => Ok(Some(value != 0)),
+        Some(Value::String(value)) => match value.to_ascii_lowercase().as_str() {
+            "1" | "yes" | "true" => Ok(Some(true)),
+            "0" | "no" | "false" | "" => Ok(Some(false)),
+            _ => Err(invalid_type(key, "a boolean", &Value::String(value))),
+        },
+        Some(other) => Err(invalid_type(key, "a boolean", &other)),
+    }
+}
+
+impl InfoPlist {
+    /// Builds the model from a parsed property list.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`PlistError::InvalidType`] if the root isn't a dictionary or a known key has the
+    /// wrong type.
+    pub fn from_value(value: Value) -> Result<Self, PlistError> {
+        let mut dict = match value {
+            Value::Dictionary(dict) => dict,
+            other => return Err(invalid_type("", "a dictionary", &other)),
+        };
+        let privileged_executables = match take_dictionary(&mut dict, "SMPrivilegedExecutables")? {
+            None => None,
+            Some(executables) => Some(
+                executables
+                    .into_iter()
+                    .map(|(label, requirement)| match requirement {
+                        Value::String(requirement) => Ok((label, requirement)),
+                        other => Err(invalid_type(
+                            &format!("SMPrivilegedExecutables.{label}"),
+                            "a string",
+                            &other,
+                        )),
+                    })
+                    .collect::<Result<_, _>>()?,
+            ),
+        };
+
+        Ok(Self {
+            bundle_identifier: take_string(&mut dict, "CFBundleIdentifier")?,
+            bundle_version: take_string(&mut dict, "CFBundleVersion")?,
+            short_version_string: take_string(&mut dict, "CFBundleShortVersionString")?,
+            executable: take_string(&mut dict, "CFBundleExecutable")?,
+            display_name: take_string(&mut dict, "CFBundleDisplayName")?,
+            ui_element: take_flag(&mut dict, "LSUIElement")?,
+            background_only: take_flag(&mut dict, "LSBackgroundOnly")?,
+            minimum_system_version: take_string(&mut dict, "LSMinimumSystemVersion")?,
+            privileged_executables,
+            authorized_clients: take_string_array(&mut dict, "SMAuthorizedClients")?,
+            other: dict,
+        })
+    }
+
+    /// Converts the model into a property list dictionary.
+    pub fn to_value(&self) -> Value {
+        let mut dict = self.other.clone();
+        let mut set = |key: &str, value: Option<Value>| {
+            if let Some(value) = value {
+                dict.insert(key.to_string(), value);
+            }
+        };
+        set(
+            "CFBundleIdentifier",
+            self.bundle_identifier.as_deref().map(Value::from),
+        );
+        set(
+            "CFBundleVersion",
+            self.bundle_version.as_deref().map(Value::from),
+        );
+        set(
+            "CFBundleShortVersionString",
+            self.short_version_string.as_deref().map(Value::from),
+        );
+        set(
+            "CFBundleExecutable",
+            self.executable.as_deref().map(Value::from),
+        );
+        set(
+            "CFBundleDisplayName",
+            self.display_name.as_deref().map(Value::from),
+        );
+        set("LSUIElement", self.ui_element.map(Value::Boolean));
+        set("LSBackgroundOnly", self.background_only.map(Value::Boolean));
+        set(
+            "LSMinimumSystemVersion",
+            self.minimum_system_version.as_deref().map(Value::from),
+        );
+        set(
+            "SMPrivilegedExecutables",
+            self.privileged_executables.as_ref().map(|executables| {
+                Value::Dictionary(
+                    executables
+                        .iter()
+                        .map(|(label, requirement)| {
+                            (label.clone(), Value::from(requirement.as_str()))
+                        })
+                        .collect(),
+                )
+            }),
+        );
+        set(
+            "SMAuthorizedClients",
+            self.authorized_clients.as_ref().map(|clients| {
+                Value::Array(
+                    clients
+                        .iter()
+                        .map(|client| Value::from(client.as_str()))
+                        .collect(),
+                )
+            }),
+        );
+        Value::Dictionary(dict)
+    }
+
+    /// Parses the model from an XML property list.
+    pub fn from_xml(xml: &str) -> Result<Self, PlistError> {
+        Self::from_value(Value::from_xml(xml)?)
+    }
+
+    /// Serializes the model as an XML property list.
+    pub fn to_xml(&self) -> String {
+        self.to_value().to_xml()
+    }
+
+    /// Reads the model from the XML or binary property list at `path`.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, PlistError> {
+        Self::from_value(Value::from_file(path)?)
+    }
+
+    /// Reads `Contents/Info.plist` of the bundle at `bundle`, which may be the main app or a
+    /// helper in its `Contents/Library/LoginItems` directory.
+    pub fn for_bundle(bundle: impl AsRef<Path>) -> Result<Self, PlistError> {
+        Self::from_file(bundle.as_ref().join("Contents").join("Info.plist"))
+    }
+
+    /// Writes the model as an XML property list to `path`.
+    pub fn write_to_file(&self, path: impl AsRef<Path>) -> Result<(), PlistError> {
+        self.to_value().write_to_file(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<plist version="1.0">
+<dict>
+    <key>CFBundleIdentifier</key>
+    <string>com.example.app</string>
+    <key>CFBundleVersion</key>
+    <string>42</string>
+    <key>CFBundleShortVersionString</key>
+    <string>1.2.0</string>
+    <key>CFBundleExecutable</key>
+    <string>Example</string>
+    <key>LSBackgroundOnly</key>
+    <true/>
+    <key>LSMinimumSystemVersion</key>
+    <string>13.0</string>
+    <key>SMPrivilegedExecutables</key>
+    <dict>
+        <key>com.example.tool</key>
+        <string>identifier "com.example.tool" and anchor apple generic</string>
+    </dict>
+    <key>SMAuthorizedClients</key>
+    <array>
+        <string>identifier "com.example.app" and anchor apple generic</string>
+    </array>
+    <key>NSHumanReadableCopyright</key>
+    <string>Example</string>
+</dict>
+</plist>"#;
+        let info = InfoPlist::from_xml(xml).unwrap();
+        assert_eq!(info.bundle_version.as_deref(), Some("42"));
+        assert_eq!(info.background_only, Some(true));
+        assert_eq!(info.ui_element, None);
+        assert_eq!(
+            info.privileged_executables.as_ref().unwrap()["com.example.tool"],
+            "identifier \"com.example.tool\" and anchor apple generic"
+        );
+        assert_eq!(info.authorized_clients.as_ref().map(Vec::len), Some(1));
+        assert!(info.other.contains_key("NSHumanReadableCopyright"));
+
+        assert_eq!(InfoPlist::from_xml(&info.to_xml()).unwrap(), info);
+    }
+
+    #[test]
+    fn test_flags_and_invalid_types() {
+        let flag = |value: &str| {
+            InfoPlist::from_xml(&format!(
+                "<plist><dict><key>LSUIElement</key>{value}</dict></plist>"
+            ))
+            .map(|info| info.ui_element)
+        };
+        assert_eq!(flag("<string>YES</string>").unwrap(), Some(true));
+        assert_eq!(flag("<integer>0</integer>").unwrap(), Some(false));
+        assert!(matches!(
+            flag("<string>maybe</string>"),
+            Err(PlistError::InvalidType { key, .. }) if key == "LSUIElement"
+        ));
+        assert!(matches!(
+            InfoPlist::from_xml(
+                "<plist><dict><key>SMAuthorizedClients</key><string>a</string></dict></plist>"
+            ),
+            Err(PlistError::InvalidType { key, .. }) if key == "SMAuthorizedClients"
+        ));
+    }
+}
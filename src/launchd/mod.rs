@@ -0,0 +1,434 @@
+//! A typed model of launchd job property lists.
+
+mod validate;
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::plist::{Dictionary, PlistError, Value};
+
+pub use validate::{Diagnostic, Severity, ValidationReport};
+
+/// Whether and when launchd keeps a job running, as set by the `KeepAlive` key.
+#[derive(Debug, Clone, PartialEq)]
+pub enum KeepAlive {
+    /// `true` keeps the job running unconditionally, `false` lets it run on demand.
+    Always(bool),
+
+    /// A dictionary of conditions such as `SuccessfulExit`, `Crashed` or `PathState`.
+    Conditions(Dictionary),
+}
+
+/// A launchd job definition, as stored in a bundle's `Contents/Library/LaunchAgents` or
+/// `Contents/Library/LaunchDaemons` directory and referenced by
+/// [`ServiceType::Agent`](crate::ServiceType::Agent) and
+/// [`ServiceType::Daemon`](crate::ServiceType::Daemon).
+///
+/// The common keys are exposed as typed fields. Any other key is kept in [`other`](#structfield.other)
+/// so a plist survives a parse and serialize round trip unchanged.
+///
+/// # Examples
+///
+/// ```rust
+/// use smappservice_rs::LaunchdPlist;
+///
+/// let plist = LaunchdPlist {
+///     bundle_program: Some("Contents/MacOS/agent".to_string()),
+///     run_at_load: Some(true),
+///     associated_bundle_identifiers: Some(vec!["com.example.myapp".to_string()]),
+///     ..LaunchdPlist::new("com.example.myapp.agent")
+/// };
+///
+/// let xml = plist.to_xml();
+/// assert_eq!(LaunchdPlist::from_xml(&xml).unwrap(), plist);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LaunchdPlist {
+    /// `Label`: the unique name of the job.
+    pub label: String,
+
+    /// `Program`: the absolute path of the executable to run.
+    pub program: Option<String>,
+
+    /// `ProgramArguments`: the executable followed by its arguments.
+    pub program_arguments: Option<Vec<String>>,
+
+    /// `BundleProgram`: the executable path relative to the app bundle.
+    pub bundle_program: Option<String>,
+
+    /// `RunAtLoad`: whether the job is started as soon as it's loaded.
+    pub run_at_load: Option<bool>,
+
+    /// `KeepAlive`: whether and when the job is kept running.
+    pub keep_alive: Option<KeepAlive>,
+
+    /// `EnvironmentVariables`: extra environment variables for the job.
+    pub environment_variables: Option<BTreeMap<String, String>>,
+
+    /// `WorkingDirectory`: the directory the job is started in.
+    pub working_directory: Option<String>,
+
+    /// `StandardOutPath`: the file standard output is written to.
+    pub standard_out_path: Option<String>,
+
+    /// `StandardErrorPath`: the file standard error is written to.
+    pub standard_error_path: Option<String>,
+
+    /// `AssociatedBundleIdentifiers`: the apps the job is attributed to in System Settings.
+    pub associated_bundle_identifiers: Option<Vec<String>>,
+
+    /// `MachServices`: the Mach services the job advertises.
+    pub mach_services: Option<Dictionary>,
+
+    /// `StartInterval`: restart the job every given number of seconds.
+    pub start_interval: Option<i64>,
+
+    /// `ThrottleInterval`: the minimum number of seconds between two launches.
+    pub throttle_interval: Option<i64>,
+
+    /// `ProcessType`: the resource limits applied to the job, e.g. `Background` or `Interactive`.
+    pub process_type: Option<String>,
+
+    /// `UserName`: the user a daemon runs as.
+    pub user_name: Option<String>,
+
+    /// `GroupName`: the group a daemon runs as.
+    pub group_name: Option<String>,
+
+    /// `Disabled`: whether the job is disabled by default.
+    pub disabled: Option<bool>,
+
+    /// Every key not covered by the fields above.
+    pub other: Dictionary,
+}
+
+pub(crate) fn invalid_type(key: &str, expected: &'static str, found: &Value) -> PlistError {
+    PlistError::InvalidType {
+        key: key.to_string(),
+        expected,
+        found: found.type_name(),
+    }
+}
+
+pub(crate) fn take_string(dict: &mut Dictionary, key: &str) -> Result<Option<String>, PlistError> {
+    match dict.remove(key) {
+        None => Ok(None),
+        Some(Value::String(value)) => Ok(Some(value)),
+        Some(other) => Err(invalid_type(key, "a string", &other)),
+    }
+}
+
+pub(crate) fn take_bool(dict: &mut Dictionary, key: &str) -> Result<Option<bool>, PlistError> {
+    match dict.remove(key) {
+        None => Ok(None),
+        Some(Value::Boolean(value)) => Ok(Some(value)),
+        Some(other) => Err(invalid_type(key, "a boolean", &other)),
+    }
+}
+
+pub(crate) fn take_integer(dict: &mut Dictionary, key: &str) -> Result<Option<i64>, PlistError> {
+    match dict.remove(key) {
+        None => Ok(None),
+        Some(Value::Integer(value)) => Ok(Some(value)),
+        Some(other) => Err(invalid_type(key, "an integer", &other)),
+    }
+}
+
+pub(crate) fn take_dictionary(
+    dict: &mut Dictionary,
+    key: &str,
+) -> Result<Option<Dictionary>, PlistError> {
+    match dict.remove(key) {
+        None => Ok(None),
+        Some(Value::Dictionary(value)) => Ok(Some(value)),
+        Some(other) => Err(invalid_type(key, "a dictionary", &other)),
+    }
+}
+
+pub(crate) fn string_array(key: &str, values: Vec<Value>) -> Result<Vec<String>, PlistError> {
+    values
+        .into_iter()
+        .enumerate()
+        .map(|(index, value)| match value {
+            Value::String(value) => Ok(value),
+            other => Err(invalid_type(&format!("{key}.{index}"), "a string", &other)),
+        })
+        .collect()
+}
+
+pub(crate) fn take_string_array(
+    dict: &mut Dictionary,
+    key: &str,
+) -> Result<Option<Vec<String>>, PlistError> {
+    match dict.remove(key) {
+        None => Ok(None),
+        Some(Value::Array(values)) => string_array(key, values).map(Some),
+        Some(other) => Err(invalid_type(key, "an array of strings", &other)),
+    }
+}
+
+impl LaunchdPlist {
+    /// Creates a job definition with the given label and no other keys.
+    pub fn new(label: impl Into<String>) -> Self {
+        Self {
+            label: label.into(),
+            ..Self::default()
+        }
+    }
+
+    /// Builds a job definition from a parsed property list.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`PlistError::MissingKey`] if `Label` is absent and [`PlistError::InvalidType`]
+    /// if the root isn't a dictionary or a known key has the wrong type.
+    pub fn from_value(value: Value) -> Result<Self, PlistError> {
+        let mut dict = match value {
+            Value::Dictionary(dict) => dict,
+            other => return Err(invalid_type("", "a dictionary", &other)),
+        };
+        let label = take_string(&mut dict, "Label")?
+            .ok_or_else(|| PlistError::MissingKey("Label".to_string()))?;
+        let keep_alive = match dict.remove("KeepAlive") {
+            None => None,
+            Some(Value::Boolean(value)) => Some(KeepAlive::Always(value)),
+            Some(Value::Dictionary(conditions)) => Some(KeepAlive::Conditions(conditions)),
+            Some(other) => {
+                return Err(invalid_type(
+                    "KeepAlive",
+                    "a boolean or a dictionary",
+                    &other,
+                ));
+            }
+        };
+        let environment_variables = match take_dictionary(&mut dict, "EnvironmentVariables")? {
+            None => None,
+            Some(variables) => Some(
+                variables
+                    .into_iter()
+                    .map(|(name, value)| match value {
+                        Value::String(value) => Ok((name, value)),
+                        other => Err(invalid_type(
+                            &format!("EnvironmentVariables.{name}"),
+                            "a string",
+                            &other,
+                        )),
+                    })
+                    .collect::<Result<_, _>>()?,
+            ),
+        };
+        // launchd also accepts a single string here.
+        let associated_bundle_identifiers = match dict.remove("AssociatedBundleIdentifiers") {
+            None => None,
+            Some(Value::String(identifier)) => Some(vec![identifier]),
+            Some(Value::Array(values)) => {
+                Some(string_array("AssociatedBundleIdentifiers", values)?)
+            }
+            Some(other) => {
+                return Err(invalid_type(
+                    "AssociatedBundleIdentifiers",
+                    "a string or an array of strings",
+                    &other,
+                ));
+            }
+        };
+
+        Ok(Self {
+            label,
+            program: take_string(&mut dict, "Program")?,
+            program_arguments: take_string_array(&mut dict, "ProgramArguments")?,
+            bundle_program: take_string(&mut dict, "BundleProgram")?,
+            run_at_load: take_bool(&mut dict, "RunAtLoad")?,
+            keep_alive,
+            environment_variables,
+            working_directory: take_string(&mut dict, "WorkingDirectory")?,
+            standard_out_path: take_string(&mut dict, "StandardOutPath")?,
+            standard_error_path: take_string(&mut dict, "StandardErrorPath")?,
+            associated_bundle_identifiers,
+            mach_services: take_dictionary(&mut dict, "MachServices")?,
+            start_interval: take_integer(&mut dict, "StartInterval")?,
+            throttle_interval: take_integer(&mut dict, "ThrottleInterval")?,
+            process_type: take_string(&mut dict, "ProcessType")?,
+            user_name: take_string(&mut dict, "UserName")?,
+            group_name: take_string(&mut dict, "GroupName")?,
+            disabled: take_bool(&mut dict, "Disabled")?,
+            other: dict,
+        })
+    }
+
+    /// Converts the job definition into a property list dictionary.
+    pub fn to_value(&self) -> Value {
+        fn strings(values: &[String]) -> Value {
+            Value::Array(
+                values
+                    .iter()
+                    .map(|value| Value::from(value.as_str()))
+                    .collect(),
+            )
+        }
+
+        let mut dict = self.other.clone();
+        dict.insert("Label".to_string(), Value::from(self.label.as_str()));
+        let mut set = |key: &str, value: Option<Value>| {
+            if let Some(value) = value {
+                dict.insert(key.to_string(), value);
+            }
+        };
+        set("Program", self.program.as_deref().map(Value::from));
+        set(
+            "ProgramArguments",
+            self.program_arguments.as_deref().map(strings),
+        );
+        set(
+            "BundleProgram",
+            self.bundle_program.as_deref().map(Value::from),
+        );
+        set("RunAtLoad", self.run_at_load.map(Value::Boolean));
+        set(
+            "KeepAlive",
+            self.keep_alive.as_ref().map(|keep_alive| match keep_alive {
+                KeepAlive::Always(value) => Value::Boolean(*value),
+                KeepAlive::Conditions(conditions) => Value::Dictionary(conditions.clone()),
+            }),
+        );
+        set(
+            "EnvironmentVariables",
+            self.environment_variables.as_ref().map(|variables| {
+                Value::Dictionary(
+                    variables
+                        .iter()
+                        .map(|(name, value)| (name.clone(), Value::from(value.as_str())))
+                        .collect(),
+                )
+            }),
+        );
+        set(
+            "WorkingDirectory",
+            self.working_directory.as_deref().map(Value::from),
+        );
+        set(
+            "StandardOutPath",
+            self.standard_out_path.as_deref().map(Value::from),
+        );
+        set(
+            "StandardErrorPath",
+            self.standard_error_path.as_deref().map(Value::from),
+        );
+        set(
+            "AssociatedBundleIdentifiers",
+            self.associated_bundle_identifiers.as_deref().map(strings),
+        );
+        set(
+            "MachServices",
+            self.mach_services.clone().map(Value::Dictionary),
+        );
+        set("StartInterval", self.start_interval.map(Value::Integer));
+        set(
+            "ThrottleInterval",
+            self.throttle_interval.map(Value::Integer),
+        );
+        set("ProcessType", self.process_type.as_deref().map(Value::from));
+        set("UserName", self.user_name.as_deref().map(Value::from));
+        set("GroupName", self.group_name.as_deref().map(Value::from));
+        set("Disabled", self.disabled.map(Value::Boolean));
+        Value::Dictionary(dict)
+    }
+
+    /// Parses a job definition from an XML property list.
+    pub fn from_xml(xml: &str) -> Result<Self, PlistError> {
+        Self::from_value(Value::from_xml(xml)?)
+    }
+
+    /// Serializes the job definition as an XML property list.
+    pub fn to_xml(&self) -> String {
+        self.to_value().to_xml()
+    }
+
+    /// Reads a job definition from the XML or binary property list at `path`.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, PlistError> {
+        Self::from_value(Value::from_file(path)?)
+    }
+
+    /// Writes the job definition as an XML property list to `path`.
+    pub fn write_to_file(&self, path: impl AsRef<Path>) -> Result<(), PlistError> {
+        self.to_value().write_to_file(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip_with_unknown_keys() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<plist version="1.0">
+<dict>
+    <key>Label</key>
+    <string>com.example.agent</string>
+    <key>ProgramArguments</key>
+    <array>
+        <string>/usr/bin/true</string>
+        <string>--flag</string>
+    </array>
+    <key>KeepAlive</key>
+    <dict>
+        <key>SuccessfulExit</key>
+        <false/>
+    </dict>
+    <key>EnvironmentVariables</key>
+    <dict>
+        <key>RUST_LOG</key>
+        <string>debug</string>
+    </dict>
+    <key>AssociatedBundleIdentifiers</key>
+    <string>com.example.app</string>
+    <key>LimitLoadToSessionType</key>
+    <string>Aqua</string>
+</dict>
+</plist>"#;
+        let plist = LaunchdPlist::from_xml(xml).unwrap();
+        assert_eq!(plist.label, "com.example.agent");
+        assert_eq!(
+            plist.program_arguments.as_deref(),
+            Some(&["/usr/bin/true".to_string(), "--flag".to_string()][..])
+        );
+        assert!(matches!(plist.keep_alive, Some(KeepAlive::Conditions(_))));
+        assert_eq!(
+            plist.environment_variables.as_ref().unwrap()["RUST_LOG"],
+            "debug"
+        );
+        assert_eq!(
+            plist.associated_bundle_identifiers,
+            Some(vec!["com.example.app".to_string()])
+        );
+        assert_eq!(
+            plist.other["LimitLoadToSessionType"].as_string(),
+            Some("Aqua")
+        );
+
+        assert_eq!(LaunchdPlist::from_xml(&plist.to_xml()).unwrap(), plist);
+    }
+
+    #[test]
+    fn test_invalid_plists() {
+        assert!(matches!(
+            LaunchdPlist::from_xml("<plist><dict/></plist>"),
+            Err(PlistError::MissingKey(key)) if key == "Label"
+        ));
+        assert!(matches!(
+            LaunchdPlist::from_xml(
+                "<plist><dict><key>Label</key><string>a</string>\
+                 <key>RunAtLoad</key><string>yes</string></dict></plist>"
+            ),
+            Err(PlistError::InvalidType { key, .. }) if key == "RunAtLoad"
+        ));
+        assert!(matches!(
+            LaunchdPlist::from_xml(
+                "<plist><dict><key>Label</key><string>a</string>\
+                 <key>ProgramArguments</key><array><integer>1</integer></array></dict></plist>"
+            ),
+            Err(PlistError::InvalidType { key, .. }) if key == "ProgramArguments.0"
+        ));
+    }
+}
@@ -0,0 +1,550 @@
+//! Offline validation of launchd job property lists.
+
+use std::path::{Component, Path};
+
+use crate::ServiceManagementError;
+use crate::plist::{Dictionary, Value};
+
+/// How serious a [`Diagnostic`] is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    /// The framework is likely to reject the plist or the job won't run as intended.
+    Warning,
+
+    /// The framework will reject the plist.
+    Error,
+}
+
+impl std::fmt::Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// A problem found in a launchd property list.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// How serious the problem is.
+    pub severity: Severity,
+
+    /// The path of the offending value, with components separated by `.`, for example
+    /// `ProgramArguments.0`. Empty for problems with the document as a whole.
+    pub key_path: String,
+
+    /// A human-readable description of the problem.
+    pub message: String,
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.key_path.is_empty() {
+            write!(f, "{}: {}", self.severity, self.message)
+        } else {
+            write!(f, "{}: {}: {}", self.severity, self.key_path, self.message)
+        }
+    }
+}
+
+/// The diagnostics produced by validating a launchd property list.
+///
+/// # Examples
+///
+/// ```rust
+/// use smappservice_rs::{ServiceManagementError, ValidationReport};
+/// use smappservice_rs::plist::Value;
+///
+/// let plist = Value::from_xml(
+///     "<plist><dict><key>RunAtLoad</key><string>yes</string></dict></plist>",
+/// )
+/// .unwrap();
+/// let report = ValidationReport::for_value(&plist, Some("com.example.agent.plist"));
+///
+/// for diagnostic in &report.diagnostics {
+///     println!("{}", diagnostic);
+/// }
+/// assert_eq!(report.predicted_error(), Some(ServiceManagementError::InvalidPlist));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationReport {
+    /// Whether the property list file was found. Always `true` for in-memory values.
+    pub found: bool,
+
+    /// The problems found, in the order they were detected.
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// The type a launchd key must have.
+#[derive(Clone, Copy)]
+enum Shape {
+    String,
+    Boolean,
+    Integer,
+    Dictionary,
+    StringArray,
+    StringDictionary,
+    BooleanOrDictionary,
+    StringOrStringArray,
+    DictionaryOrArray,
+    IntegerOrString,
+}
+
+impl Shape {
+    fn description(self) -> &'static str {
+        match self {
+            Shape::String => "a string",
+            Shape::Boolean => "a boolean",
+            Shape::Integer => "an integer",
+            Shape::Dictionary => "a dictionary",
+            Shape::StringArray => "an array of strings",
+            Shape::StringDictionary => "a dictionary of strings",
+            Shape::BooleanOrDictionary => "a boolean or a dictionary",
+            Shape::StringOrStringArray => "a string or an array of strings",
+            Shape::DictionaryOrArray => "a dictionary or an array of dictionaries",
+            Shape::IntegerOrString => "an integer or a string",
+        }
+    }
+}
+
+/// The keys documented in launchd.plist(5), with the type launchd expects.
+const KEYS: &[(&str, Shape)] = &[
+    ("Label", Shape::String),
+    ("Disabled", Shape::Boolean),
+    ("UserName", Shape::String),
+    ("GroupName", Shape::String),
+    ("inetdCompatibility", Shape::Dictionary),
+    ("LimitLoadToHosts", Shape::StringArray),
+    ("LimitLoadFromHosts", Shape::StringArray),
+    ("LimitLoadToSessionType", Shape::StringOrStringArray),
+    ("LimitLoadToHardware", Shape::Dictionary),
+    ("LimitLoadFromHardware", Shape::Dictionary),
+    ("Program", Shape::String),
+    ("BundleProgram", Shape::String),
+    ("ProgramArguments", Shape::StringArray),
+    ("EnableGlobbing", Shape::Boolean),
+    ("EnableTransactions", Shape::Boolean),
+    ("EnablePressuredExit", Shape::Boolean),
+    ("OnDemand", Shape::Boolean),
+    ("ServiceIPC", Shape::Boolean),
+    ("KeepAlive", Shape::BooleanOrDictionary),
+    ("RunAtLoad", Shape::Boolean),
+    ("RootDirectory", Shape::String),
+    ("WorkingDirectory", Shape::String),
+    ("EnvironmentVariables", Shape::StringDictionary),
+    ("Umask", Shape::IntegerOrString),
+    ("TimeOut", Shape::Integer),
+    ("ExitTimeOut", Shape::Integer),
+    ("ThrottleInterval", Shape::Integer),
+    ("InitGroups", Shape::Boolean),
+    ("WatchPaths", Shape::StringArray),
+    ("QueueDirectories", Shape::StringArray),
+    ("StartOnMount", Shape::Boolean),
+    ("StartInterval", Shape::Integer),
+    ("StartCalendarInterval", Shape::DictionaryOrArray),
+    ("StandardInPath", Shape::String),
+    ("StandardOutPath", Shape::String),
+    ("StandardErrorPath", Shape::String),
+    ("Debug", Shape::Boolean),
+    ("WaitForDebugger", Shape::Boolean),
+    ("SoftResourceLimits", Shape::Dictionary),
+    ("HardResourceLimits", Shape::Dictionary),
+    ("Nice", Shape::Integer),
+    ("ProcessType", Shape::String),
+    ("AbandonProcessGroup", Shape::Boolean),
+    ("LowPriorityIO", Shape::Boolean),
+    ("LowPriorityBackgroundIO", Shape::Boolean),
+    ("MaterializeDatalessFiles", Shape::Boolean),
+    ("LaunchOnlyOnce", Shape::Boolean),
+    ("MachServices", Shape::Dictionary),
+    ("Sockets", Shape::Dictionary),
+    ("LaunchEvents", Shape::Dictionary),
+    ("SessionCreate", Shape::Boolean),
+    ("LegacyTimers", Shape::Boolean),
+    ("AssociatedBundleIdentifiers", Shape::StringOrStringArray),
+    ("POSIXSpawnType", Shape::String),
+];
+
+const PROCESS_TYPES: &[&str] = &["Background", "Standard", "Adaptive", "Interactive"];
+
+fn strings_only<'a>(
+    values: impl Iterator<Item = (String, &'a Value)>,
+    report: &mut ValidationReport,
+) {
+    for (path, value) in values {
+        if value.as_string().is_none() {
+            report.push(
+                Severity::Error,
+                path,
+                format!("must be a string, found {}", value.type_name()),
+            );
+        }
+    }
+}
+
+/// Checks `value` against `shape`, reporting the offending path if it doesn't match.
+fn check_shape(key: &str, value: &Value, shape: Shape, report: &mut ValidationReport) {
+    let matches = match (shape, value) {
+        (Shape::String, Value::String(_))
+        | (Shape::Boolean, Value::Boolean(_))
+        | (Shape::Integer, Value::Integer(_))
+        | (Shape::Dictionary, Value::Dictionary(_))
+        | (Shape::BooleanOrDictionary, Value::Boolean(_) | Value::Dictionary(_))
+        | (Shape::StringOrStringArray, Value::String(_))
+        | (Shape::IntegerOrString, Value::Integer(_) | Value::String(_))
+        | (Shape::DictionaryOrArray, Value::Dictionary(_)) => true,
+        (Shape::StringArray | Shape::StringOrStringArray, Value::Array(values)) => {
+            let paths = values
+                .iter()
+                .enumerate()
+                .map(|(index, value)| (format!("{key}.{index}"), value));
+            strings_only(paths, report);
+            true
+        }
+        (Shape::StringDictionary, Value::Dictionary(dict)) => {
+            let paths = dict
+                .iter()
+                .map(|(name, value)| (format!("{key}.{name}"), value));
+            strings_only(paths, report);
+            true
+        }
+        (Shape::DictionaryOrArray, Value::Array(values)) => {
+            for (index, value) in values.iter().enumerate() {
+                if value.as_dictionary().is_none() {
+                    report.push(
+                        Severity::Error,
+                        format!("{key}.{index}"),
+                        format!("must be a dictionary, found {}", value.type_name()),
+                    );
+                }
+            }
+            true
+        }
+        _ => false,
+    };
+    if !matches {
+        report.push(
+            Severity::Error,
+            key,
+            format!(
+                "must be {}, found {}",
+                shape.description(),
+                value.type_name()
+            ),
+        );
+    }
+}
+
+/// Returns `true` if `path` is relative and stays inside the directory it's resolved against.
+fn is_bundle_relative(path: &str) -> bool {
+    let mut depth = 0usize;
+    for component in Path::new(path).components() {
+        match component {
+            Component::Normal(_) => depth += 1,
+            Component::CurDir => {}
+            Component::ParentDir => match depth.checked_sub(1) {
+                Some(parent) => depth = parent,
+                None => return false,
+            },
+            Component::RootDir | Component::Prefix(_) => return false,
+        }
+    }
+    depth > 0
+}
+
+impl Default for ValidationReport {
+    /// An empty report for a file that was found: it predicts no error.
+    fn default() -> Self {
+        Self {
+            found: true,
+            diagnostics: Vec::new(),
+        }
+    }
+}
+
+impl ValidationReport {
+    /// Validates an already parsed launchd property list.
+    ///
+    /// When `file_name` is given, the `Label` is expected to match it without the `.plist`
+    /// extension, which is the convention launchd and the ServiceManagement framework follow.
+    pub fn for_value(value: &Value, file_name: Option<&str>) -> Self {
+        let mut report = ValidationReport::default();
+        let dict = match value {
+            Value::Dictionary(dict) => dict,
+            other => {
+                report.push(
+                    Severity::Error,
+                    "",
+                    format!("the root must be a dictionary, found {}", other.type_name()),
+                );
+                return report;
+            }
+        };
+        report.check_keys(dict);
+        report.check_label(dict, file_name);
+        report.check_program(dict);
+        if let Some(process_type) = dict.get("ProcessType").and_then(Value::as_string)
+            && !PROCESS_TYPES.contains(&process_type)
+        {
+            report.push(
+                Severity::Warning,
+                "ProcessType",
+                format!(
+                    "unknown process type `{process_type}`, expected one of {}",
+                    PROCESS_TYPES.join(", ")
+                ),
+            );
+        }
+        report
+    }
+
+    /// Validates the property list at `path`, in either the XML or the binary encoding.
+    ///
+    /// A missing file or a document that can't be parsed is reported as a diagnostic rather than
+    /// an error, so the report always explains why registration would fail.
+    pub fn for_file(path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref();
+        let data = match std::fs::read(path) {
+            Ok(data) => data,
+            Err(error) => {
+                let mut report = ValidationReport {
+                    found: error.kind() != std::io::ErrorKind::NotFound,
+                    diagnostics: Vec::new(),
+                };
+                report.push(
+                    Severity::Error,
+                    "",
+                    format!("failed to read {}: {}", path.display(), error),
+                );
+                return report;
+            }
+        };
+        match Value::from_bytes(&data) {
+            Ok(value) => {
+                let file_name = path.file_name().and_then(|name| name.to_str());
+                Self::for_value(&value, file_name)
+            }
+            Err(error) => {
+                let mut report = ValidationReport {
+                    found: true,
+                    diagnostics: Vec::new(),
+                };
+                report.push(Severity::Error, "", error.to_string());
+                report
+            }
+        }
+    }
+
+    /// Returns `true` if no diagnostic has [`Severity::Error`].
+    pub fn is_valid(&self) -> bool {
+        self.errors().next().is_none()
+    }
+
+    /// Returns the diagnostics with [`Severity::Error`].
+    pub fn errors(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.severity == Severity::Error)
+    }
+
+    /// Returns the diagnostics with [`Severity::Warning`].
+    pub fn warnings(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.severity == Severity::Warning)
+    }
+
+    /// Returns the error registering this plist is expected to fail with, if any.
+    ///
+    /// A missing file predicts [`ServiceManagementError::JobPlistNotFound`] and any other error
+    /// diagnostic predicts [`ServiceManagementError::InvalidPlist`].
+    pub fn predicted_error(&self) -> Option<ServiceManagementError> {
+        if !self.found {
+            Some(ServiceManagementError::JobPlistNotFound)
+        } else if !self.is_valid() {
+            Some(ServiceManagementError::InvalidPlist)
+        } else {
+            None
+        }
+    }
+
+    fn push(
+        &mut self,
+        severity: Severity,
+        key_path: impl Into<String>,
+        message: impl Into<String>,
+    ) {
+        self.diagnostics.push(Diagnostic {
+            severity,
+            key_path: key_path.into(),
+            message: message.into(),
+        });
+    }
+
+    fn check_keys(&mut self, dict: &Dictionary) {
+        for (key, value) in dict {
+            match KEYS.iter().find(|(known, _)| known == key) {
+                Some((_, shape)) => check_shape(key, value, *shape, self),
+                None => self.push(Severity::Warning, key.as_str(), "unknown launchd key"),
+            }
+        }
+    }
+
+    fn check_label(&mut self, dict: &Dictionary, file_name: Option<&str>) {
+        let label = match dict.get("Label") {
+            None => {
+                self.push(Severity::Error, "Label", "the required key is missing");
+                return;
+            }
+            Some(Value::String(label)) => label,
+            // Wrong types are already reported by `check_keys`.
+            Some(_) => return,
+        };
+        if label.is_empty() {
+            self.push(Severity::Error, "Label", "must not be empty");
+            return;
+        }
+        if let Some(stem) = file_name.map(|name| name.strip_suffix(".plist").unwrap_or(name))
+            && stem != label
+        {
+            self.push(
+                Severity::Warning,
+                "Label",
+                format!("`{label}` doesn't match the file name `{stem}.plist`"),
+            );
+        }
+    }
+
+    fn check_program(&mut self, dict: &Dictionary) {
+        let program = dict.get("Program");
+        let arguments = dict.get("ProgramArguments");
+        let bundle_program = dict.get("BundleProgram");
+
+        if program.is_none() && arguments.is_none() && bundle_program.is_none() {
+            self.push(
+                Severity::Error,
+                "",
+                "one of Program, ProgramArguments or BundleProgram is required",
+            );
+        }
+        if let Some(Value::Array(arguments)) = arguments
+            && arguments.is_empty()
+        {
+            self.push(Severity::Error, "ProgramArguments", "must not be empty");
+        }
+        if let Some(Value::String(program)) = program
+            && !program.starts_with('/')
+        {
+            self.push(
+                Severity::Error,
+                "Program",
+                format!("`{program}` must be an absolute path"),
+            );
+        }
+        if let Some(Value::String(bundle_program)) = bundle_program
+            && !is_bundle_relative(bundle_program)
+        {
+            self.push(
+                Severity::Error,
+                "BundleProgram",
+                format!(
+                    "`{bundle_program}` must be a path relative to the app bundle, \
+                     e.g. Contents/MacOS/helper"
+                ),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key_paths(report: &ValidationReport, severity: Severity) -> Vec<&str> {
+        report
+            .diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.severity == severity)
+            .map(|diagnostic| diagnostic.key_path.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_valid_plist() {
+        let xml = r#"<plist><dict>
+            <key>Label</key><string>com.example.agent</string>
+            <key>BundleProgram</key><string>Contents/MacOS/agent</string>
+            <key>KeepAlive</key><dict><key>SuccessfulExit</key><false/></dict>
+            <key>StartCalendarInterval</key><array><dict><key>Hour</key><integer>3</integer></dict></array>
+        </dict></plist>"#;
+        let report = ValidationReport::for_value(
+            &Value::from_xml(xml).unwrap(),
+            Some("com.example.agent.plist"),
+        );
+        assert_eq!(report.diagnostics, []);
+        assert_eq!(report.predicted_error(), None);
+    }
+
+    #[test]
+    fn test_invalid_plist() {
+        let xml = r#"<plist><dict>
+            <key>Label</key><string>com.example.other</string>
+            <key>BundleProgram</key><string>../../outside</string>
+            <key>EnvironmentVariables</key><dict><key>DEBUG</key><true/></dict>
+            <key>RunAtLoad</key><string>yes</string>
+            <key>ProcessType</key><string>Fast</string>
+            <key>RunAtLogin</key><true/>
+        </dict></plist>"#;
+        let report = ValidationReport::for_value(
+            &Value::from_xml(xml).unwrap(),
+            Some("com.example.agent.plist"),
+        );
+        assert_eq!(
+            key_paths(&report, Severity::Error),
+            ["EnvironmentVariables.DEBUG", "RunAtLoad", "BundleProgram"]
+        );
+        assert_eq!(
+            key_paths(&report, Severity::Warning),
+            ["RunAtLogin", "Label", "ProcessType"]
+        );
+        assert_eq!(
+            report.predicted_error(),
+            Some(ServiceManagementError::InvalidPlist)
+        );
+    }
+
+    #[test]
+    fn test_missing_label_and_program() {
+        let report = ValidationReport::for_value(&Value::Dictionary(Dictionary::new()), None);
+        assert_eq!(key_paths(&report, Severity::Error), ["Label", ""]);
+    }
+
+    #[test]
+    fn test_missing_file() {
+        let report = ValidationReport::for_file("/nonexistent/com.example.agent.plist");
+        assert!(!report.found);
+        assert_eq!(
+            report.predicted_error(),
+            Some(ServiceManagementError::JobPlistNotFound)
+        );
+    }
+
+    #[test]
+    fn test_default_report() {
+        let report = ValidationReport::default();
+        assert!(report.found);
+        assert_eq!(report.predicted_error(), None);
+    }
+
+    #[test]
+    fn test_bundle_relative_paths() {
+        assert!(is_bundle_relative("Contents/MacOS/agent"));
+        assert!(is_bundle_relative("./Contents/../Contents/MacOS/agent"));
+        assert!(!is_bundle_relative(
+            "/Applications/App.app/Contents/MacOS/agent"
+        ));
+        assert!(!is_bundle_relative("../agent"));
+        assert!(!is_bundle_relative(""));
+    }
+}
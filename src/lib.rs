@@ -53,22 +53,98 @@
 //! if let Err(e) = login_item.register() {
 //!     eprintln!("Failed to register login item: {}", e);
 //! }
+//! ```
 //!
+//! ## Backends
+//!
+//! `AppService` delegates every call to a [`ServiceBackend`]. [`AppService::new`] uses the
+//! platform's [`DefaultBackend`], which is the ServiceManagement framework on macOS. On other
+//! platforms the default backend reports every service as [`ServiceStatus::NotFound`], and
+//! [`AppService::with_backend`] can be used to plug in a different implementation.
+
+mod approval;
+mod audit;
+mod backend;
+mod bundle;
+pub mod csreq;
+mod digest;
+mod ensure;
+mod fault;
+#[cfg(feature = "async")]
+mod future;
+mod graph;
+mod group;
+mod info;
+mod launchd;
+pub mod macho;
+mod migration;
+pub mod plist;
+mod preflight;
+mod reconcile;
+mod seal;
+mod simulated;
+mod state;
+#[cfg(not(target_os = "macos"))]
+mod sys;
+mod trace;
+mod upgrade;
+mod wait;
+mod watcher;
+
+pub use approval::{ApprovalError, ApprovalEvent, ApprovalFlow, ApprovalOptions, ApprovalOutcome};
+pub use audit::{AuditFinding, AuditedComponent, Signing, SigningInfo, TeamIdAudit};
+pub use backend::{DefaultBackend, Operation, ServiceBackend, UnsupportedBackend};
+pub use bundle::{AppBundle, BundleError, BundledService, ServiceDefinition};
+pub use ensure::EnsureReport;
+pub use fault::{BackendCall, FaultInjectingBackend};
+#[cfg(feature = "async")]
+pub use future::{ServiceFuture, StatusStream};
+pub use graph::{GraphError, GraphReport, NodeOutcome, ServiceGraph, ServiceNode};
+pub use group::{GroupError, Member, MemberOutcome, ServiceGroup};
+pub use info::InfoPlist;
+pub use launchd::{Diagnostic, KeepAlive, LaunchdPlist, Severity, ValidationReport};
+pub use migration::{Migration, MigrationLog, MigrationReport, Migrator};
+pub use preflight::{preflight, PreflightError};
+pub use reconcile::{
+    ApplyReport, DesiredService, DesiredState, DesiredStateError, Plan, PlannedChange, Reconciler,
+    ServiceResult,
+};
+pub use seal::{CodeResources, SealError, SealReport, SealedResource};
+pub use simulated::SimulatedBackend;
+pub use state::StateError;
+pub use trace::{RecordingBackend, ReplayBackend, Trace, TraceError, TraceEvent};
+pub use upgrade::{
+    Fingerprint, FingerprintChange, FingerprintStore, UpgradeError, UpgradeOutcome, UpgradeReport,
+    Upgrader,
+};
+pub use wait::{CancellationToken, WaitError};
+pub use watcher::{StatusChange, StatusWatcher, WatchOptions};
+#[cfg(target_os = "macos")]
+pub use backend::SMAppServiceBackend;
 
-use objc2::rc::Retained;
-use objc2_foundation::NSString;
+#[cfg(target_os = "macos")]
 use objc2_service_management::{
     kSMErrorAlreadyRegistered, kSMErrorAuthorizationFailure, kSMErrorInternalFailure,
     kSMErrorInvalidPlist, kSMErrorInvalidSignature, kSMErrorJobMustBeEnabled, kSMErrorJobNotFound,
     kSMErrorJobPlistNotFound, kSMErrorLaunchDeniedByUser, kSMErrorServiceUnavailable,
-    kSMErrorToolNotValid, SMAppService, SMAppServiceStatus,
+    kSMErrorToolNotValid, SMAppServiceStatus,
+};
+#[cfg(not(target_os = "macos"))]
+use sys::{
+    kSMErrorAlreadyRegistered, kSMErrorAuthorizationFailure, kSMErrorInternalFailure,
+    kSMErrorInvalidPlist, kSMErrorInvalidSignature, kSMErrorJobMustBeEnabled, kSMErrorJobNotFound,
+    kSMErrorJobPlistNotFound, kSMErrorLaunchDeniedByUser, kSMErrorServiceUnavailable,
+    kSMErrorToolNotValid, SMAppServiceStatus,
 };
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 /// Represents the various types of services that can be registered with the ServiceManagement framework.
 ///
 /// This enum is used to specify which kind of service you want to register when creating an `AppService`.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum ServiceType<'a> {
     /// An app service object that corresponds to the main application as a login item.
     ///
@@ -102,11 +178,101 @@ pub enum ServiceType<'a> {
     LoginItem { identifier: &'a str },
 }
 
+/// An owned counterpart of [`ServiceType`].
+///
+/// Useful when a service type has to be stored beyond the lifetime of the strings it was created from,
+/// for example as a key in a map.
+///
+/// When serialized, the variant is stored in a `kind` field next to its parameters, for example
+/// `{"kind": "agent", "plist_name": "com.example.myapp.agent.plist"}`.
+///
+/// # Examples
+///
+/// ```rust
+/// use smappservice_rs::{OwnedServiceType, ServiceType};
+///
+/// let owned = OwnedServiceType::from(ServiceType::Agent {
+///     plist_name: "com.example.myapp.agent.plist",
+/// });
+/// assert_eq!(
+///     owned.as_service_type(),
+///     ServiceType::Agent { plist_name: "com.example.myapp.agent.plist" }
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum OwnedServiceType {
+    /// See [`ServiceType::MainApp`].
+    MainApp,
+
+    /// See [`ServiceType::Agent`].
+    Agent { plist_name: String },
+
+    /// See [`ServiceType::Daemon`].
+    Daemon { plist_name: String },
+
+    /// See [`ServiceType::LoginItem`].
+    LoginItem { identifier: String },
+}
+
+impl OwnedServiceType {
+    /// Borrows this value as a [`ServiceType`].
+    pub fn as_service_type(&self) -> ServiceType<'_> {
+        match self {
+            OwnedServiceType::MainApp => ServiceType::MainApp,
+            OwnedServiceType::Agent { plist_name } => ServiceType::Agent { plist_name },
+            OwnedServiceType::Daemon { plist_name } => ServiceType::Daemon { plist_name },
+            OwnedServiceType::LoginItem { identifier } => ServiceType::LoginItem { identifier },
+        }
+    }
+}
+
+impl From<ServiceType<'_>> for OwnedServiceType {
+    fn from(service_type: ServiceType<'_>) -> Self {
+        match service_type {
+            ServiceType::MainApp => OwnedServiceType::MainApp,
+            ServiceType::Agent { plist_name } => OwnedServiceType::Agent {
+                plist_name: plist_name.to_string(),
+            },
+            ServiceType::Daemon { plist_name } => OwnedServiceType::Daemon {
+                plist_name: plist_name.to_string(),
+            },
+            ServiceType::LoginItem { identifier } => OwnedServiceType::LoginItem {
+                identifier: identifier.to_string(),
+            },
+        }
+    }
+}
+
+impl From<&ServiceType<'_>> for OwnedServiceType {
+    fn from(service_type: &ServiceType<'_>) -> Self {
+        OwnedServiceType::from(*service_type)
+    }
+}
+
+impl std::fmt::Display for ServiceType<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ServiceType::MainApp => write!(f, "main app"),
+            ServiceType::Agent { plist_name } => write!(f, "agent {}", plist_name),
+            ServiceType::Daemon { plist_name } => write!(f, "daemon {}", plist_name),
+            ServiceType::LoginItem { identifier } => write!(f, "login item {}", identifier),
+        }
+    }
+}
+
+impl std::fmt::Display for OwnedServiceType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.as_service_type().fmt(f)
+    }
+}
+
 /// Represents the status of a service registration.
 ///
 /// This enum corresponds to the `SMAppServiceStatus` values in the ServiceManagement framework.
 /// It provides information about the current state of a registered service.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 #[repr(isize)]
 pub enum ServiceStatus {
     /// The service hasn't registered with the Service Management framework,
@@ -155,7 +321,7 @@ impl TryFrom<isize> for ServiceStatus {
 /// Represents errors that can occur when registering or unregistering services.
 ///
 /// This enum wraps the error codes returned by the ServiceManagement framework.
-#[derive(Debug, Error, PartialEq)]
+#[derive(Debug, Clone, Copy, Error, PartialEq, Eq)]
 #[repr(u32)]
 pub enum ServiceManagementError {
     /// An internal failure has occurred in the ServiceManagement framework.
@@ -282,8 +448,12 @@ impl TryFrom<u32> for ServiceManagementError {
 ///
 /// `AppService` provides methods to register, unregister, and check the status of various
 /// types of services, such as login items, launch agents, and daemons.
+///
+/// Every call is delegated to a [`ServiceBackend`]. Services created with
+/// [`new`](#method.new) use the platform's [`DefaultBackend`].
 pub struct AppService {
-    service: Retained<SMAppService>,
+    service_type: OwnedServiceType,
+    backend: Arc<dyn ServiceBackend>,
 }
 
 impl AppService {
@@ -315,23 +485,36 @@ impl AppService {
     ///     identifier: "com.example.helper"
     /// });
     /// ```
-    pub fn new(service_type: ServiceType) -> Self {
-        let service = match service_type {
-            ServiceType::MainApp => unsafe { SMAppService::mainAppService() },
-            ServiceType::Agent { plist_name } => unsafe {
-                let input_arg = NSString::from_str(plist_name);
-                SMAppService::agentServiceWithPlistName(&input_arg)
-            },
-            ServiceType::Daemon { plist_name } => unsafe {
-                let input_arg = NSString::from_str(plist_name);
-                SMAppService::daemonServiceWithPlistName(&input_arg)
-            },
-            ServiceType::LoginItem { identifier } => unsafe {
-                let input_arg = NSString::from_str(identifier);
-                SMAppService::loginItemServiceWithIdentifier(&input_arg)
-            },
-        };
-        Self { service }
+    pub fn new(service_type: ServiceType<'_>) -> Self {
+        Self::with_backend(service_type, DefaultBackend::default())
+    }
+
+    /// Creates a new `AppService` instance that delegates its calls to `backend`.
+    ///
+    /// Use this to run code built on `AppService` against something other than the
+    /// ServiceManagement framework, for example in tests or on platforms other than macOS.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use smappservice_rs::{AppService, ServiceType, UnsupportedBackend};
+    ///
+    /// let service = AppService::with_backend(ServiceType::MainApp, UnsupportedBackend);
+    /// assert!(service.register().is_err());
+    /// ```
+    pub fn with_backend(
+        service_type: ServiceType<'_>,
+        backend: impl ServiceBackend + 'static,
+    ) -> Self {
+        Self {
+            service_type: service_type.into(),
+            backend: Arc::new(backend),
+        }
+    }
+
+    /// Returns the service type this instance was created for.
+    pub fn service_type(&self) -> ServiceType<'_> {
+        self.service_type.as_service_type()
     }
 
     /// Registers the service so it can begin launching according to its configuration.
@@ -371,14 +554,7 @@ impl AppService {
     /// }
     /// ```
     pub fn register(&self) -> Result<(), ServiceManagementError> {
-        match unsafe { self.service.registerAndReturnError() } {
-            Ok(()) => Ok(()),
-            Err(error) => {
-                let error_code = error.code() as u32;
-                Err(ServiceManagementError::try_from(error_code)
-                    .unwrap_or(ServiceManagementError::Unknown(error_code)))
-            }
-        }
+        self.backend.register(&self.service_type())
     }
 
     /// Un registers the service, preventing it from launching automatically in the future.
@@ -411,14 +587,7 @@ impl AppService {
     /// }
     /// ```
     pub fn unregister(&self) -> Result<(), ServiceManagementError> {
-        match unsafe { self.service.unregisterAndReturnError() } {
-            Ok(()) => Ok(()),
-            Err(error) => {
-                let error_code = error.code() as u32;
-                Err(ServiceManagementError::try_from(error_code)
-                    .unwrap_or(ServiceManagementError::Unknown(error_code)))
-            }
-        }
+        self.backend.unregister(&self.service_type())
     }
 
     /// Opens the Login Items section in System Settings.
@@ -435,7 +604,15 @@ impl AppService {
     /// AppService::open_system_settings_login_items();
     /// ```
     pub fn open_system_settings_login_items() {
-        unsafe { SMAppService::openSystemSettingsLoginItems() }
+        DefaultBackend::default().open_system_settings_login_items()
+    }
+
+    /// Opens the Login Items section in System Settings through this service's backend.
+    ///
+    /// Behaves like [`open_system_settings_login_items`](#method.open_system_settings_login_items)
+    /// but goes through the backend the service was created with.
+    pub fn open_system_settings(&self) {
+        self.backend.open_system_settings_login_items()
     }
 
     /// Checks the current registration status of the service.
@@ -461,11 +638,34 @@ impl AppService {
     /// }
     /// ```
     pub fn status(&self) -> ServiceStatus {
-        let status = unsafe { self.service.status() };
-        match ServiceStatus::try_from(status.0) {
-            Ok(status) => status,
-            Err(_) => ServiceStatus::NotFound,
-        }
+        self.backend.status(&self.service_type())
+    }
+
+    /// Checks that the files this service refers to are in place inside the running app's bundle.
+    ///
+    /// Call this before [`register`](#method.register) to turn a `JobPlistNotFound` or
+    /// `ToolNotValid` failure into an explanation of which file is missing. See [`preflight`] for
+    /// the checks performed. Fails with [`PreflightError::NotABundle`] when the running
+    /// executable isn't inside an app bundle.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use smappservice_rs::{AppService, ServiceType};
+    ///
+    /// let agent = AppService::new(ServiceType::Agent {
+    ///     plist_name: "com.example.myapp.agent.plist"
+    /// });
+    /// match agent.preflight() {
+    ///     Ok(()) => agent.register().unwrap(),
+    ///     Err(e) => eprintln!("Agent can't be registered: {}", e),
+    /// }
+    /// ```
+    pub fn preflight(&self) -> Result<(), PreflightError> {
+        let bundle = preflight::current_bundle().ok_or_else(|| {
+            PreflightError::NotABundle(std::env::current_exe().unwrap_or_default())
+        })?;
+        preflight(bundle, &self.service_type())
     }
 }
 
@@ -497,6 +697,71 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_with_backend_delegates() {
+        use std::sync::{Arc, Mutex};
+
+        #[derive(Default)]
+        struct RecordingBackend {
+            calls: Mutex<Vec<String>>,
+        }
+
+        impl ServiceBackend for RecordingBackend {
+            fn register(&self, service_type: &ServiceType) -> Result<(), ServiceManagementError> {
+                self.calls
+                    .lock()
+                    .unwrap()
+                    .push(format!("register {:?}", service_type));
+                Err(ServiceManagementError::AlreadyRegistered)
+            }
+
+            fn unregister(&self, service_type: &ServiceType) -> Result<(), ServiceManagementError> {
+                self.calls
+                    .lock()
+                    .unwrap()
+                    .push(format!("unregister {:?}", service_type));
+                Ok(())
+            }
+
+            fn status(&self, service_type: &ServiceType) -> ServiceStatus {
+                self.calls
+                    .lock()
+                    .unwrap()
+                    .push(format!("status {:?}", service_type));
+                ServiceStatus::RequiresApproval
+            }
+
+            fn open_system_settings_login_items(&self) {
+                self.calls.lock().unwrap().push("open settings".to_string());
+            }
+        }
+
+        let backend = Arc::new(RecordingBackend::default());
+        let service_type = ServiceType::Agent {
+            plist_name: "com.example.agent.plist",
+        };
+        let service = AppService::with_backend(service_type, backend.clone());
+
+        assert_eq!(service.service_type(), service_type);
+        assert_eq!(
+            service.register(),
+            Err(ServiceManagementError::AlreadyRegistered)
+        );
+        assert_eq!(service.unregister(), Ok(()));
+        assert_eq!(service.status(), ServiceStatus::RequiresApproval);
+        service.open_system_settings();
+
+        assert_eq!(
+            *backend.calls.lock().unwrap(),
+            [
+                "register Agent { plist_name: \"com.example.agent.plist\" }",
+                "unregister Agent { plist_name: \"com.example.agent.plist\" }",
+                "status Agent { plist_name: \"com.example.agent.plist\" }",
+                "open settings",
+            ]
+        );
+    }
+
     #[test]
     fn test_service_management_error_code() {
         // Test known error variants
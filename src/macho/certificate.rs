@@ -0,0 +1,409 @@
+//! Extraction of the certificate chain from a signature's CMS blob.
+//!
+//! Only the parts of X.509 that code requirements refer to are decoded: subject attributes,
+//! extension identifiers and the certificate hash. Nothing is cryptographically verified.
+
+use super::MachOError;
+use crate::digest::sha1;
+
+const TAG_SEQUENCE: u8 = 0x30;
+const TAG_SET: u8 = 0x31;
+const TAG_OID: u8 = 0x06;
+const TAG_CONTEXT_0: u8 = 0xa0;
+const TAG_CONTEXT_3: u8 = 0xa3;
+
+const OID_SIGNED_DATA: &str = "1.2.840.113549.1.7.2";
+
+fn invalid(message: &str) -> MachOError {
+    MachOError::InvalidSignature(format!("CMS signature: {message}"))
+}
+
+/// A DER value: its tag, its contents and the bytes of the whole encoding.
+struct Tlv<'a> {
+    tag: u8,
+    contents: &'a [u8],
+    raw: &'a [u8],
+}
+
+/// Reads the DER value at the start of `data` and returns it with the remaining bytes.
+fn read_tlv(data: &[u8]) -> Result<(Tlv<'_>, &[u8]), MachOError> {
+    let (&tag, rest) = data
+        .split_first()
+        .ok_or_else(|| invalid("truncated value"))?;
+    let (&first, rest) = rest
+        .split_first()
+        .ok_or_else(|| invalid("truncated length"))?;
+    let (length, rest) = if first < 0x80 {
+        (first as usize, rest)
+    } else {
+        let count = (first & 0x7f) as usize;
+        if count == 0 || count > 4 || rest.len() < count {
+            return Err(invalid("unsupported length encoding"));
+        }
+        let length = rest[..count]
+            .iter()
+            .fold(0usize, |length, &byte| length << 8 | byte as usize);
+        (length, &rest[count..])
+    };
+    if rest.len() < length {
+        return Err(invalid("value extends past its container"));
+    }
+    let header = data.len() - rest.len();
+    Ok((
+        Tlv {
+            tag,
+            contents: &rest[..length],
+            raw: &data[..header + length],
+        },
+        &rest[length..],
+    ))
+}
+
+/// Splits the contents of a constructed value into its elements.
+fn elements(mut data: &[u8]) -> Result<Vec<Tlv<'_>>, MachOError> {
+    let mut elements = Vec::new();
+    while !data.is_empty() {
+        let (element, rest) = read_tlv(data)?;
+        elements.push(element);
+        data = rest;
+    }
+    Ok(elements)
+}
+
+fn expect<'a>(value: Option<&Tlv<'a>>, tag: u8, what: &str) -> Result<&'a [u8], MachOError> {
+    match value {
+        Some(value) if value.tag == tag => Ok(value.contents),
+        _ => Err(invalid(&format!("expected {what}"))),
+    }
+}
+
+fn decode_oid(bytes: &[u8]) -> String {
+    let mut parts = Vec::new();
+    let mut value = 0u64;
+    for &byte in bytes {
+        value = value << 7 | u64::from(byte & 0x7f);
+        if byte & 0x80 == 0 {
+            if parts.is_empty() {
+                let first = (value / 40).min(2);
+                parts.push(first);
+                parts.push(value - first * 40);
+            } else {
+                parts.push(value);
+            }
+            value = 0;
+        }
+    }
+    parts
+        .iter()
+        .map(u64::to_string)
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+fn decode_string(value: &Tlv) -> String {
+    match value.tag {
+        // BMPString
+        0x1e => {
+            let units: Vec<u16> = value
+                .contents
+                .chunks_exact(2)
+                .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+                .collect();
+            String::from_utf16_lossy(&units)
+        }
+        _ => String::from_utf8_lossy(value.contents).into_owned(),
+    }
+}
+
+/// An X.509 certificate from a code signature's chain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Certificate {
+    der: Vec<u8>,
+    subject: Vec<(String, String)>,
+    raw_subject: Vec<u8>,
+    raw_issuer: Vec<u8>,
+    extensions: Vec<String>,
+}
+
+impl Certificate {
+    /// Parses a DER-encoded certificate.
+    pub fn from_der(der: &[u8]) -> Result<Self, MachOError> {
+        let (certificate, _) = read_tlv(der)?;
+        let parts = elements(expect(Some(&certificate), TAG_SEQUENCE, "a certificate")?)?;
+        let tbs = elements(expect(parts.first(), TAG_SEQUENCE, "a TBS certificate")?)?;
+        // The version is optional and explicitly tagged; skip it to line up the other fields.
+        let fields: Vec<&Tlv> = tbs
+            .iter()
+            .skip_while(|field| field.tag == TAG_CONTEXT_0)
+            .collect();
+        let (issuer, subject) = match fields.as_slice() {
+            [_serial, _algorithm, issuer, _validity, subject, ..] => (issuer, subject),
+            _ => return Err(invalid("truncated TBS certificate")),
+        };
+
+        let mut attributes = Vec::new();
+        for rdn in elements(expect(Some(subject), TAG_SEQUENCE, "a subject name")?)? {
+            for attribute in elements(expect(Some(&rdn), TAG_SET, "a name component")?)? {
+                let pair = elements(expect(Some(&attribute), TAG_SEQUENCE, "an attribute")?)?;
+                let oid = expect(pair.first(), TAG_OID, "an attribute type")?;
+                if let Some(value) = pair.get(1) {
+                    attributes.push((decode_oid(oid), decode_string(value)));
+                }
+            }
+        }
+
+        let mut extensions = Vec::new();
+        if let Some(wrapper) = fields.iter().find(|field| field.tag == TAG_CONTEXT_3) {
+            let (list, _) = read_tlv(wrapper.contents)?;
+            for extension in elements(expect(Some(&list), TAG_SEQUENCE, "extensions")?)? {
+                let parts = elements(expect(Some(&extension), TAG_SEQUENCE, "an extension")?)?;
+                extensions.push(decode_oid(expect(
+                    parts.first(),
+                    TAG_OID,
+                    "an extension id",
+                )?));
+            }
+        }
+
+        Ok(Self {
+            der: certificate.raw.to_vec(),
+            subject: attributes,
+            raw_subject: subject.raw.to_vec(),
+            raw_issuer: issuer.raw.to_vec(),
+            extensions,
+        })
+    }
+
+    /// Returns the DER encoding of the certificate.
+    pub fn der(&self) -> &[u8] {
+        &self.der
+    }
+
+    /// Returns the SHA-1 hash of the certificate, which `certificate ... = H"..."` requirements
+    /// compare against.
+    pub fn sha1(&self) -> [u8; 20] {
+        sha1(&self.der)
+    }
+
+    /// Returns a subject attribute by its short name (`CN`, `OU`, `O`, `C`, `L`, `ST`, `E` or
+    /// `UID`) or by its dotted object identifier.
+    pub fn subject_attribute(&self, name: &str) -> Option<&str> {
+        let oid = match name {
+            "CN" => "2.5.4.3",
+            "C" => "2.5.4.6",
+            "L" => "2.5.4.7",
+            "ST" => "2.5.4.8",
+            "O" => "2.5.4.10",
+            "OU" => "2.5.4.11",
+            "E" => "1.2.840.113549.1.9.1",
+            "UID" => "0.9.2342.19200300.100.1.1",
+            oid => oid,
+        };
+        self.subject
+            .iter()
+            .find(|(candidate, _)| candidate == oid)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Returns whether the certificate has an extension with the given dotted object identifier.
+    pub fn has_extension(&self, oid: &str) -> bool {
+        self.extensions.iter().any(|candidate| candidate == oid)
+    }
+
+    fn is_self_signed(&self) -> bool {
+        self.raw_subject == self.raw_issuer
+    }
+}
+
+/// Extracts the certificates from a CMS `SignedData` blob and orders them from the leaf to the
+/// root.
+pub(crate) fn certificate_chain(cms: &[u8]) -> Result<Vec<Certificate>, MachOError> {
+    if cms.is_empty() {
+        return Ok(Vec::new());
+    }
+    let (content_info, _) = read_tlv(cms)?;
+    let parts = elements(expect(Some(&content_info), TAG_SEQUENCE, "content info")?)?;
+    if decode_oid(expect(parts.first(), TAG_OID, "a content type")?) != OID_SIGNED_DATA {
+        return Err(invalid("not signed data"));
+    }
+    let (signed_data, _) = read_tlv(expect(parts.get(1), TAG_CONTEXT_0, "signed data")?)?;
+    let fields = elements(expect(Some(&signed_data), TAG_SEQUENCE, "signed data")?)?;
+    let certificates = match fields.iter().find(|field| field.tag == TAG_CONTEXT_0) {
+        Some(set) => elements(set.contents)?
+            .iter()
+            .map(|certificate| Certificate::from_der(certificate.raw))
+            .collect::<Result<Vec<_>, _>>()?,
+        None => return Ok(Vec::new()),
+    };
+
+    // Start from the certificate that didn't issue any other and follow the issuers upwards.
+    let issued_another = |candidate: &Certificate| {
+        !candidate.is_self_signed()
+            && certificates
+                .iter()
+                .any(|other| other.raw_issuer == candidate.raw_subject && other != candidate)
+    };
+    let Some(leaf) = certificates
+        .iter()
+        .position(|certificate| !issued_another(certificate) && !certificate.is_self_signed())
+    else {
+        return Ok(certificates);
+    };
+    let mut chain = vec![certificates[leaf].clone()];
+    while chain.len() < certificates.len() {
+        let current = chain.last().unwrap();
+        if current.is_self_signed() {
+            break;
+        }
+        match certificates
+            .iter()
+            .find(|candidate| candidate.raw_subject == current.raw_issuer)
+        {
+            Some(issuer) if !chain.contains(issuer) => chain.push(issuer.clone()),
+            _ => break,
+        }
+    }
+    Ok(chain)
+}
+
+/// DER builders for synthetic certificates used by tests across the crate.
+#[cfg(test)]
+pub(crate) mod fixture {
+    pub(crate) fn tlv(tag: u8, contents: &[u8]) -> Vec<u8> {
+        let mut out = vec![tag];
+        if contents.len() < 0x80 {
+            out.push(contents.len() as u8);
+        } else {
+            let length = (contents.len() as u32).to_be_bytes();
+            let skip = length.iter().take_while(|&&b| b == 0).count();
+            out.push(0x80 | (4 - skip) as u8);
+            out.extend_from_slice(&length[skip..]);
+        }
+        out.extend_from_slice(contents);
+        out
+    }
+
+    pub(crate) fn oid(dotted: &str) -> Vec<u8> {
+        let parts: Vec<u64> = dotted
+            .split('.')
+            .map(|part| part.parse().unwrap())
+            .collect();
+        let mut contents = vec![(parts[0] * 40 + parts[1]) as u8];
+        for &part in &parts[2..] {
+            let mut bytes = vec![(part & 0x7f) as u8];
+            let mut rest = part >> 7;
+            while rest > 0 {
+                bytes.push((rest & 0x7f) as u8 | 0x80);
+                rest >>= 7;
+            }
+            bytes.reverse();
+            contents.extend_from_slice(&bytes);
+        }
+        tlv(0x06, &contents)
+    }
+
+    fn name(attributes: &[(&str, &str)]) -> Vec<u8> {
+        let rdns: Vec<u8> = attributes
+            .iter()
+            .flat_map(|(type_oid, value)| {
+                let attribute = [oid(type_oid), tlv(0x0c, value.as_bytes())].concat();
+                tlv(0x31, &tlv(0x30, &attribute))
+            })
+            .collect();
+        tlv(0x30, &rdns)
+    }
+
+    /// A certificate with the given subject and issuer attributes, as `(oid, value)` pairs.
+    pub(crate) fn certificate(
+        subject: &[(&str, &str)],
+        issuer: &[(&str, &str)],
+        extensions: &[&str],
+    ) -> Vec<u8> {
+        let algorithm = tlv(0x30, &oid("1.2.840.113549.1.1.11"));
+        let mut tbs = [
+            tlv(0xa0, &tlv(0x02, &[2])),
+            tlv(0x02, &[1]),
+            algorithm.clone(),
+            name(issuer),
+            tlv(
+                0x30,
+                &[tlv(0x17, b"200101000000Z"), tlv(0x17, b"300101000000Z")].concat(),
+            ),
+            name(subject),
+            tlv(
+                0x30,
+                &[tlv(0x30, &oid("1.2.840.113549.1.1.1")), tlv(0x03, &[0])].concat(),
+            ),
+        ]
+        .concat();
+        if !extensions.is_empty() {
+            let list: Vec<u8> = extensions
+                .iter()
+                .flat_map(|extension| tlv(0x30, &[oid(extension), tlv(0x04, &[])].concat()))
+                .collect();
+            tbs.extend(tlv(0xa3, &tlv(0x30, &list)));
+        }
+        tlv(
+            0x30,
+            &[tlv(0x30, &tbs), algorithm, tlv(0x03, &[0])].concat(),
+        )
+    }
+
+    /// A CMS `SignedData` blob carrying `certificates` and no signer information.
+    pub(crate) fn cms(certificates: &[Vec<u8>]) -> Vec<u8> {
+        let signed_data = [
+            tlv(0x02, &[1]),
+            tlv(0x31, &[]),
+            tlv(0x30, &oid("1.2.840.113549.1.7.1")),
+            tlv(0xa0, &certificates.concat()),
+            tlv(0x31, &[]),
+        ]
+        .concat();
+        tlv(
+            0x30,
+            &[
+                oid(super::OID_SIGNED_DATA),
+                tlv(0xa0, &tlv(0x30, &signed_data)),
+            ]
+            .concat(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::fixture::*;
+    use super::*;
+
+    #[test]
+    fn test_chain_order_and_attributes() {
+        let root_name = [("2.5.4.3", "Example Root")];
+        let intermediate_name = [("2.5.4.3", "Example Intermediate")];
+        let leaf_name = [
+            ("2.5.4.3", "Developer ID Application: Example (TEAM000001)"),
+            ("2.5.4.11", "TEAM000001"),
+        ];
+        let root = certificate(&root_name, &root_name, &[]);
+        let intermediate = certificate(
+            &intermediate_name,
+            &root_name,
+            &["1.2.840.113635.100.6.2.6"],
+        );
+        let leaf = certificate(
+            &leaf_name,
+            &intermediate_name,
+            &["1.2.840.113635.100.6.1.13"],
+        );
+
+        let chain = certificate_chain(&cms(&[root.clone(), leaf.clone(), intermediate])).unwrap();
+        assert_eq!(chain.len(), 3);
+        assert_eq!(chain[0].subject_attribute("OU"), Some("TEAM000001"));
+        assert!(chain[0].has_extension("1.2.840.113635.100.6.1.13"));
+        assert!(chain[1].has_extension("1.2.840.113635.100.6.2.6"));
+        assert_eq!(chain[2].subject_attribute("CN"), Some("Example Root"));
+        assert_eq!(chain[2].sha1(), sha1(&root));
+
+        assert!(certificate_chain(&[]).unwrap().is_empty());
+        assert!(certificate_chain(&leaf[..10]).is_err());
+    }
+}
@@ -0,0 +1,161 @@
+//! Property lists linked into an executable's `__TEXT` segment.
+//!
+//! Command-line tools can't have an `Info.plist` next to them, so the linker embeds it in a
+//! `__TEXT,__info_plist` section (`-sectcreate __TEXT __info_plist Info.plist`). Helpers
+//! installed with `SMJobBless` also embed their launchd property list in `__TEXT,__launchd_plist`.
+
+use super::{MachO, MachOError, MachOFile};
+use crate::plist::Value;
+use crate::{InfoPlist, LaunchdPlist};
+
+const INFO_PLIST_SECTION: &str = "__info_plist";
+const LAUNCHD_PLIST_SECTION: &str = "__launchd_plist";
+
+impl MachO {
+    /// Parses the property list embedded in `__TEXT,<section>`, if there is one.
+    fn embedded_plist(&self, section: &'static str) -> Result<Option<Value>, MachOError> {
+        let Some(data) = self.section_data("__TEXT", section) else {
+            return Ok(None);
+        };
+        // XML sections are often NUL-terminated or padded. A binary plist's trailer can end in a
+        // zero byte, so those are left alone.
+        let end = if data.starts_with(b"bplist") {
+            data.len()
+        } else {
+            data.iter()
+                .rposition(|&b| b != 0)
+                .map_or(0, |last| last + 1)
+        };
+        Value::from_bytes(&data[..end])
+            .map(Some)
+            .map_err(|source| MachOError::EmbeddedPlist { section, source })
+    }
+
+    /// Returns the `Info.plist` embedded in the `__TEXT,__info_plist` section, if present.
+    pub fn embedded_info_plist(&self) -> Result<Option<InfoPlist>, MachOError> {
+        self.embedded_plist(INFO_PLIST_SECTION)?
+            .map(InfoPlist::from_value)
+            .transpose()
+            .map_err(|source| MachOError::EmbeddedPlist {
+                section: INFO_PLIST_SECTION,
+                source,
+            })
+    }
+
+    /// Returns the launchd property list embedded in the `__TEXT,__launchd_plist` section, if
+    /// present.
+    pub fn embedded_launchd_plist(&self) -> Result<Option<LaunchdPlist>, MachOError> {
+        self.embedded_plist(LAUNCHD_PLIST_SECTION)?
+            .map(LaunchdPlist::from_value)
+            .transpose()
+            .map_err(|source| MachOError::EmbeddedPlist {
+                section: LAUNCHD_PLIST_SECTION,
+                source,
+            })
+    }
+}
+
+impl MachOFile {
+    /// Returns the embedded `Info.plist` of the first architecture that has one.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use smappservice_rs::macho::MachOFile;
+    ///
+    /// let helper = MachOFile::from_file("MyApp.app/Contents/MacOS/helper").unwrap();
+    /// if let Some(info) = helper.embedded_info_plist().unwrap() {
+    ///     println!("identified as {:?}", info.bundle_identifier);
+    /// }
+    /// ```
+    pub fn embedded_info_plist(&self) -> Result<Option<InfoPlist>, MachOError> {
+        for slice in self.slices() {
+            if let Some(info) = slice.embedded_info_plist()? {
+                return Ok(Some(info));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Returns the embedded launchd property list of the first architecture that has one.
+    ///
+    /// # Examples
+    ///
+    /// Moving an `SMJobBless` helper to a bundled daemon plist:
+    ///
+    /// ```rust,no_run
+    /// use smappservice_rs::macho::MachOFile;
+    /// use smappservice_rs::LaunchdPlist;
+    ///
+    /// let helper = MachOFile::from_file("MyApp.app/Contents/Library/LaunchServices/com.example.helper").unwrap();
+    /// let legacy = helper.embedded_launchd_plist().unwrap().expect("not a blessed helper");
+    /// let daemon = LaunchdPlist {
+    ///     program: None,
+    ///     bundle_program: Some("Contents/MacOS/com.example.helper".to_string()),
+    ///     ..legacy
+    /// };
+    /// daemon
+    ///     .write_to_file(format!("MyApp.app/Contents/Library/LaunchDaemons/{}.plist", daemon.label))
+    ///     .unwrap();
+    /// ```
+    pub fn embedded_launchd_plist(&self) -> Result<Option<LaunchdPlist>, MachOError> {
+        for slice in self.slices() {
+            if let Some(plist) = slice.embedded_launchd_plist()? {
+                return Ok(Some(plist));
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::fixture::{fat, FixtureMachO, CPU_TYPE_ARM64, CPU_TYPE_X86_64};
+    use super::*;
+
+    #[test]
+    fn test_embedded_plists() {
+        let info = InfoPlist {
+            bundle_identifier: Some("com.example.helper".to_string()),
+            bundle_version: Some("3".to_string()),
+            ..InfoPlist::default()
+        };
+        let launchd = LaunchdPlist {
+            program: Some("/Library/PrivilegedHelperTools/com.example.helper".to_string()),
+            ..LaunchdPlist::new("com.example.helper")
+        };
+        let helper = FixtureMachO {
+            sections: vec![
+                ("__text", vec![0xc3; 4]),
+                (INFO_PLIST_SECTION, info.to_xml().into_bytes()),
+                (LAUNCHD_PLIST_SECTION, launchd.to_value().to_binary()),
+            ],
+            ..FixtureMachO::new(CPU_TYPE_ARM64)
+        }
+        .build();
+        let file =
+            MachOFile::parse(&fat(&[FixtureMachO::new(CPU_TYPE_X86_64).build(), helper])).unwrap();
+
+        assert_eq!(file.slices()[0].embedded_info_plist().unwrap(), None);
+        assert_eq!(file.embedded_info_plist().unwrap(), Some(info));
+        assert_eq!(file.embedded_launchd_plist().unwrap(), Some(launchd));
+    }
+
+    #[test]
+    fn test_invalid_embedded_plist() {
+        let binary = FixtureMachO {
+            sections: vec![(INFO_PLIST_SECTION, b"<plist><dict>".to_vec())],
+            ..FixtureMachO::new(CPU_TYPE_ARM64)
+        }
+        .build();
+        let file = MachOFile::parse(&binary).unwrap();
+        assert!(matches!(
+            file.embedded_info_plist(),
+            Err(MachOError::EmbeddedPlist {
+                section: "__info_plist",
+                ..
+            })
+        ));
+        assert_eq!(file.embedded_launchd_plist().unwrap(), None);
+    }
+}
@@ -0,0 +1,838 @@
+//! A reader for Mach-O executables and the code signatures embedded in them.
+//!
+//! Both thin and universal ("fat") binaries are supported. Nothing here depends on macOS, so
+//! bundles can be inspected from any platform, for example in CI before notarization.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use smappservice_rs::macho::MachOFile;
+//!
+//! let file = MachOFile::for_bundle("/Applications/MyApp.app").unwrap();
+//! for slice in file.slices() {
+//!     match slice.code_signature().unwrap() {
+//!         Some(signature) => println!(
+//!             "{}: {} (team {:?}, flags {})",
+//!             slice.architecture(),
+//!             signature.identifier(),
+//!             signature.team_id(),
+//!             signature.flags()
+//!         ),
+//!         None => println!("{}: not signed", slice.architecture()),
+//!     }
+//! }
+//! ```
+
+pub(crate) mod certificate;
+mod embedded;
+mod signature;
+
+use std::path::Path;
+
+use thiserror::Error;
+
+use crate::bundle::bundle_executable;
+use crate::plist::PlistError;
+
+pub use certificate::Certificate;
+pub use signature::{CodeDirectory, CodeSignature, CodeSignatureFlags, HashType};
+
+const FAT_MAGIC: u32 = 0xcafe_babe;
+const FAT_MAGIC_64: u32 = 0xcafe_babf;
+const MH_MAGIC: u32 = 0xfeed_face;
+const MH_MAGIC_64: u32 = 0xfeed_facf;
+const MH_CIGAM: u32 = 0xcefa_edfe;
+const MH_CIGAM_64: u32 = 0xcffa_edfe;
+
+const LC_SEGMENT: u32 = 0x1;
+const LC_SEGMENT_64: u32 = 0x19;
+const LC_CODE_SIGNATURE: u32 = 0x1d;
+
+/// More architectures than any real universal binary has. Java class files share the fat magic
+/// but store a version number here, which is always larger.
+const MAX_FAT_ARCHS: u32 = 32;
+
+/// Errors that can occur while reading a Mach-O file or its code signature.
+#[derive(Debug, Error)]
+pub enum MachOError {
+    /// The file couldn't be read.
+    #[error("failed to read the executable: {0}")]
+    Io(#[from] std::io::Error),
+
+    /// The data doesn't start with a Mach-O or universal binary header.
+    #[error("not a Mach-O file")]
+    NotMachO,
+
+    /// A header, load command or offset is inconsistent with the file.
+    #[error("malformed Mach-O file: {0}")]
+    Malformed(String),
+
+    /// The embedded code signature is malformed.
+    #[error("malformed code signature: {0}")]
+    InvalidSignature(String),
+
+    /// The executable has no embedded code signature.
+    #[error("the executable is not signed")]
+    Unsigned,
+
+    /// The embedded entitlements aren't a valid property list dictionary.
+    #[error("invalid entitlements: {0}")]
+    Entitlements(#[source] PlistError),
+
+    /// An embedded `__TEXT,__info_plist` or `__TEXT,__launchd_plist` section isn't a valid
+    /// property list.
+    #[error("invalid property list in __TEXT,{section}: {source}")]
+    EmbeddedPlist {
+        /// The section name.
+        section: &'static str,
+        /// The underlying error.
+        source: PlistError,
+    },
+}
+
+/// Bounds-checked integer access to a byte slice of either endianness.
+#[derive(Debug, Clone, Copy)]
+struct Reader<'a> {
+    data: &'a [u8],
+    big_endian: bool,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&self, offset: usize, len: usize) -> Option<&'a [u8]> {
+        self.data.get(offset..offset.checked_add(len)?)
+    }
+
+    fn u32(&self, offset: usize) -> Option<u32> {
+        let bytes = self.bytes(offset, 4)?.try_into().ok()?;
+        Some(if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    }
+
+    fn u64(&self, offset: usize) -> Option<u64> {
+        let bytes = self.bytes(offset, 8)?.try_into().ok()?;
+        Some(if self.big_endian {
+            u64::from_be_bytes(bytes)
+        } else {
+            u64::from_le_bytes(bytes)
+        })
+    }
+
+    /// Reads a fixed-size name field, which is NUL-padded rather than NUL-terminated.
+    fn name(&self, offset: usize) -> Option<String> {
+        let bytes = self.bytes(offset, 16)?;
+        let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+        Some(String::from_utf8_lossy(&bytes[..end]).into_owned())
+    }
+}
+
+fn truncated(what: &str) -> MachOError {
+    MachOError::Malformed(format!("{what} extends past the end of the file"))
+}
+
+/// A segment load command and the sections in it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    /// The segment name, such as `__TEXT`.
+    pub name: String,
+
+    /// The address the segment is mapped at.
+    pub vm_address: u64,
+
+    /// The size of the segment in memory.
+    pub vm_size: u64,
+
+    /// The offset of the segment's contents in the file.
+    pub file_offset: u64,
+
+    /// The size of the segment's contents in the file.
+    pub file_size: u64,
+
+    /// The sections in the segment.
+    pub sections: Vec<Section>,
+}
+
+/// A section within a [`Segment`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section {
+    /// The section name, such as `__text`.
+    pub name: String,
+
+    /// The name of the segment the section belongs to.
+    pub segment_name: String,
+
+    /// The address the section is mapped at.
+    pub address: u64,
+
+    /// The size of the section.
+    pub size: u64,
+
+    /// The offset of the section's contents in the file, or 0 for zero-fill sections.
+    pub offset: u32,
+}
+
+/// A single-architecture Mach-O image, either a whole thin file or one slice of a universal
+/// binary.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MachO {
+    /// The `cputype` field of the header.
+    pub cpu_type: u32,
+
+    /// The `cpusubtype` field of the header.
+    pub cpu_subtype: u32,
+
+    /// The `filetype` field of the header, for example 2 for an executable.
+    pub file_type: u32,
+
+    /// Whether the image uses the 64-bit header and load command layouts.
+    pub is_64: bool,
+
+    /// The segments, in load command order.
+    pub segments: Vec<Segment>,
+
+    code_signature: Option<(usize, usize)>,
+    data: Vec<u8>,
+}
+
+impl MachO {
+    /// Parses a thin Mach-O image.
+    pub fn parse(data: &[u8]) -> Result<Self, MachOError> {
+        let le = Reader {
+            data,
+            big_endian: false,
+        };
+        let (big_endian, is_64) = match le.u32(0).ok_or(MachOError::NotMachO)? {
+            MH_MAGIC => (false, false),
+            MH_MAGIC_64 => (false, true),
+            MH_CIGAM => (true, false),
+            MH_CIGAM_64 => (true, true),
+            _ => return Err(MachOError::NotMachO),
+        };
+        let reader = Reader { data, big_endian };
+        let header = |offset| reader.u32(offset).ok_or_else(|| truncated("the header"));
+        let (cpu_type, cpu_subtype, file_type) = (header(4)?, header(8)?, header(12)?);
+        let command_count = header(16)?;
+
+        let mut segments = Vec::new();
+        let mut code_signature = None;
+        let mut offset = if is_64 { 32 } else { 28 };
+        for index in 0..command_count {
+            let command = |field: usize| {
+                reader
+                    .u32(offset + field)
+                    .ok_or_else(|| truncated(&format!("load command {index}")))
+            };
+            let (cmd, size) = (command(0)?, command(4)? as usize);
+            if size < 8 || reader.bytes(offset, size).is_none() {
+                return Err(MachOError::Malformed(format!(
+                    "load command {index} has invalid size {size}"
+                )));
+            }
+            match cmd {
+                LC_SEGMENT | LC_SEGMENT_64 => {
+                    segments.push(parse_segment(&reader, offset, size, cmd == LC_SEGMENT_64)?);
+                }
+                LC_CODE_SIGNATURE => {
+                    let (data_offset, data_size) = (command(8)? as usize, command(12)? as usize);
+                    if reader.bytes(data_offset, data_size).is_none() {
+                        return Err(truncated("the code signature"));
+                    }
+                    code_signature = Some((data_offset, data_size));
+                }
+                _ => {}
+            }
+            offset += size;
+        }
+
+        Ok(Self {
+            cpu_type,
+            cpu_subtype,
+            file_type,
+            is_64,
+            segments,
+            code_signature,
+            data: data.to_vec(),
+        })
+    }
+
+    /// Returns the conventional name of the image's architecture, such as `arm64` or `x86_64`.
+    pub fn architecture(&self) -> &'static str {
+        const CPU_ARCH_ABI64: u32 = 0x0100_0000;
+        const CPU_ARCH_ABI64_32: u32 = 0x0200_0000;
+        const CPU_SUBTYPE_MASK: u32 = 0xff00_0000;
+        let subtype = self.cpu_subtype & !CPU_SUBTYPE_MASK;
+        match self.cpu_type {
+            7 => "i386",
+            t if t == 7 | CPU_ARCH_ABI64 && subtype == 8 => "x86_64h",
+            t if t == 7 | CPU_ARCH_ABI64 => "x86_64",
+            12 => "arm",
+            t if t == 12 | CPU_ARCH_ABI64 && subtype == 2 => "arm64e",
+            t if t == 12 | CPU_ARCH_ABI64 => "arm64",
+            t if t == 12 | CPU_ARCH_ABI64_32 => "arm64_32",
+            18 => "ppc",
+            t if t == 18 | CPU_ARCH_ABI64 => "ppc64",
+            _ => "unknown",
+        }
+    }
+
+    /// Returns the raw bytes of the image.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Returns the section with the given segment and section names.
+    pub fn section(&self, segment: &str, name: &str) -> Option<&Section> {
+        self.segments
+            .iter()
+            .filter(|candidate| candidate.name == segment)
+            .flat_map(|candidate| &candidate.sections)
+            .find(|section| section.name == name)
+    }
+
+    /// Returns the contents of the section with the given segment and section names. Returns
+    /// `None` if there is no such section or it is zero-fill.
+    pub fn section_data(&self, segment: &str, name: &str) -> Option<&[u8]> {
+        let section = self.section(segment, name)?;
+        if section.offset == 0 {
+            return None;
+        }
+        let start = section.offset as usize;
+        self.data
+            .get(start..start.checked_add(usize::try_from(section.size).ok()?)?)
+    }
+
+    /// Returns the raw embedded signature referenced by `LC_CODE_SIGNATURE`, if the image is
+    /// signed.
+    pub fn code_signature_data(&self) -> Option<&[u8]> {
+        let (offset, size) = self.code_signature?;
+        self.data.get(offset..offset + size)
+    }
+
+    /// Parses the embedded code signature. Returns `Ok(None)` for an unsigned image.
+    pub fn code_signature(&self) -> Result<Option<CodeSignature>, MachOError> {
+        self.code_signature_data()
+            .map(CodeSignature::parse)
+            .transpose()
+    }
+}
+
+fn parse_segment(
+    reader: &Reader,
+    offset: usize,
+    size: usize,
+    is_64: bool,
+) -> Result<Segment, MachOError> {
+    let field32 = |at| reader.u32(offset + at).map(u64::from);
+    let field64 = |at| reader.u64(offset + at);
+    let segment = if is_64 {
+        (|| {
+            Some(Segment {
+                name: reader.name(offset + 8)?,
+                vm_address: field64(24)?,
+                vm_size: field64(32)?,
+                file_offset: field64(40)?,
+                file_size: field64(48)?,
+                sections: Vec::new(),
+            })
+        })()
+    } else {
+        (|| {
+            Some(Segment {
+                name: reader.name(offset + 8)?,
+                vm_address: field32(24)?,
+                vm_size: field32(28)?,
+                file_offset: field32(32)?,
+                file_size: field32(36)?,
+                sections: Vec::new(),
+            })
+        })()
+    };
+    let mut segment = segment.ok_or_else(|| truncated("a segment command"))?;
+
+    let (header_size, section_size, count_at) = if is_64 { (72, 80, 64) } else { (56, 68, 48) };
+    let count = reader.u32(offset + count_at).unwrap_or_default() as usize;
+    if count
+        .checked_mul(section_size)
+        .and_then(|len| len.checked_add(header_size))
+        .is_none_or(|len| len > size)
+    {
+        return Err(MachOError::Malformed(format!(
+            "segment {} has more sections than fit in its load command",
+            segment.name
+        )));
+    }
+    for index in 0..count {
+        let at = offset + header_size + index * section_size;
+        let section = if is_64 {
+            Section {
+                name: reader.name(at).unwrap_or_default(),
+                segment_name: reader.name(at + 16).unwrap_or_default(),
+                address: reader.u64(at + 32).unwrap_or_default(),
+                size: reader.u64(at + 40).unwrap_or_default(),
+                offset: reader.u32(at + 48).unwrap_or_default(),
+            }
+        } else {
+            Section {
+                name: reader.name(at).unwrap_or_default(),
+                segment_name: reader.name(at + 16).unwrap_or_default(),
+                address: reader.u32(at + 32).map(u64::from).unwrap_or_default(),
+                size: reader.u32(at + 36).map(u64::from).unwrap_or_default(),
+                offset: reader.u32(at + 40).unwrap_or_default(),
+            }
+        };
+        segment.sections.push(section);
+    }
+    Ok(segment)
+}
+
+/// A Mach-O file: a single image, or a universal binary containing one image per architecture.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MachOFile {
+    fat: bool,
+    slices: Vec<MachO>,
+}
+
+impl MachOFile {
+    /// Parses a thin or universal Mach-O file.
+    pub fn parse(data: &[u8]) -> Result<Self, MachOError> {
+        let reader = Reader {
+            data,
+            big_endian: true,
+        };
+        let magic = reader.u32(0).ok_or(MachOError::NotMachO)?;
+        if magic != FAT_MAGIC && magic != FAT_MAGIC_64 {
+            return Ok(Self {
+                fat: false,
+                slices: vec![MachO::parse(data)?],
+            });
+        }
+        let count = reader.u32(4).ok_or_else(|| truncated("the fat header"))?;
+        if count > MAX_FAT_ARCHS {
+            return Err(MachOError::NotMachO);
+        }
+
+        let mut slices = Vec::with_capacity(count as usize);
+        for index in 0..count as usize {
+            let (offset, size) = if magic == FAT_MAGIC_64 {
+                let at = 8 + index * 32;
+                (reader.u64(at + 8), reader.u64(at + 16))
+            } else {
+                let at = 8 + index * 20;
+                (
+                    reader.u32(at + 8).map(u64::from),
+                    reader.u32(at + 12).map(u64::from),
+                )
+            };
+            let (offset, size) = offset
+                .zip(size)
+                .ok_or_else(|| truncated("the fat architecture table"))?;
+            let slice = usize::try_from(offset)
+                .ok()
+                .zip(usize::try_from(size).ok())
+                .and_then(|(offset, size)| reader.bytes(offset, size))
+                .ok_or_else(|| truncated(&format!("architecture {index}")))?;
+            slices.push(MachO::parse(slice)?);
+        }
+        Ok(Self { fat: true, slices })
+    }
+
+    /// Reads and parses the Mach-O file at `path`.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, MachOError> {
+        Self::parse(&std::fs::read(path)?)
+    }
+
+    /// Reads the main executable of the bundle at `bundle`, as named by its `CFBundleExecutable`.
+    pub fn for_bundle(bundle: impl AsRef<Path>) -> Result<Self, MachOError> {
+        Self::from_file(bundle_executable(bundle.as_ref()))
+    }
+
+    /// Returns whether the file is a universal binary.
+    pub fn is_fat(&self) -> bool {
+        self.fat
+    }
+
+    /// Returns the images in the file, in the order they're stored.
+    pub fn slices(&self) -> &[MachO] {
+        &self.slices
+    }
+
+    /// Returns the image for the named architecture, such as `arm64`.
+    pub fn slice(&self, architecture: &str) -> Option<&MachO> {
+        self.slices
+            .iter()
+            .find(|slice| slice.architecture() == architecture)
+    }
+}
+
+/// Builders for synthetic Mach-O files used by tests across the crate.
+#[cfg(test)]
+pub(crate) mod fixture {
+    use super::*;
+
+    pub(crate) const CPU_TYPE_X86_64: u32 = 0x0100_0007;
+    pub(crate) const CPU_TYPE_ARM64: u32 = 0x0100_000c;
+
+    /// The contents of an embedded signature.
+    #[derive(Clone)]
+    pub(crate) struct FixtureSignature {
+        pub(crate) identifier: String,
+        pub(crate) team_id: Option<String>,
+        pub(crate) flags: u32,
+        pub(crate) hash_type: u8,
+        /// Special slot hashes, starting with slot 1.
+        pub(crate) special_slots: Vec<Vec<u8>>,
+        pub(crate) requirements: Option<Vec<u8>>,
+        pub(crate) entitlements: Option<String>,
+        /// DER certificates for the CMS blob, which is left empty when there are none.
+        pub(crate) certificates: Vec<Vec<u8>>,
+    }
+
+    impl FixtureSignature {
+        pub(crate) fn new(identifier: &str) -> Self {
+            Self {
+                identifier: identifier.to_string(),
+                team_id: None,
+                flags: 0,
+                hash_type: 2,
+                special_slots: Vec::new(),
+                requirements: None,
+                entitlements: None,
+                certificates: Vec::new(),
+            }
+        }
+
+        pub(crate) fn code_directory(&self) -> Vec<u8> {
+            const HEADER: usize = 88;
+            let hash_size = match self.hash_type {
+                1 | 3 => 20,
+                4 => 48,
+                _ => 32,
+            };
+            let mut strings = self.identifier.as_bytes().to_vec();
+            strings.push(0);
+            let team_offset = self.team_id.as_ref().map_or(0, |team| {
+                let offset = HEADER + strings.len();
+                strings.extend_from_slice(team.as_bytes());
+                strings.push(0);
+                offset
+            });
+            let hash_offset = HEADER + strings.len() + self.special_slots.len() * hash_size;
+            let length = hash_offset;
+
+            let mut cd = Vec::new();
+            for value in [
+                0xfade_0c02,
+                length as u32,
+                0x20400,
+                self.flags,
+                hash_offset as u32,
+                HEADER as u32,
+                self.special_slots.len() as u32,
+                0,
+                0,
+            ] {
+                cd.extend_from_slice(&u32::to_be_bytes(value));
+            }
+            cd.extend_from_slice(&[hash_size as u8, self.hash_type, 0, 12]);
+            cd.extend_from_slice(&[0; 8]);
+            cd.extend_from_slice(&(team_offset as u32).to_be_bytes());
+            cd.extend_from_slice(&[0; 36]);
+            cd.extend_from_slice(&strings);
+            for slot in self.special_slots.iter().rev() {
+                let mut hash = slot.clone();
+                hash.resize(hash_size, 0);
+                cd.extend_from_slice(&hash);
+            }
+            cd
+        }
+
+        pub(crate) fn blob(&self) -> Vec<u8> {
+            let mut blobs = vec![(0u32, self.code_directory())];
+            if let Some(requirements) = &self.requirements {
+                blobs.push((2, requirements.clone()));
+            }
+            if let Some(entitlements) = &self.entitlements {
+                blobs.push((5, wrap(0xfade_7171, entitlements.as_bytes())));
+            }
+            let cms = if self.certificates.is_empty() {
+                Vec::new()
+            } else {
+                super::certificate::fixture::cms(&self.certificates)
+            };
+            blobs.push((0x10000, wrap(0xfade_0b01, &cms)));
+
+            let mut offset = 12 + blobs.len() * 8;
+            let mut index = Vec::new();
+            let mut payload = Vec::new();
+            for (slot, blob) in &blobs {
+                index.extend_from_slice(&slot.to_be_bytes());
+                index.extend_from_slice(&(offset as u32).to_be_bytes());
+                offset += blob.len();
+                payload.extend_from_slice(blob);
+            }
+            let mut super_blob = Vec::new();
+            super_blob.extend_from_slice(&0xfade_0cc0u32.to_be_bytes());
+            super_blob.extend_from_slice(&(offset as u32).to_be_bytes());
+            super_blob.extend_from_slice(&(blobs.len() as u32).to_be_bytes());
+            super_blob.extend_from_slice(&index);
+            super_blob.extend_from_slice(&payload);
+            super_blob
+        }
+    }
+
+    pub(crate) fn wrap(magic: u32, payload: &[u8]) -> Vec<u8> {
+        let mut blob = magic.to_be_bytes().to_vec();
+        blob.extend_from_slice(&((payload.len() + 8) as u32).to_be_bytes());
+        blob.extend_from_slice(payload);
+        blob
+    }
+
+    /// A 64-bit little-endian executable with a `__TEXT` segment holding `sections`.
+    pub(crate) struct FixtureMachO {
+        pub(crate) cpu_type: u32,
+        pub(crate) cpu_subtype: u32,
+        pub(crate) sections: Vec<(&'static str, Vec<u8>)>,
+        pub(crate) signature: Option<FixtureSignature>,
+    }
+
+    impl FixtureMachO {
+        pub(crate) fn new(cpu_type: u32) -> Self {
+            Self {
+                cpu_type,
+                cpu_subtype: 0,
+                sections: Vec::new(),
+                signature: None,
+            }
+        }
+
+        pub(crate) fn build(&self) -> Vec<u8> {
+            let text_size = 72 + 80 * self.sections.len();
+            let signed = self.signature.is_some();
+            let commands_size = text_size + 72 + if signed { 16 } else { 0 };
+            let data_start = (32 + commands_size).next_multiple_of(16);
+
+            let mut section_data = Vec::new();
+            let mut section_offsets = Vec::new();
+            for (_, data) in &self.sections {
+                section_offsets.push(data_start + section_data.len());
+                section_data.extend_from_slice(data);
+                section_data.resize(section_data.len().next_multiple_of(16), 0);
+            }
+            let linkedit_start = data_start + section_data.len();
+            let signature = self
+                .signature
+                .as_ref()
+                .map(FixtureSignature::blob)
+                .unwrap_or_default();
+
+            let mut out = Vec::new();
+            let put32 = |out: &mut Vec<u8>, value: u32| out.extend_from_slice(&value.to_le_bytes());
+            for value in [
+                MH_MAGIC_64,
+                self.cpu_type,
+                self.cpu_subtype,
+                2,
+                if signed { 3 } else { 2 },
+                commands_size as u32,
+                0,
+                0,
+            ] {
+                put32(&mut out, value);
+            }
+            let name = |name: &str| {
+                let mut field = [0u8; 16];
+                field[..name.len()].copy_from_slice(name.as_bytes());
+                field
+            };
+            let segment = |out: &mut Vec<u8>,
+                           segname: &str,
+                           size: usize,
+                           fileoff: usize,
+                           filesize: usize,
+                           nsects: usize| {
+                out.extend_from_slice(&LC_SEGMENT_64.to_le_bytes());
+                out.extend_from_slice(&(size as u32).to_le_bytes());
+                out.extend_from_slice(&name(segname));
+                for value in [
+                    0x1_0000_0000u64 + fileoff as u64,
+                    filesize as u64,
+                    fileoff as u64,
+                    filesize as u64,
+                ] {
+                    out.extend_from_slice(&value.to_le_bytes());
+                }
+                for value in [5u32, 5, nsects as u32, 0] {
+                    out.extend_from_slice(&value.to_le_bytes());
+                }
+            };
+            segment(
+                &mut out,
+                "__TEXT",
+                text_size,
+                0,
+                linkedit_start,
+                self.sections.len(),
+            );
+            for ((section, data), offset) in self.sections.iter().zip(&section_offsets) {
+                out.extend_from_slice(&name(section));
+                out.extend_from_slice(&name("__TEXT"));
+                out.extend_from_slice(&(0x1_0000_0000u64 + *offset as u64).to_le_bytes());
+                out.extend_from_slice(&(data.len() as u64).to_le_bytes());
+                out.extend_from_slice(&(*offset as u32).to_le_bytes());
+                out.extend_from_slice(&[0; 28]);
+            }
+            segment(
+                &mut out,
+                "__LINKEDIT",
+                72,
+                linkedit_start,
+                signature.len(),
+                0,
+            );
+            if signed {
+                for value in [
+                    LC_CODE_SIGNATURE,
+                    16,
+                    linkedit_start as u32,
+                    signature.len() as u32,
+                ] {
+                    put32(&mut out, value);
+                }
+            }
+            out.resize(data_start, 0);
+            out.extend_from_slice(&section_data);
+            out.extend_from_slice(&signature);
+            out
+        }
+    }
+
+    /// Wraps thin images in a universal binary.
+    pub(crate) fn fat(slices: &[Vec<u8>]) -> Vec<u8> {
+        const ALIGN: usize = 4096;
+        let mut out = Vec::new();
+        out.extend_from_slice(&FAT_MAGIC.to_be_bytes());
+        out.extend_from_slice(&(slices.len() as u32).to_be_bytes());
+        let mut offset = (8 + slices.len() * 20).next_multiple_of(ALIGN);
+        for slice in slices {
+            let header = MachO::parse(slice).unwrap();
+            for value in [
+                header.cpu_type,
+                header.cpu_subtype,
+                offset as u32,
+                slice.len() as u32,
+                12,
+            ] {
+                out.extend_from_slice(&value.to_be_bytes());
+            }
+            offset = (offset + slice.len()).next_multiple_of(ALIGN);
+        }
+        for slice in slices {
+            out.resize(out.len().next_multiple_of(ALIGN), 0);
+            out.extend_from_slice(slice);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::fixture::*;
+    use super::*;
+    use crate::digest::sha256;
+    use crate::plist::Value;
+
+    const ENTITLEMENTS: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<plist version="1.0"><dict>
+<key>com.apple.security.app-sandbox</key><true/>
+</dict></plist>"#;
+
+    #[test]
+    fn test_thin_signature() {
+        let signature = FixtureSignature {
+            team_id: Some("ABCDE12345".to_string()),
+            flags: CodeSignatureFlags::RUNTIME.0,
+            entitlements: Some(ENTITLEMENTS.to_string()),
+            ..FixtureSignature::new("com.example.helper")
+        };
+        let binary = FixtureMachO {
+            signature: Some(signature.clone()),
+            sections: vec![("__text", vec![0xc3; 10])],
+            ..FixtureMachO::new(CPU_TYPE_ARM64)
+        }
+        .build();
+
+        let file = MachOFile::parse(&binary).unwrap();
+        assert!(!file.is_fat());
+        let slice = &file.slices()[0];
+        assert_eq!(slice.architecture(), "arm64");
+        assert_eq!(slice.section("__TEXT", "__text").unwrap().size, 10);
+
+        let parsed = slice.code_signature().unwrap().unwrap();
+        assert_eq!(parsed.identifier(), "com.example.helper");
+        assert_eq!(parsed.team_id(), Some("ABCDE12345"));
+        assert!(parsed.has_hardened_runtime());
+        assert!(!parsed.is_adhoc());
+        assert_eq!(
+            parsed.cdhash(),
+            Some(&sha256(&signature.code_directory())[..20])
+        );
+        assert_eq!(
+            parsed.entitlements.as_ref().unwrap()["com.apple.security.app-sandbox"],
+            Value::Boolean(true)
+        );
+    }
+
+    #[test]
+    fn test_fat_binary() {
+        let unsigned = FixtureMachO::new(CPU_TYPE_X86_64).build();
+        let adhoc = FixtureMachO {
+            signature: Some(FixtureSignature {
+                flags: CodeSignatureFlags::ADHOC.0,
+                hash_type: 1,
+                ..FixtureSignature::new("helper-5555494")
+            }),
+            cpu_subtype: 2,
+            ..FixtureMachO::new(CPU_TYPE_ARM64)
+        }
+        .build();
+
+        let file = MachOFile::parse(&fat(&[unsigned, adhoc])).unwrap();
+        assert!(file.is_fat());
+        assert_eq!(file.slices().len(), 2);
+        assert_eq!(
+            file.slice("x86_64").unwrap().code_signature().unwrap(),
+            None
+        );
+
+        let signature = file
+            .slice("arm64e")
+            .unwrap()
+            .code_signature()
+            .unwrap()
+            .unwrap();
+        assert!(signature.is_adhoc());
+        assert_eq!(signature.team_id(), None);
+        assert_eq!(signature.code_directory().hash_type, HashType::Sha1);
+        assert_eq!(signature.cdhash().map(<[u8]>::len), Some(20));
+    }
+
+    #[test]
+    fn test_invalid_files() {
+        assert!(matches!(
+            MachOFile::parse(b"#!/bin/sh\n"),
+            Err(MachOError::NotMachO)
+        ));
+        let binary = FixtureMachO::new(CPU_TYPE_ARM64).build();
+        assert!(matches!(
+            MachOFile::parse(&binary[..40]),
+            Err(MachOError::Malformed(_))
+        ));
+        // A Java class file: the fat magic followed by a version number.
+        assert!(matches!(
+            MachOFile::parse(&[0xca, 0xfe, 0xba, 0xbe, 0, 0, 0, 52]),
+            Err(MachOError::NotMachO)
+        ));
+    }
+}
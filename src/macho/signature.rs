@@ -0,0 +1,551 @@
+//! Parsing of embedded code signatures.
+
+use std::fmt;
+
+use super::certificate::{certificate_chain, Certificate};
+use super::{MachOError, Reader};
+use crate::digest::{hex, sha1, sha256, sha384};
+use crate::plist::{Dictionary, Value};
+
+const CSMAGIC_EMBEDDED_SIGNATURE: u32 = 0xfade_0cc0;
+const CSMAGIC_CODEDIRECTORY: u32 = 0xfade_0c02;
+const CSMAGIC_EMBEDDED_ENTITLEMENTS: u32 = 0xfade_7171;
+const CSMAGIC_BLOBWRAPPER: u32 = 0xfade_0b01;
+
+const CSSLOT_CODEDIRECTORY: u32 = 0;
+const CSSLOT_REQUIREMENTS: u32 = 2;
+const CSSLOT_ENTITLEMENTS: u32 = 5;
+const CSSLOT_ALTERNATE_CODEDIRECTORIES: u32 = 0x1000;
+const CSSLOT_ALTERNATE_CODEDIRECTORY_MAX: u32 = 5;
+const CSSLOT_SIGNATURESLOT: u32 = 0x10000;
+
+fn invalid(message: impl Into<String>) -> MachOError {
+    MachOError::InvalidSignature(message.into())
+}
+
+/// The hash algorithm of a [`CodeDirectory`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HashType {
+    /// SHA-1, used by signatures made for macOS 10.11 and earlier.
+    Sha1,
+
+    /// SHA-256.
+    Sha256,
+
+    /// SHA-256 truncated to 20 bytes.
+    Sha256Truncated,
+
+    /// SHA-384.
+    Sha384,
+
+    /// A hash type this crate doesn't know.
+    Unknown(u8),
+}
+
+impl HashType {
+    fn from_raw(value: u8) -> Self {
+        match value {
+            1 => HashType::Sha1,
+            2 => HashType::Sha256,
+            3 => HashType::Sha256Truncated,
+            4 => HashType::Sha384,
+            other => HashType::Unknown(other),
+        }
+    }
+
+    /// Hashes `data` with this algorithm, or returns `None` if it isn't supported.
+    pub(crate) fn digest(&self, data: &[u8]) -> Option<Vec<u8>> {
+        match self {
+            HashType::Sha1 => Some(sha1(data).to_vec()),
+            HashType::Sha256 => Some(sha256(data).to_vec()),
+            HashType::Sha256Truncated => Some(sha256(data)[..20].to_vec()),
+            HashType::Sha384 => Some(sha384(data).to_vec()),
+            HashType::Unknown(_) => None,
+        }
+    }
+
+    /// Returns the length of a hash of this type, or `None` if the type is unknown.
+    fn digest_len(&self) -> Option<usize> {
+        match self {
+            HashType::Sha1 | HashType::Sha256Truncated => Some(20),
+            HashType::Sha256 => Some(32),
+            HashType::Sha384 => Some(48),
+            HashType::Unknown(_) => None,
+        }
+    }
+
+    /// Orders hash types the way the kernel picks among multiple code directories.
+    fn strength(&self) -> u8 {
+        match self {
+            HashType::Sha1 => 1,
+            HashType::Sha256Truncated => 2,
+            HashType::Sha256 => 3,
+            HashType::Sha384 => 4,
+            HashType::Unknown(_) => 0,
+        }
+    }
+}
+
+/// The flags field of a [`CodeDirectory`], as shown by `codesign -dv`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct CodeSignatureFlags(pub u32);
+
+impl CodeSignatureFlags {
+    /// The code was signed by the system on the fly.
+    pub const HOST: Self = Self(0x1);
+    /// The signature is ad-hoc: it has no certificate and identifies the code by hash only.
+    pub const ADHOC: Self = Self(0x2);
+    /// Pages that fail validation cause the process to be killed.
+    pub const HARD: Self = Self(0x100);
+    /// The process is killed when its signature becomes invalid.
+    pub const KILL: Self = Self(0x200);
+    /// The signature expires with its certificates.
+    pub const EXPIRATION: Self = Self(0x400);
+    /// Restricts debugging and `DYLD_` environment variables.
+    pub const RESTRICT: Self = Self(0x800);
+    /// Code signing is enforced for the process.
+    pub const ENFORCEMENT: Self = Self(0x1000);
+    /// Only libraries signed by Apple or the same team may be loaded.
+    pub const LIBRARY_VALIDATION: Self = Self(0x2000);
+    /// The hardened runtime is enabled.
+    pub const RUNTIME: Self = Self(0x10000);
+    /// The signature was created by the linker.
+    pub const LINKER_SIGNED: Self = Self(0x20000);
+
+    const NAMES: [(Self, &'static str); 10] = [
+        (Self::HOST, "host"),
+        (Self::ADHOC, "adhoc"),
+        (Self::HARD, "hard"),
+        (Self::KILL, "kill"),
+        (Self::EXPIRATION, "expires"),
+        (Self::RESTRICT, "restrict"),
+        (Self::ENFORCEMENT, "enforcement"),
+        (Self::LIBRARY_VALIDATION, "library-validation"),
+        (Self::RUNTIME, "runtime"),
+        (Self::LINKER_SIGNED, "linker-signed"),
+    ];
+
+    /// Returns whether every flag set in `other` is also set in `self`.
+    pub fn contains(&self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl fmt::Display for CodeSignatureFlags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<&str> = Self::NAMES
+            .iter()
+            .filter(|(flag, _)| self.contains(*flag))
+            .map(|(_, name)| *name)
+            .collect();
+        if names.is_empty() {
+            write!(f, "{:#x}(none)", self.0)
+        } else {
+            write!(f, "{:#x}({})", self.0, names.join(","))
+        }
+    }
+}
+
+/// A code directory: the signed description of the code, its identifier and its hashes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CodeDirectory {
+    /// The format version, such as `0x20400`.
+    pub version: u32,
+
+    /// The signature flags.
+    pub flags: CodeSignatureFlags,
+
+    /// The hash algorithm used for the slot hashes and the CDHash.
+    pub hash_type: HashType,
+
+    /// The signing identifier, usually the bundle identifier.
+    pub identifier: String,
+
+    /// The Team ID of the signing certificate. Absent for ad-hoc signatures.
+    pub team_id: Option<String>,
+
+    /// The platform identifier, non-zero only for Apple platform binaries.
+    pub platform: u8,
+
+    /// The size of each hashed code page in bytes, or 0 for a single page.
+    pub page_size: u32,
+
+    /// The number of bytes of the image covered by the code slots.
+    pub code_limit: u64,
+
+    /// The special slot hashes, starting with slot 1 (`Info.plist`). See the `*_SLOT` constants.
+    pub special_slots: Vec<Vec<u8>>,
+
+    /// The hash of each code page.
+    pub code_slots: Vec<Vec<u8>>,
+
+    /// The executable segment flags, for versions that record them.
+    pub exec_segment_flags: Option<u64>,
+
+    /// The SDK version the hardened runtime was configured for, for versions that record it.
+    pub runtime_version: Option<u32>,
+
+    /// The CDHash: this code directory's hash, truncated to 20 bytes. `None` if the hash type
+    /// isn't supported.
+    pub cdhash: Option<Vec<u8>>,
+}
+
+impl CodeDirectory {
+    /// The special slot holding the hash of `Info.plist`.
+    pub const INFO_SLOT: usize = 1;
+    /// The special slot holding the hash of the requirements blob.
+    pub const REQUIREMENTS_SLOT: usize = 2;
+    /// The special slot holding the hash of `_CodeSignature/CodeResources`.
+    pub const RESOURCES_SLOT: usize = 3;
+    /// The special slot holding the hash of the entitlements blob.
+    pub const ENTITLEMENTS_SLOT: usize = 5;
+
+    /// Parses a code directory blob, starting at its magic.
+    pub fn parse(data: &[u8]) -> Result<Self, MachOError> {
+        let reader = Reader {
+            data,
+            big_endian: true,
+        };
+        let field = |offset| {
+            reader
+                .u32(offset)
+                .ok_or_else(|| invalid("truncated code directory"))
+        };
+        if field(0)? != CSMAGIC_CODEDIRECTORY {
+            return Err(invalid("bad code directory magic"));
+        }
+        let length = field(4)? as usize;
+        let data = data
+            .get(..length)
+            .ok_or_else(|| invalid("code directory extends past its blob"))?;
+        let reader = Reader {
+            data,
+            big_endian: true,
+        };
+        let version = field(8)?;
+        let flags = CodeSignatureFlags(field(12)?);
+        let hash_offset = field(16)? as usize;
+        let identifier_offset = field(20)? as usize;
+        let special_count = field(24)? as usize;
+        let code_count = field(28)? as usize;
+        let mut code_limit = u64::from(field(32)?);
+        let header = reader
+            .bytes(36, 4)
+            .ok_or_else(|| invalid("truncated code directory"))?;
+        let (hash_size, hash_type, platform, page_shift) = (
+            header[0] as usize,
+            HashType::from_raw(header[1]),
+            header[2],
+            header[3],
+        );
+        if hash_size == 0 || hash_type.digest_len().is_some_and(|len| len != hash_size) {
+            return Err(invalid("hash size doesn't match the hash type"));
+        }
+        if page_shift >= 32 {
+            return Err(invalid("page size out of range"));
+        }
+
+        let string = |offset: usize| {
+            let bytes = data
+                .get(offset..)
+                .ok_or_else(|| invalid("string offset out of bounds"))?;
+            let end = bytes
+                .iter()
+                .position(|&b| b == 0)
+                .ok_or_else(|| invalid("unterminated string"))?;
+            Ok::<_, MachOError>(String::from_utf8_lossy(&bytes[..end]).into_owned())
+        };
+        let identifier = string(identifier_offset)?;
+        let team_id = match reader.u32(48) {
+            Some(offset) if version >= 0x20200 && offset != 0 => Some(string(offset as usize)?),
+            _ => None,
+        };
+        if version >= 0x20300
+            && let Some(limit) = reader.u64(56).filter(|&limit| limit != 0)
+        {
+            code_limit = limit;
+        }
+        let exec_segment_flags = if version >= 0x20400 {
+            reader.u64(80)
+        } else {
+            None
+        };
+        let runtime_version = if version >= 0x20500 {
+            reader.u32(88)
+        } else {
+            None
+        };
+
+        let hash = |index: isize| {
+            let offset = (hash_offset as isize + index * hash_size as isize) as usize;
+            reader
+                .bytes(offset, hash_size)
+                .map(<[u8]>::to_vec)
+                .ok_or_else(|| invalid("hash slot out of bounds"))
+        };
+        if special_count > hash_offset / hash_size {
+            return Err(invalid("special slots precede the code directory"));
+        }
+        if code_count > data.len().saturating_sub(hash_offset) / hash_size {
+            return Err(invalid("code slots extend past the code directory"));
+        }
+        let special_slots = (1..=special_count as isize)
+            .map(|slot| hash(-slot))
+            .collect::<Result<_, _>>()?;
+        let code_slots = (0..code_count as isize)
+            .map(hash)
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            version,
+            flags,
+            hash_type,
+            identifier,
+            team_id,
+            platform,
+            page_size: if page_shift == 0 { 0 } else { 1 << page_shift },
+            code_limit,
+            special_slots,
+            code_slots,
+            exec_segment_flags,
+            runtime_version,
+            cdhash: hash_type.digest(data).map(|digest| digest[..20].to_vec()),
+        })
+    }
+
+    /// Returns the hash stored in special slot `slot`, if the code directory has that many.
+    pub fn special_slot(&self, slot: usize) -> Option<&[u8]> {
+        self.special_slots
+            .get(slot.checked_sub(1)?)
+            .map(Vec::as_slice)
+    }
+
+    /// Returns the CDHash as lowercase hexadecimal, as shown by `codesign -dv`.
+    pub fn cdhash_hex(&self) -> Option<String> {
+        self.cdhash.as_deref().map(hex)
+    }
+}
+
+/// An embedded code signature, as found in the `__LINKEDIT` segment of a signed image.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CodeSignature {
+    /// Every code directory, in slot order. Signatures made for older systems carry a SHA-1 code
+    /// directory next to a SHA-256 one.
+    pub code_directories: Vec<CodeDirectory>,
+
+    /// The raw internal requirements blob, if present.
+    pub requirements: Option<Vec<u8>>,
+
+    /// The entitlements granted to the code, if present.
+    pub entitlements: Option<Dictionary>,
+
+    /// The CMS signature over the code directories. Empty for ad-hoc signatures.
+    pub cms_signature: Vec<u8>,
+}
+
+impl CodeSignature {
+    /// Parses an embedded signature super blob.
+    pub fn parse(data: &[u8]) -> Result<Self, MachOError> {
+        let reader = Reader {
+            data,
+            big_endian: true,
+        };
+        let field = |offset| {
+            reader
+                .u32(offset)
+                .ok_or_else(|| invalid("truncated super blob"))
+        };
+        if field(0)? != CSMAGIC_EMBEDDED_SIGNATURE {
+            return Err(invalid("bad super blob magic"));
+        }
+        let count = field(8)? as usize;
+
+        let mut signature = CodeSignature {
+            code_directories: Vec::new(),
+            requirements: None,
+            entitlements: None,
+            cms_signature: Vec::new(),
+        };
+        for index in 0..count {
+            let slot = field(12 + index * 8)?;
+            let offset = field(16 + index * 8)? as usize;
+            let blob = reader
+                .u32(offset + 4)
+                .and_then(|length| reader.bytes(offset, length as usize))
+                .filter(|blob| blob.len() >= 8)
+                .ok_or_else(|| invalid(format!("blob in slot {slot:#x} is out of bounds")))?;
+            let magic = u32::from_be_bytes(blob[..4].try_into().unwrap());
+            match slot {
+                CSSLOT_CODEDIRECTORY => {
+                    signature.code_directories.push(CodeDirectory::parse(blob)?)
+                }
+                _ if (CSSLOT_ALTERNATE_CODEDIRECTORIES
+                    ..CSSLOT_ALTERNATE_CODEDIRECTORIES + CSSLOT_ALTERNATE_CODEDIRECTORY_MAX)
+                    .contains(&slot) =>
+                {
+                    signature.code_directories.push(CodeDirectory::parse(blob)?);
+                }
+                CSSLOT_REQUIREMENTS => signature.requirements = Some(blob.to_vec()),
+                CSSLOT_ENTITLEMENTS if magic == CSMAGIC_EMBEDDED_ENTITLEMENTS => {
+                    let entitlements =
+                        match Value::from_bytes(&blob[8..]).map_err(MachOError::Entitlements)? {
+                            Value::Dictionary(entitlements) => entitlements,
+                            other => {
+                                return Err(MachOError::Entitlements(
+                                    crate::plist::PlistError::InvalidType {
+                                        key: String::new(),
+                                        expected: "a dictionary",
+                                        found: other.type_name(),
+                                    },
+                                ));
+                            }
+                        };
+                    signature.entitlements = Some(entitlements);
+                }
+                CSSLOT_SIGNATURESLOT if magic == CSMAGIC_BLOBWRAPPER => {
+                    signature.cms_signature = blob[8..].to_vec();
+                }
+                _ => {}
+            }
+        }
+        if signature.code_directories.is_empty() {
+            return Err(invalid("no code directory"));
+        }
+        Ok(signature)
+    }
+
+    /// Returns the code directory the system identifies the code by: the one with the strongest
+    /// hash this crate can compute, or the first one if none can be computed.
+    pub fn code_directory(&self) -> &CodeDirectory {
+        self.code_directories
+            .iter()
+            .rev()
+            .filter(|cd| cd.cdhash.is_some())
+            .max_by_key(|cd| cd.hash_type.strength())
+            .or(self.code_directories.first())
+            .expect("a parsed signature has a code directory")
+    }
+
+    /// Returns the signing identifier.
+    pub fn identifier(&self) -> &str {
+        &self.code_directory().identifier
+    }
+
+    /// Returns the Team ID of the signing certificate.
+    pub fn team_id(&self) -> Option<&str> {
+        self.code_directory().team_id.as_deref()
+    }
+
+    /// Returns the signature flags.
+    pub fn flags(&self) -> CodeSignatureFlags {
+        self.code_directory().flags
+    }
+
+    /// Returns the CDHash of the primary code directory.
+    pub fn cdhash(&self) -> Option<&[u8]> {
+        self.code_directory().cdhash.as_deref()
+    }
+
+    /// Returns whether the signature is ad-hoc.
+    pub fn is_adhoc(&self) -> bool {
+        self.flags().contains(CodeSignatureFlags::ADHOC)
+    }
+
+    /// Returns whether the hardened runtime is enabled.
+    pub fn has_hardened_runtime(&self) -> bool {
+        self.flags().contains(CodeSignatureFlags::RUNTIME)
+    }
+
+    /// Returns the certificate chain embedded in the CMS signature, from the leaf to the root.
+    /// Empty for ad-hoc signatures.
+    pub fn certificates(&self) -> Result<Vec<Certificate>, MachOError> {
+        certificate_chain(&self.cms_signature)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::macho::fixture::FixtureSignature;
+
+    #[test]
+    fn test_flags_display() {
+        assert_eq!(
+            CodeSignatureFlags(0x10002).to_string(),
+            "0x10002(adhoc,runtime)"
+        );
+        assert_eq!(CodeSignatureFlags(0).to_string(), "0x0(none)");
+        assert!(CodeSignatureFlags(0x10002).contains(CodeSignatureFlags::RUNTIME));
+    }
+
+    #[test]
+    fn test_invalid_signatures() {
+        assert!(matches!(
+            CodeSignature::parse(&[0xfa, 0xde, 0x0c, 0xc0, 0, 0, 0, 12, 0, 0, 0, 0]),
+            Err(MachOError::InvalidSignature(message)) if message == "no code directory"
+        ));
+        assert!(matches!(
+            CodeSignature::parse(&[
+                0xfa, 0xde, 0x0c, 0xc0, 0, 0, 0, 20, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 64
+            ]),
+            Err(MachOError::InvalidSignature(_))
+        ));
+    }
+
+    #[test]
+    fn test_malformed_code_directories() {
+        let cd = FixtureSignature::new("com.example.app").code_directory();
+        let invalid = |patch: &dyn Fn(&mut Vec<u8>)| {
+            let mut cd = cd.clone();
+            patch(&mut cd);
+            matches!(
+                CodeDirectory::parse(&cd),
+                Err(MachOError::InvalidSignature(_))
+            )
+        };
+        assert!(CodeDirectory::parse(&cd).is_ok());
+        // A page shift that doesn't fit the page size.
+        assert!(invalid(&|cd| cd[39] = 40));
+        // Empty hashes, which would let any slot count through.
+        assert!(invalid(&|cd| {
+            cd[36] = 0;
+            cd[28..32].copy_from_slice(&u32::MAX.to_be_bytes());
+        }));
+        // A hash size that isn't the hash type's.
+        assert!(invalid(&|cd| cd[36] = 20));
+        // More code slots than fit in the blob.
+        assert!(invalid(
+            &|cd| cd[28..32].copy_from_slice(&u32::MAX.to_be_bytes())
+        ));
+    }
+
+    #[test]
+    fn test_strongest_code_directory() {
+        let code_directory = |hash_type| {
+            CodeDirectory::parse(
+                &FixtureSignature {
+                    hash_type,
+                    ..FixtureSignature::new("com.example.app")
+                }
+                .code_directory(),
+            )
+            .unwrap()
+        };
+        let signature = CodeSignature {
+            code_directories: vec![code_directory(1), code_directory(4), code_directory(2)],
+            requirements: None,
+            entitlements: None,
+            cms_signature: Vec::new(),
+        };
+        assert_eq!(signature.code_directory().hash_type, HashType::Sha384);
+        assert_eq!(signature.cdhash().map(<[u8]>::len), Some(20));
+
+        // A hash type this crate can't compute is never preferred, whatever its position.
+        let signature = CodeSignature {
+            code_directories: vec![code_directory(2), code_directory(9)],
+            ..signature
+        };
+        assert_eq!(signature.code_directory().hash_type, HashType::Sha256);
+        assert!(signature.cdhash().is_some());
+    }
+}
@@ -0,0 +1,409 @@
+//! One-time replacement of renamed or retired services.
+//!
+//! Renaming an agent plist or changing a helper's bundle identifier leaves the old registration
+//! behind, and launchd keeps it in System Settings until it is unregistered. A [`Migration`]
+//! names the services an app used to register and the ones that replace them. A [`Migrator`]
+//! runs each migration once and records that it completed.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::state::{self, StateError};
+use crate::{
+    AppService, DefaultBackend, EnsureReport, OwnedServiceType, ServiceBackend,
+    ServiceManagementError,
+};
+
+/// The name of the migration log inside the app's Application Support directory.
+const LOG_FILE_NAME: &str = "service-migrations.json";
+
+/// Services to retire and the services that replace them.
+///
+/// # Examples
+///
+/// ```rust
+/// use smappservice_rs::{Migration, ServiceType};
+///
+/// let migration = Migration {
+///     id: "rename-agent".to_string(),
+///     retired: vec![ServiceType::Agent { plist_name: "com.old.agent.plist" }.into()],
+///     current: vec![ServiceType::Agent { plist_name: "com.new.agent.plist" }.into()],
+/// };
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Migration {
+    /// Identifies the migration in the [`MigrationLog`]. Must stay the same across releases.
+    pub id: String,
+
+    /// Services the app no longer ships, unregistered if they are still registered.
+    pub retired: Vec<OwnedServiceType>,
+
+    /// Services that replace them, registered once the retired ones are gone.
+    pub current: Vec<OwnedServiceType>,
+}
+
+/// The migrations that have completed, kept in a JSON file across launches.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MigrationLog {
+    #[serde(skip)]
+    path: PathBuf,
+    completed: Vec<String>,
+}
+
+impl MigrationLog {
+    /// Reads the log at `path`. A missing file is an empty log.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, StateError> {
+        let path = path.as_ref().to_path_buf();
+        Ok(Self {
+            path: path.clone(),
+            ..state::read(&path)?
+        })
+    }
+
+    /// Reads the log in `~/Library/Application Support/<identifier>`, where `identifier` is the
+    /// app's bundle identifier.
+    pub fn for_app(identifier: &str) -> Result<Self, StateError> {
+        Self::open(state::application_support(identifier, LOG_FILE_NAME)?)
+    }
+
+    /// Returns the path of the log file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns whether the migration with this id has completed.
+    pub fn is_completed(&self, id: &str) -> bool {
+        self.completed.iter().any(|completed| completed == id)
+    }
+
+    /// Records the migration with this id as completed, and saves the log.
+    pub fn complete(&mut self, id: &str) -> Result<(), StateError> {
+        if !self.is_completed(id) {
+            self.completed.push(id.to_string());
+        }
+        state::write(&self.path, self)
+    }
+}
+
+/// What [`Migrator::run`] did.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MigrationReport {
+    /// The id of the migration.
+    pub id: String,
+
+    /// Whether the migration had already completed on an earlier run, in which case nothing
+    /// was done.
+    pub already_completed: bool,
+
+    /// The result of unregistering each retired service.
+    pub retired: Vec<(
+        OwnedServiceType,
+        Result<EnsureReport, ServiceManagementError>,
+    )>,
+
+    /// The result of registering each current service.
+    pub current: Vec<(
+        OwnedServiceType,
+        Result<EnsureReport, ServiceManagementError>,
+    )>,
+}
+
+impl MigrationReport {
+    /// Returns whether the migration has completed, on this run or an earlier one.
+    pub fn is_complete(&self) -> bool {
+        self.already_completed
+            || self
+                .retired
+                .iter()
+                .chain(&self.current)
+                .all(|(_, result)| result.is_ok())
+    }
+}
+
+impl fmt::Display for MigrationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.already_completed {
+            return writeln!(f, "{}: already completed", self.id);
+        }
+        let results = self
+            .retired
+            .iter()
+            .map(|entry| ("unregister", entry))
+            .chain(self.current.iter().map(|entry| ("register", entry)));
+        for (operation, (service_type, result)) in results {
+            match result {
+                Ok(report) if report.changed => {
+                    writeln!(f, "{}: {} {}: done", self.id, operation, service_type)?
+                }
+                Ok(_) => writeln!(
+                    f,
+                    "{}: {} {}: nothing to do",
+                    self.id, operation, service_type
+                )?,
+                Err(error) => writeln!(
+                    f,
+                    "{}: {} {}: failed ({})",
+                    self.id, operation, service_type, error
+                )?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Runs [`Migration`]s once each, recording the completed ones in a [`MigrationLog`].
+///
+/// A migration unregisters every retired service, then registers the current services. A
+/// retired service whose plist or helper is gone from the bundle is reported as
+/// [`ServiceStatus::NotFound`](crate::ServiceStatus::NotFound) even while launchd still runs it,
+/// so retired services are unregistered whatever their status. If any step fails, the migration isn't recorded and is tried again on the
+/// next run.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use smappservice_rs::{Migration, Migrator, ServiceType};
+///
+/// let mut migrator = Migrator::for_current_app().unwrap();
+/// let report = migrator
+///     .run(&Migration {
+///         id: "rename-agent".to_string(),
+///         retired: vec![ServiceType::Agent { plist_name: "com.old.agent.plist" }.into()],
+///         current: vec![ServiceType::Agent { plist_name: "com.new.agent.plist" }.into()],
+///     })
+///     .unwrap();
+/// print!("{}", report);
+/// ```
+pub struct Migrator {
+    log: MigrationLog,
+    backend: Arc<dyn ServiceBackend>,
+}
+
+impl fmt::Debug for Migrator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Migrator")
+            .field("log", &self.log)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Migrator {
+    /// Creates a migrator that uses the platform's [`DefaultBackend`].
+    pub fn new(log: MigrationLog) -> Self {
+        Self::with_backend(log, DefaultBackend::default())
+    }
+
+    /// Creates a migrator that goes through `backend`.
+    pub fn with_backend(log: MigrationLog, backend: impl ServiceBackend + 'static) -> Self {
+        Self {
+            log,
+            backend: Arc::new(backend),
+        }
+    }
+
+    /// Creates a migrator for the running app, with its log in the app's Application Support
+    /// directory.
+    pub fn for_current_app() -> Result<Self, StateError> {
+        let (_, identifier) = state::current_app()?;
+        Ok(Self::new(MigrationLog::for_app(&identifier)?))
+    }
+
+    /// Returns the migration log.
+    pub fn log(&self) -> &MigrationLog {
+        &self.log
+    }
+
+    /// Runs `migration` unless it already completed.
+    ///
+    /// Fails only if the log can't be saved; per-service failures are in the report.
+    pub fn run(&mut self, migration: &Migration) -> Result<MigrationReport, StateError> {
+        let mut report = MigrationReport {
+            id: migration.id.clone(),
+            already_completed: self.log.is_completed(&migration.id),
+            retired: Vec::new(),
+            current: Vec::new(),
+        };
+        if report.already_completed {
+            return Ok(report);
+        }
+
+        report.retired = migration
+            .retired
+            .iter()
+            .map(|service_type| {
+                let result = retire(&self.service(service_type));
+                (service_type.clone(), result)
+            })
+            .collect();
+        report.current = migration
+            .current
+            .iter()
+            .map(|service_type| {
+                let result = self.service(service_type).ensure_registered();
+                (service_type.clone(), result)
+            })
+            .collect();
+
+        if report.is_complete() {
+            self.log.complete(&migration.id)?;
+        }
+        Ok(report)
+    }
+
+    fn service(&self, service_type: &OwnedServiceType) -> AppService {
+        AppService::with_backend(service_type.as_service_type(), self.backend.clone())
+    }
+}
+
+/// Unregisters a retired service without checking its status first. `JobNotFound` means it was
+/// already gone.
+fn retire(service: &AppService) -> Result<EnsureReport, ServiceManagementError> {
+    let before = service.status();
+    let changed = match service.unregister() {
+        Ok(()) => true,
+        Err(ServiceManagementError::JobNotFound) => false,
+        Err(error) => return Err(error),
+    };
+    Ok(EnsureReport {
+        changed,
+        before,
+        after: service.status(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::fixture::TempDir;
+    use crate::{FaultInjectingBackend, Operation, ServiceStatus, ServiceType, SimulatedBackend};
+
+    const OLD_AGENT: ServiceType<'static> = ServiceType::Agent {
+        plist_name: "com.old.agent.plist",
+    };
+    const NEW_AGENT: ServiceType<'static> = ServiceType::Agent {
+        plist_name: "com.new.agent.plist",
+    };
+    const OLD_HELPER: ServiceType<'static> = ServiceType::LoginItem {
+        identifier: "com.old.helper",
+    };
+
+    /// Reports the old agent as `NotFound` while it's registered, as SMAppService does once its
+    /// plist has been removed from the bundle.
+    #[derive(Clone, Default)]
+    struct RemovedFromBundle(SimulatedBackend);
+
+    impl ServiceBackend for RemovedFromBundle {
+        fn register(&self, service_type: &ServiceType) -> Result<(), ServiceManagementError> {
+            self.0.register(service_type)
+        }
+
+        fn unregister(&self, service_type: &ServiceType) -> Result<(), ServiceManagementError> {
+            self.0.unregister(service_type)
+        }
+
+        fn status(&self, service_type: &ServiceType) -> ServiceStatus {
+            match self.0.status(service_type) {
+                ServiceStatus::Enabled if *service_type == OLD_AGENT => ServiceStatus::NotFound,
+                status => status,
+            }
+        }
+
+        fn open_system_settings_login_items(&self) {
+            self.0.open_system_settings_login_items()
+        }
+    }
+
+    fn migration() -> Migration {
+        Migration {
+            id: "rename-agent".to_string(),
+            retired: vec![OLD_AGENT.into(), OLD_HELPER.into()],
+            current: vec![NEW_AGENT.into()],
+        }
+    }
+
+    #[test]
+    fn test_runs_once() {
+        let directory = TempDir::new();
+        let log_path = directory.path.join(LOG_FILE_NAME);
+        let backend = FaultInjectingBackend::new(SimulatedBackend::new());
+        backend
+            .inner()
+            .set_status(&OLD_AGENT, ServiceStatus::Enabled);
+
+        let mut migrator =
+            Migrator::with_backend(MigrationLog::open(&log_path).unwrap(), backend.clone());
+        let report = migrator.run(&migration()).unwrap();
+        assert!(report.is_complete());
+        assert_eq!(
+            report.to_string(),
+            "rename-agent: unregister agent com.old.agent.plist: done\n\
+             rename-agent: unregister login item com.old.helper: nothing to do\n\
+             rename-agent: register agent com.new.agent.plist: done\n"
+        );
+        assert_eq!(
+            backend.inner().status(&OLD_AGENT),
+            ServiceStatus::NotRegistered
+        );
+        assert_eq!(backend.inner().status(&NEW_AGENT), ServiceStatus::Enabled);
+
+        // A later launch reads the log and leaves the services alone.
+        let mut migrator =
+            Migrator::with_backend(MigrationLog::open(&log_path).unwrap(), backend.clone());
+        assert!(migrator.log().is_completed("rename-agent"));
+        let report = migrator.run(&migration()).unwrap();
+        assert!(report.already_completed);
+        assert_eq!(backend.call_count(&NEW_AGENT, Operation::Register), 1);
+    }
+
+    #[test]
+    fn test_retired_service_not_found() {
+        let directory = TempDir::new();
+        let backend = RemovedFromBundle::default();
+        backend.0.set_status(&OLD_AGENT, ServiceStatus::Enabled);
+        let mut migrator = Migrator::with_backend(
+            MigrationLog::open(directory.path.join(LOG_FILE_NAME)).unwrap(),
+            backend.clone(),
+        );
+
+        let report = migrator.run(&migration()).unwrap();
+        assert!(report.is_complete());
+        assert_eq!(
+            report.retired[0].1,
+            Ok(EnsureReport {
+                changed: true,
+                before: ServiceStatus::NotFound,
+                after: ServiceStatus::NotRegistered,
+            })
+        );
+        assert_eq!(backend.0.status(&OLD_AGENT), ServiceStatus::NotRegistered);
+    }
+
+    #[test]
+    fn test_failure_is_retried() {
+        let directory = TempDir::new();
+        let backend = FaultInjectingBackend::new(SimulatedBackend::new());
+        backend.fail_next(
+            &NEW_AGENT,
+            Operation::Register,
+            ServiceManagementError::InvalidSignature,
+        );
+        let mut migrator = Migrator::with_backend(
+            MigrationLog::open(directory.path.join(LOG_FILE_NAME)).unwrap(),
+            backend.clone(),
+        );
+
+        let report = migrator.run(&migration()).unwrap();
+        assert!(!report.is_complete());
+        assert_eq!(
+            report.current[0].1,
+            Err(ServiceManagementError::InvalidSignature)
+        );
+        assert!(!migrator.log().is_completed("rename-agent"));
+
+        assert!(migrator.run(&migration()).unwrap().is_complete());
+        assert!(migrator.log().is_completed("rename-agent"));
+    }
+}
@@ -0,0 +1,467 @@
+//! Reading and writing the binary (`bplist00`) property list encoding.
+
+use std::cell::Cell;
+use std::collections::HashMap;
+
+use super::{Date, Dictionary, MAX_DEPTH, PlistError, Value};
+
+pub(super) const MAGIC: &[u8] = b"bplist00";
+const TRAILER_LEN: usize = 32;
+/// Shared objects are decoded again at every reference to them. Unless containers are shared,
+/// that is at most one value per reference, so per byte; decoding more values than this many per
+/// byte of input is treated as malformed rather than expanding exponentially.
+const MAX_VALUES_PER_BYTE: usize = 4;
+
+fn error(message: impl Into<String>) -> PlistError {
+    PlistError::Binary(message.into())
+}
+
+fn read_uint(bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .fold(0, |value, &byte| (value << 8) | u64::from(byte))
+}
+
+pub(super) fn parse(data: &[u8]) -> Result<Value, PlistError> {
+    if !data.starts_with(MAGIC) {
+        return Err(error("missing the bplist00 header"));
+    }
+    if data.len() < MAGIC.len() + TRAILER_LEN {
+        return Err(error("the data is too short"));
+    }
+    let trailer = &data[data.len() - TRAILER_LEN..];
+    let offset_size = trailer[6] as usize;
+    let ref_size = trailer[7] as usize;
+    let object_count = read_uint(&trailer[8..16]);
+    let top_object = read_uint(&trailer[16..24]);
+    let offset_table = read_uint(&trailer[24..32]);
+
+    if !(1..=8).contains(&offset_size) || !(1..=8).contains(&ref_size) {
+        return Err(error("invalid offset or reference size"));
+    }
+    let table_len = object_count
+        .checked_mul(offset_size as u64)
+        .ok_or_else(|| error("invalid object count"))?;
+    let table_end = offset_table
+        .checked_add(table_len)
+        .filter(|&end| end <= (data.len() - TRAILER_LEN) as u64)
+        .ok_or_else(|| error("the offset table is out of bounds"))?;
+    if top_object >= object_count || offset_table < MAGIC.len() as u64 {
+        return Err(error("invalid trailer"));
+    }
+    let offsets = data[offset_table as usize..table_end as usize]
+        .chunks(offset_size)
+        .map(|chunk| read_uint(chunk) as usize)
+        .collect::<Vec<_>>();
+
+    let reader = Reader {
+        data: &data[..offset_table as usize],
+        offsets,
+        ref_size,
+        budget: Cell::new(data.len().saturating_mul(MAX_VALUES_PER_BYTE)),
+    };
+    reader.object(top_object as usize, &mut Vec::new())
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    offsets: Vec<usize>,
+    ref_size: usize,
+    /// How many more values may be decoded.
+    budget: Cell<usize>,
+}
+
+impl Reader<'_> {
+    fn slice(&self, start: usize, len: usize) -> Result<&[u8], PlistError> {
+        start
+            .checked_add(len)
+            .and_then(|end| self.data.get(start..end))
+            .ok_or_else(|| error("an object extends past the object table"))
+    }
+
+    /// Reads the length stored in the low nibble of a marker, or in the integer that follows.
+    fn length(&self, marker: u8, offset: usize) -> Result<(usize, usize), PlistError> {
+        let nibble = marker & 0x0f;
+        if nibble != 0x0f {
+            return Ok((nibble as usize, offset + 1));
+        }
+        let int_marker = *self.slice(offset + 1, 1)?.first().unwrap_or(&0);
+        if int_marker & 0xf0 != 0x10 {
+            return Err(error("invalid length marker"));
+        }
+        let size = 1usize << (int_marker & 0x0f);
+        if size > 8 {
+            return Err(error("invalid length size"));
+        }
+        let length = read_uint(self.slice(offset + 2, size)?);
+        let length = usize::try_from(length).map_err(|_| error("invalid length"))?;
+        Ok((length, offset + 2 + size))
+    }
+
+    fn refs(&self, start: usize, count: usize) -> Result<Vec<usize>, PlistError> {
+        let len = count
+            .checked_mul(self.ref_size)
+            .ok_or_else(|| error("invalid container length"))?;
+        Ok(self
+            .slice(start, len)?
+            .chunks(self.ref_size)
+            .map(|chunk| read_uint(chunk) as usize)
+            .collect())
+    }
+
+    fn object(&self, index: usize, stack: &mut Vec<usize>) -> Result<Value, PlistError> {
+        if stack.contains(&index) {
+            return Err(error("the object graph contains a cycle"));
+        }
+        if stack.len() >= MAX_DEPTH {
+            return Err(error("objects are nested too deeply"));
+        }
+        let budget = self
+            .budget
+            .get()
+            .checked_sub(1)
+            .ok_or_else(|| error("shared objects expand to too many values"))?;
+        self.budget.set(budget);
+        let offset = *self
+            .offsets
+            .get(index)
+            .ok_or_else(|| error(format!("object reference {index} is out of bounds")))?;
+        let marker = self.slice(offset, 1)?[0];
+        let value = match marker >> 4 {
+            0x0 => match marker {
+                0x08 => Value::Boolean(false),
+                0x09 => Value::Boolean(true),
+                _ => return Err(error(format!("unsupported object marker {marker:#04x}"))),
+            },
+            0x1 => {
+                let size = 1usize << (marker & 0x0f);
+                let bytes = self.slice(offset + 1, size)?;
+                match size {
+                    // 8-byte integers are signed, narrower ones unsigned.
+                    1 | 2 | 4 | 8 => Value::Integer(read_uint(bytes) as i64),
+                    // 128-bit integers are only written for values above i64::MAX.
+                    16 => {
+                        let value = read_uint(&bytes[8..]);
+                        if read_uint(&bytes[..8]) != 0 || value > i64::MAX as u64 {
+                            return Err(error("integer doesn't fit in 64 bits"));
+                        }
+                        Value::Integer(value as i64)
+                    }
+                    _ => return Err(error("invalid integer size")),
+                }
+            }
+            0x2 => {
+                let bytes = self.slice(offset + 1, 1usize << (marker & 0x0f))?;
+                match bytes.len() {
+                    4 => Value::Real(f32::from_be_bytes(bytes.try_into().unwrap()) as f64),
+                    8 => Value::Real(f64::from_be_bytes(bytes.try_into().unwrap())),
+                    _ => return Err(error("invalid real size")),
+                }
+            }
+            0x3 if marker == 0x33 => {
+                let bytes = self.slice(offset + 1, 8)?;
+                Value::Date(Date::from_reference_seconds(f64::from_be_bytes(
+                    bytes.try_into().unwrap(),
+                )))
+            }
+            0x4 => {
+                let (len, start) = self.length(marker, offset)?;
+                Value::Data(self.slice(start, len)?.to_vec())
+            }
+            0x5 => {
+                let (len, start) = self.length(marker, offset)?;
+                let bytes = self.slice(start, len)?;
+                if !bytes.is_ascii() {
+                    return Err(error("ASCII string contains non-ASCII bytes"));
+                }
+                Value::String(String::from_utf8(bytes.to_vec()).unwrap())
+            }
+            0x6 => {
+                let (len, start) = self.length(marker, offset)?;
+                let len = len
+                    .checked_mul(2)
+                    .ok_or_else(|| error("invalid string length"))?;
+                let units = self
+                    .slice(start, len)?
+                    .chunks(2)
+                    .map(|unit| u16::from_be_bytes([unit[0], unit[1]]))
+                    .collect::<Vec<_>>();
+                Value::String(
+                    String::from_utf16(&units).map_err(|_| error("invalid UTF-16 string"))?,
+                )
+            }
+            // Keyed archiver UIDs, represented the way `plutil` converts them to XML.
+            0x8 => {
+                let bytes = self.slice(offset + 1, (marker & 0x0f) as usize + 1)?;
+                let mut dict = Dictionary::new();
+                dict.insert(
+                    "CF$UID".to_string(),
+                    Value::Integer(read_uint(bytes) as i64),
+                );
+                Value::Dictionary(dict)
+            }
+            0xa => {
+                let (len, start) = self.length(marker, offset)?;
+                stack.push(index);
+                let values = self
+                    .refs(start, len)?
+                    .into_iter()
+                    .map(|child| self.object(child, stack))
+                    .collect::<Result<_, _>>()?;
+                stack.pop();
+                Value::Array(values)
+            }
+            0xd => {
+                let (len, start) = self.length(marker, offset)?;
+                let keys = self.refs(start, len)?;
+                let values = self.refs(start + len * self.ref_size, len)?;
+                stack.push(index);
+                let mut dict = Dictionary::new();
+                for (key, value) in keys.into_iter().zip(values) {
+                    let key = match self.object(key, stack)? {
+                        Value::String(key) => key,
+                        other => {
+                            return Err(error(format!(
+                                "dictionary key is a {}, not a string",
+                                other.type_name()
+                            )));
+                        }
+                    };
+                    dict.insert(key, self.object(value, stack)?);
+                }
+                stack.pop();
+                Value::Dictionary(dict)
+            }
+            _ => return Err(error(format!("unsupported object marker {marker:#04x}"))),
+        };
+        Ok(value)
+    }
+}
+
+/// An object in the flattened object table.
+enum Object<'a> {
+    Scalar(&'a Value),
+    Key(&'a str),
+    Array(Vec<usize>),
+    Dictionary(Vec<usize>, Vec<usize>),
+}
+
+struct Writer<'a> {
+    objects: Vec<Object<'a>>,
+    strings: HashMap<&'a str, usize>,
+}
+
+impl<'a> Writer<'a> {
+    fn string(&mut self, value: &'a str, object: Object<'a>) -> usize {
+        if let Some(&index) = self.strings.get(value) {
+            return index;
+        }
+        self.objects.push(object);
+        self.strings.insert(value, self.objects.len() - 1);
+        self.objects.len() - 1
+    }
+
+    fn add(&mut self, value: &'a Value) -> usize {
+        match value {
+            Value::String(string) => self.string(string, Object::Scalar(value)),
+            Value::Array(values) => {
+                let index = self.objects.len();
+                self.objects.push(Object::Array(Vec::new()));
+                let refs = values.iter().map(|value| self.add(value)).collect();
+                self.objects[index] = Object::Array(refs);
+                index
+            }
+            Value::Dictionary(dict) => {
+                let index = self.objects.len();
+                self.objects
+                    .push(Object::Dictionary(Vec::new(), Vec::new()));
+                let keys = dict
+                    .keys()
+                    .map(|key| self.string(key, Object::Key(key)))
+                    .collect();
+                let values = dict.values().map(|value| self.add(value)).collect();
+                self.objects[index] = Object::Dictionary(keys, values);
+                index
+            }
+            _ => {
+                self.objects.push(Object::Scalar(value));
+                self.objects.len() - 1
+            }
+        }
+    }
+}
+
+fn byte_size(max: u64) -> usize {
+    match max {
+        0..=0xff => 1,
+        0x100..=0xffff => 2,
+        0x1_0000..=0xffff_ffff => 4,
+        _ => 8,
+    }
+}
+
+fn write_uint(out: &mut Vec<u8>, value: u64, size: usize) {
+    out.extend_from_slice(&value.to_be_bytes()[8 - size..]);
+}
+
+fn write_integer(out: &mut Vec<u8>, value: i64) {
+    let size = if value < 0 {
+        8
+    } else {
+        byte_size(value as u64)
+    };
+    out.push(0x10 | size.trailing_zeros() as u8);
+    write_uint(out, value as u64, size);
+}
+
+fn write_marker(out: &mut Vec<u8>, kind: u8, len: usize) {
+    if len < 0x0f {
+        out.push(kind | len as u8);
+    } else {
+        out.push(kind | 0x0f);
+        write_integer(out, len as i64);
+    }
+}
+
+fn write_string(out: &mut Vec<u8>, value: &str) {
+    if value.is_ascii() {
+        write_marker(out, 0x50, value.len());
+        out.extend_from_slice(value.as_bytes());
+    } else {
+        let units = value.encode_utf16().collect::<Vec<_>>();
+        write_marker(out, 0x60, units.len());
+        for unit in units {
+            out.extend_from_slice(&unit.to_be_bytes());
+        }
+    }
+}
+
+pub(super) fn write(value: &Value) -> Vec<u8> {
+    let mut writer = Writer {
+        objects: Vec::new(),
+        strings: HashMap::new(),
+    };
+    writer.add(value);
+    let ref_size = byte_size(writer.objects.len() as u64 - 1);
+
+    let mut out = MAGIC.to_vec();
+    let mut offsets = Vec::with_capacity(writer.objects.len());
+    for object in &writer.objects {
+        offsets.push(out.len() as u64);
+        match object {
+            Object::Key(key) => write_string(&mut out, key),
+            Object::Array(refs) => {
+                write_marker(&mut out, 0xa0, refs.len());
+                for &child in refs {
+                    write_uint(&mut out, child as u64, ref_size);
+                }
+            }
+            Object::Dictionary(keys, values) => {
+                write_marker(&mut out, 0xd0, keys.len());
+                for &child in keys.iter().chain(values) {
+                    write_uint(&mut out, child as u64, ref_size);
+                }
+            }
+            Object::Scalar(value) => match value {
+                Value::String(value) => write_string(&mut out, value),
+                Value::Integer(value) => write_integer(&mut out, *value),
+                Value::Real(value) => {
+                    out.push(0x23);
+                    out.extend_from_slice(&value.to_be_bytes());
+                }
+                Value::Boolean(value) => out.push(if *value { 0x09 } else { 0x08 }),
+                Value::Date(value) => {
+                    out.push(0x33);
+                    out.extend_from_slice(&value.reference_seconds().to_be_bytes());
+                }
+                Value::Data(value) => {
+                    write_marker(&mut out, 0x40, value.len());
+                    out.extend_from_slice(value);
+                }
+                Value::Array(_) | Value::Dictionary(_) => unreachable!("containers are flattened"),
+            },
+        }
+    }
+
+    let offset_table = out.len() as u64;
+    let offset_size = byte_size(offset_table);
+    for offset in offsets {
+        write_uint(&mut out, offset, offset_size);
+    }
+    out.extend_from_slice(&[0; 6]);
+    out.push(offset_size as u8);
+    out.push(ref_size as u8);
+    out.extend_from_slice(&(writer.objects.len() as u64).to_be_bytes());
+    out.extend_from_slice(&0u64.to_be_bytes());
+    out.extend_from_slice(&offset_table.to_be_bytes());
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_known_encoding() {
+        // A hand-assembled encoding of {Label = a; N = 1; On = true}.
+        let data = [
+            b'b', b'p', b'l', b'i', b's', b't', b'0', b'0', // header
+            0xd3, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, // dict with 3 entries
+            0x55, b'L', b'a', b'b', b'e', b'l', // "Label"
+            0x51, b'N', // "N"
+            0x52, b'O', b'n', // "On"
+            0x51, b'a', // "a"
+            0x10, 0x01, // 1
+            0x09, // true
+            // offset table
+            0x08, 0x0f, 0x15, 0x17, 0x1a, 0x1c, 0x1e, // trailer
+            0, 0, 0, 0, 0, 0, 1, 1, 0, 0, 0, 0, 0, 0, 0, 7, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0x1f,
+        ];
+        let value = parse(&data).unwrap();
+        let dict = value.as_dictionary().unwrap();
+        assert_eq!(dict["Label"].as_string(), Some("a"));
+        assert_eq!(dict["N"].as_integer(), Some(1));
+        assert_eq!(dict["On"].as_boolean(), Some(true));
+    }
+
+    #[test]
+    fn test_malformed_data() {
+        let mut data = write(&Value::Array(vec![Value::from("x")]));
+        assert!(parse(&data[..20]).is_err());
+
+        // Point the array's only element back at the array itself.
+        let array_offset = MAGIC.len();
+        data[array_offset + 1] = 0;
+        assert!(
+            matches!(parse(&data), Err(PlistError::Binary(message)) if message.contains("cycle"))
+        );
+    }
+
+    #[test]
+    fn test_shared_objects() {
+        // Many references to one string decode fine.
+        let data = write(&Value::Array(vec![Value::from("x"); 1000]));
+        assert_eq!(parse(&data).unwrap().as_array().unwrap().len(), 1000);
+
+        // A chain of arrays each holding the next one twice doubles at every level.
+        const LEVELS: u8 = 64;
+        let mut data = MAGIC.to_vec();
+        let mut offsets = Vec::new();
+        for level in 0..LEVELS {
+            offsets.push(data.len() as u8);
+            data.extend_from_slice(&[0xa2, level + 1, level + 1]);
+        }
+        offsets.push(data.len() as u8);
+        data.push(0x09);
+        let offset_table = data.len() as u64;
+        data.extend_from_slice(&offsets);
+        data.extend_from_slice(&[0, 0, 0, 0, 0, 0, 1, 1]);
+        data.extend_from_slice(&(u64::from(LEVELS) + 1).to_be_bytes());
+        data.extend_from_slice(&0u64.to_be_bytes());
+        data.extend_from_slice(&offset_table.to_be_bytes());
+        assert!(
+            matches!(parse(&data), Err(PlistError::Binary(message)) if message.contains("expand"))
+        );
+    }
+}
@@ -0,0 +1,199 @@
+//! The plist date type.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use thiserror::Error;
+
+/// Seconds between the Unix epoch and the Core Foundation reference date, 2001-01-01T00:00:00Z.
+const REFERENCE_DATE_OFFSET: i64 = 978_307_200;
+
+/// A point in time stored in a property list.
+///
+/// Dates are kept as seconds relative to 2001-01-01T00:00:00Z, the reference date used by the
+/// binary plist encoding. The XML encoding only has second precision.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct Date {
+    seconds_since_reference: f64,
+}
+
+impl Date {
+    /// Creates a date from seconds relative to 2001-01-01T00:00:00Z.
+    pub fn from_reference_seconds(seconds: f64) -> Self {
+        Self {
+            seconds_since_reference: seconds,
+        }
+    }
+
+    /// Returns the seconds relative to 2001-01-01T00:00:00Z.
+    pub fn reference_seconds(&self) -> f64 {
+        self.seconds_since_reference
+    }
+
+    /// Creates a date from seconds relative to the Unix epoch.
+    pub fn from_unix_seconds(seconds: i64) -> Self {
+        Self::from_reference_seconds(seconds.saturating_sub(REFERENCE_DATE_OFFSET) as f64)
+    }
+
+    /// Returns the whole seconds relative to the Unix epoch.
+    pub fn unix_seconds(&self) -> i64 {
+        (self.seconds_since_reference.floor() as i64).saturating_add(REFERENCE_DATE_OFFSET)
+    }
+
+    /// Formats the date as ISO 8601 in UTC, the representation used by XML property lists.
+    pub fn to_iso8601(&self) -> String {
+        let seconds = self.unix_seconds();
+        let days = seconds.div_euclid(86_400);
+        let time = seconds.rem_euclid(86_400);
+        let (year, month, day) = civil_from_days(days);
+        format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+            year,
+            month,
+            day,
+            time / 3600,
+            time % 3600 / 60,
+            time % 60
+        )
+    }
+
+    /// Parses an ISO 8601 UTC date of the form `YYYY-MM-DDTHH:MM:SSZ`.
+    pub fn from_iso8601(value: &str) -> Option<Self> {
+        let bytes = value.as_bytes();
+        if bytes.len() != 20
+            || bytes[4] != b'-'
+            || bytes[7] != b'-'
+            || bytes[10] != b'T'
+            || bytes[13] != b':'
+            || bytes[16] != b':'
+            || bytes[19] != b'Z'
+        {
+            return None;
+        }
+        let field = |range: std::ops::Range<usize>| -> Option<i64> {
+            let digits = value.get(range)?;
+            if !digits.bytes().all(|b| b.is_ascii_digit()) {
+                return None;
+            }
+            digits.parse().ok()
+        };
+        let (year, month, day) = (field(0..4)?, field(5..7)?, field(8..10)?);
+        let (hour, minute, second) = (field(11..13)?, field(14..16)?, field(17..19)?);
+        if !(1..=12).contains(&month)
+            || !(1..=31).contains(&day)
+            || hour > 23
+            || minute > 59
+            || second > 60
+        {
+            return None;
+        }
+        let days = days_from_civil(year, month, day);
+        Some(Self::from_unix_seconds(
+            days * 86_400 + hour * 3600 + minute * 60 + second,
+        ))
+    }
+}
+
+impl From<SystemTime> for Date {
+    fn from(time: SystemTime) -> Self {
+        let unix = match time.duration_since(UNIX_EPOCH) {
+            Ok(elapsed) => elapsed.as_secs_f64(),
+            Err(error) => -error.duration().as_secs_f64(),
+        };
+        Self::from_reference_seconds(unix - REFERENCE_DATE_OFFSET as f64)
+    }
+}
+
+/// The error returned when a [`Date`] can't be represented as a [`SystemTime`].
+///
+/// Binary property lists can store any `f64` as a date, including NaN and values far outside
+/// the range of the system clock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+#[error("the date is outside the range of the system clock")]
+pub struct DateOutOfRange;
+
+impl TryFrom<Date> for SystemTime {
+    type Error = DateOutOfRange;
+
+    fn try_from(date: Date) -> Result<Self, Self::Error> {
+        let unix = date.seconds_since_reference + REFERENCE_DATE_OFFSET as f64;
+        let time = if unix >= 0.0 {
+            Duration::try_from_secs_f64(unix)
+                .ok()
+                .and_then(|elapsed| UNIX_EPOCH.checked_add(elapsed))
+        } else {
+            Duration::try_from_secs_f64(-unix)
+                .ok()
+                .and_then(|elapsed| UNIX_EPOCH.checked_sub(elapsed))
+        };
+        time.ok_or(DateOutOfRange)
+    }
+}
+
+// Conversions between days since the Unix epoch and proleptic Gregorian dates, from
+// http://howardhinnant.github.io/date_algorithms.html.
+
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month_index = if month > 2 { month - 3 } else { month + 9 };
+    let day_of_year = (153 * month_index + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_iso8601() {
+        let reference = Date::from_iso8601("2001-01-01T00:00:00Z").unwrap();
+        assert_eq!(reference.reference_seconds(), 0.0);
+
+        let date = Date::from_unix_seconds(1_709_251_199);
+        assert_eq!(date.to_iso8601(), "2024-02-29T23:59:59Z");
+        assert_eq!(Date::from_iso8601(&date.to_iso8601()), Some(date));
+
+        assert_eq!(
+            Date::from_unix_seconds(-1).to_iso8601(),
+            "1969-12-31T23:59:59Z"
+        );
+        assert_eq!(Date::from_iso8601("2024-13-01T00:00:00Z"), None);
+        assert_eq!(Date::from_iso8601("2024-01-01 00:00:00"), None);
+    }
+
+    #[test]
+    fn test_system_time() {
+        let time = UNIX_EPOCH + Duration::from_secs(1_709_251_199);
+        assert_eq!(SystemTime::try_from(Date::from(time)), Ok(time));
+        assert_eq!(
+            SystemTime::try_from(Date::from_unix_seconds(-1)),
+            Ok(UNIX_EPOCH - Duration::from_secs(1))
+        );
+
+        for seconds in [f64::NAN, f64::INFINITY, 1e300, -1e300] {
+            let date = Date::from_reference_seconds(seconds);
+            assert_eq!(SystemTime::try_from(date), Err(DateOutOfRange));
+            date.to_iso8601();
+        }
+    }
+}
@@ -0,0 +1,48 @@
+//! Conversions between property list values and JSON.
+
+use super::xml::base64_encode;
+use super::{PlistError, Value};
+
+pub(super) fn to_json(value: &Value) -> serde_json::Value {
+    match value {
+        Value::String(value) => serde_json::Value::String(value.clone()),
+        Value::Integer(value) => serde_json::Value::from(*value),
+        Value::Real(value) => serde_json::Number::from_f64(*value)
+            .map(serde_json::Value::Number)
+            .unwrap_or(serde_json::Value::Null),
+        Value::Boolean(value) => serde_json::Value::Bool(*value),
+        Value::Date(value) => serde_json::Value::String(value.to_iso8601()),
+        Value::Data(value) => serde_json::Value::String(base64_encode(value)),
+        Value::Array(values) => serde_json::Value::Array(values.iter().map(to_json).collect()),
+        Value::Dictionary(dict) => serde_json::Value::Object(
+            dict.iter()
+                .map(|(key, value)| (key.clone(), to_json(value)))
+                .collect(),
+        ),
+    }
+}
+
+pub(super) fn from_json(json: &serde_json::Value) -> Result<Value, PlistError> {
+    Ok(match json {
+        serde_json::Value::Null => {
+            return Err(PlistError::Json(
+                "null has no property list equivalent".to_string(),
+            ));
+        }
+        serde_json::Value::Bool(value) => Value::Boolean(*value),
+        serde_json::Value::Number(number) => match number.as_i64() {
+            Some(value) => Value::Integer(value),
+            None => Value::Real(number.as_f64().unwrap_or(f64::NAN)),
+        },
+        serde_json::Value::String(value) => Value::String(value.clone()),
+        serde_json::Value::Array(values) => {
+            Value::Array(values.iter().map(from_json).collect::<Result<_, _>>()?)
+        }
+        serde_json::Value::Object(object) => Value::Dictionary(
+            object
+                .iter()
+                .map(|(key, value)| Ok((key.clone(), from_json(value)?)))
+                .collect::<Result<_, PlistError>>()?,
+        ),
+    })
+}
@@ -0,0 +1,452 @@
+//! A property list value model with XML and binary serialization.
+//!
+//! Property lists are the format launchd job definitions and bundle `Info.plist` files are
+//! stored in. [`Value`] models every plist type and can be read from and written to the XML
+//! encoding with [`Value::from_xml`] and [`Value::to_xml`], and to the binary `bplist00`
+//! encoding with [`Value::from_binary`] and [`Value::to_binary`]. [`Value::from_bytes`] accepts
+//! either encoding.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use smappservice_rs::plist::{Dictionary, Value};
+//!
+//! let mut dict = Dictionary::new();
+//! dict.insert("Label".to_string(), Value::from("com.example.agent"));
+//! dict.insert("RunAtLoad".to_string(), Value::Boolean(true));
+//!
+//! let xml = Value::Dictionary(dict.clone()).to_xml();
+//! assert_eq!(Value::from_xml(&xml).unwrap(), Value::Dictionary(dict));
+//! ```
+
+mod binary;
+mod date;
+mod json;
+mod xml;
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use thiserror::Error;
+
+pub use date::{Date, DateOutOfRange};
+
+/// Nesting deeper than this is treated as malformed rather than risking a stack overflow.
+const MAX_DEPTH: usize = 512;
+
+/// A property list dictionary. Keys are kept sorted, like `plutil` does when writing.
+pub type Dictionary = BTreeMap<String, Value>;
+
+/// Errors that can occur while reading or writing property lists.
+#[derive(Debug, Error)]
+pub enum PlistError {
+    /// The property list file couldn't be read or written.
+    #[error("failed to access the property list: {0}")]
+    Io(#[from] std::io::Error),
+
+    /// The data isn't a well-formed XML property list.
+    #[error("invalid XML property list: {0}")]
+    Xml(String),
+
+    /// The data isn't a well-formed binary property list.
+    #[error("invalid binary property list: {0}")]
+    Binary(String),
+
+    /// A JSON value has no property list equivalent.
+    #[error("unsupported JSON value: {0}")]
+    Json(String),
+
+    /// A value doesn't have the type the caller expected.
+    #[error("`{key}` must be {expected}, found {found}")]
+    InvalidType {
+        /// The key path of the offending value.
+        key: String,
+        /// The expected plist type.
+        expected: &'static str,
+        /// The plist type that was found.
+        found: &'static str,
+    },
+
+    /// A required key is missing.
+    #[error("missing required key `{0}`")]
+    MissingKey(String),
+}
+
+/// A property list value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    /// A `<string>`.
+    String(String),
+
+    /// An `<integer>`.
+    Integer(i64),
+
+    /// A `<real>`.
+    Real(f64),
+
+    /// A `<true/>` or `<false/>`.
+    Boolean(bool),
+
+    /// A `<date>`.
+    Date(Date),
+
+    /// A `<data>` blob.
+    Data(Vec<u8>),
+
+    /// An `<array>`.
+    Array(Vec<Value>),
+
+    /// A `<dict>`.
+    Dictionary(Dictionary),
+}
+
+impl Value {
+    /// Parses an XML property list document.
+    pub fn from_xml(xml: &str) -> Result<Self, PlistError> {
+        xml::parse(xml)
+    }
+
+    /// Serializes the value as an XML property list document.
+    pub fn to_xml(&self) -> String {
+        xml::write(self)
+    }
+
+    /// Parses a binary (`bplist00`) property list.
+    pub fn from_binary(data: &[u8]) -> Result<Self, PlistError> {
+        binary::parse(data)
+    }
+
+    /// Serializes the value as a binary (`bplist00`) property list.
+    pub fn to_binary(&self) -> Vec<u8> {
+        binary::write(self)
+    }
+
+    /// Parses a property list in either the binary or the XML encoding.
+    pub fn from_bytes(data: &[u8]) -> Result<Self, PlistError> {
+        if data.starts_with(binary::MAGIC) {
+            return Self::from_binary(data);
+        }
+        let xml = std::str::from_utf8(data)
+            .map_err(|_| PlistError::Xml("the document isn't valid UTF-8".to_string()))?;
+        Self::from_xml(xml.strip_prefix('\u{feff}').unwrap_or(xml))
+    }
+
+    /// Reads a property list in either the binary or the XML encoding from `path`.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, PlistError> {
+        Self::from_bytes(&std::fs::read(path)?)
+    }
+
+    /// Writes the value as an XML property list to `path`.
+    pub fn write_to_file(&self, path: impl AsRef<Path>) -> Result<(), PlistError> {
+        std::fs::write(path, self.to_xml())?;
+        Ok(())
+    }
+
+    /// Writes the value as a binary property list to `path`.
+    pub fn write_binary_to_file(&self, path: impl AsRef<Path>) -> Result<(), PlistError> {
+        std::fs::write(path, self.to_binary())?;
+        Ok(())
+    }
+
+    /// Converts the value to JSON, for inspection and debugging.
+    ///
+    /// Dates become ISO 8601 strings, data becomes base64 strings and non-finite reals become
+    /// `null`, so the conversion doesn't round-trip those types.
+    pub fn to_json(&self) -> serde_json::Value {
+        json::to_json(self)
+    }
+
+    /// Converts JSON to a property list value.
+    ///
+    /// Strings, booleans, numbers, arrays and objects map to their plist counterparts.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`PlistError::Json`] for `null`, which has no property list equivalent.
+    pub fn from_json(json: &serde_json::Value) -> Result<Self, PlistError> {
+        json::from_json(json)
+    }
+
+    /// Returns the name of the plist type of this value, as used in the XML encoding.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) => "string",
+            Value::Integer(_) => "integer",
+            Value::Real(_) => "real",
+            Value::Boolean(_) => "boolean",
+            Value::Date(_) => "date",
+            Value::Data(_) => "data",
+            Value::Array(_) => "array",
+            Value::Dictionary(_) => "dictionary",
+        }
+    }
+
+    /// Returns the string if this value is a `String`.
+    pub fn as_string(&self) -> Option<&str> {
+        match self {
+            Value::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Returns the integer if this value is an `Integer`.
+    pub fn as_integer(&self) -> Option<i64> {
+        match self {
+            Value::Integer(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// Returns the number if this value is a `Real`.
+    pub fn as_real(&self) -> Option<f64> {
+        match self {
+            Value::Real(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// Returns the boolean if this value is a `Boolean`.
+    pub fn as_boolean(&self) -> Option<bool> {
+        match self {
+            Value::Boolean(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// Returns the date if this value is a `Date`.
+    pub fn as_date(&self) -> Option<Date> {
+        match self {
+            Value::Date(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// Returns the bytes if this value is `Data`.
+    pub fn as_data(&self) -> Option<&[u8]> {
+        match self {
+            Value::Data(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Returns the elements if this value is an `Array`.
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Returns the entries if this value is a `Dictionary`.
+    pub fn as_dictionary(&self) -> Option<&Dictionary> {
+        match self {
+            Value::Dictionary(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Value::String(value.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Self {
+        Value::String(value)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(value: i64) -> Self {
+        Value::Integer(value)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(value: f64) -> Self {
+        Value::Real(value)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Value::Boolean(value)
+    }
+}
+
+impl From<Date> for Value {
+    fn from(value: Date) -> Self {
+        Value::Date(value)
+    }
+}
+
+impl From<Vec<Value>> for Value {
+    fn from(value: Vec<Value>) -> Self {
+        Value::Array(value)
+    }
+}
+
+impl From<Dictionary> for Value {
+    fn from(value: Dictionary) -> Self {
+        Value::Dictionary(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_xml_round_trip() {
+        let mut nested = Dictionary::new();
+        nested.insert("SuccessfulExit".to_string(), Value::Boolean(false));
+        nested.insert("Empty".to_string(), Value::Array(vec![]));
+
+        let mut dict = Dictionary::new();
+        dict.insert(
+            "Label".to_string(),
+            Value::from("com.example <&> \"agent\""),
+        );
+        dict.insert("Nice".to_string(), Value::Integer(-5));
+        dict.insert("Ratio".to_string(), Value::Real(0.25));
+        dict.insert(
+            "Blob".to_string(),
+            Value::Data(vec![0, 1, 2, 250, 251, 252, 253]),
+        );
+        dict.insert(
+            "When".to_string(),
+            Value::Date(Date::from_unix_seconds(1_700_000_000)),
+        );
+        dict.insert(
+            "ProgramArguments".to_string(),
+            Value::Array(vec![Value::from("/bin/echo"), Value::from("")]),
+        );
+        dict.insert("KeepAlive".to_string(), Value::Dictionary(nested));
+        let value = Value::Dictionary(dict);
+
+        let xml = value.to_xml();
+        assert!(xml.starts_with("<?xml version=\"1.0\" encoding=\"UTF-8\"?>"));
+        assert_eq!(Value::from_xml(&xml).unwrap(), value);
+    }
+
+    #[test]
+    fn test_parse_handwritten_xml() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Label</key>
+    <string>com.example.agent</string>
+    <key>ProgramArguments</key>
+    <array>
+        <string>/usr/bin/true</string> <!-- Path to the binary -->
+        <string><![CDATA[a <raw> & value]]></string>
+        <string/>
+    </array>
+    <key>Blob</key>
+    <data>
+        AAEC
+        +vv8
+    </data>
+    <key>RunAtLoad</key>
+    <true/>
+    <key>Escaped</key>
+    <string>&#65;&#x42;&amp;</string>
+</dict>
+</plist>"#;
+        let value = Value::from_xml(xml).unwrap();
+        let dict = value.as_dictionary().unwrap();
+        assert_eq!(dict["Label"].as_string(), Some("com.example.agent"));
+        assert_eq!(
+            dict["ProgramArguments"].as_array().unwrap(),
+            [
+                Value::from("/usr/bin/true"),
+                Value::from("a <raw> & value"),
+                Value::from(""),
+            ]
+        );
+        assert_eq!(dict["Blob"].as_data(), Some(&[0, 1, 2, 250, 251, 252][..]));
+        assert_eq!(dict["RunAtLoad"].as_boolean(), Some(true));
+        assert_eq!(dict["Escaped"].as_string(), Some("AB&"));
+    }
+
+    #[test]
+    fn test_binary_round_trip() {
+        let mut dict = Dictionary::new();
+        dict.insert("ASCII".to_string(), Value::from("com.example.agent"));
+        dict.insert("Unicode".to_string(), Value::from("Überprüfung ✓ 🚀"));
+        dict.insert("Long".to_string(), Value::from("x".repeat(300)));
+        dict.insert("Small".to_string(), Value::Integer(7));
+        dict.insert("Large".to_string(), Value::Integer(1 << 40));
+        dict.insert("Negative".to_string(), Value::Integer(-1));
+        dict.insert("Real".to_string(), Value::Real(-2.5));
+        dict.insert("False".to_string(), Value::Boolean(false));
+        dict.insert(
+            "When".to_string(),
+            Value::Date(Date::from_reference_seconds(86_400.0)),
+        );
+        dict.insert("Blob".to_string(), Value::Data((0..=255).collect()));
+        dict.insert(
+            "Items".to_string(),
+            Value::Array(
+                (0..20)
+                    .map(|i| Value::from(format!("item {}", i % 3)))
+                    .collect(),
+            ),
+        );
+        dict.insert("Empty".to_string(), Value::Dictionary(Dictionary::new()));
+        let value = Value::Dictionary(dict);
+
+        let data = value.to_binary();
+        assert!(data.starts_with(b"bplist00"));
+        assert_eq!(Value::from_binary(&data).unwrap(), value);
+        assert_eq!(Value::from_bytes(&data).unwrap(), value);
+        assert_eq!(Value::from_bytes(value.to_xml().as_bytes()).unwrap(), value);
+    }
+
+    #[test]
+    fn test_json_conversion() {
+        let json = serde_json::json!({
+            "Label": "com.example.agent",
+            "ProgramArguments": ["/usr/bin/true"],
+            "RunAtLoad": true,
+            "Nice": -5,
+            "Ratio": 0.5,
+        });
+        let value = Value::from_json(&json).unwrap();
+        assert_eq!(value.as_dictionary().unwrap()["Nice"], Value::Integer(-5));
+        assert_eq!(value.as_dictionary().unwrap()["Ratio"], Value::Real(0.5));
+        assert_eq!(value.to_json(), json);
+
+        let value = Value::Array(vec![
+            Value::Data(b"foo".to_vec()),
+            Value::Date(Date::from_unix_seconds(0)),
+        ]);
+        assert_eq!(
+            value.to_json(),
+            serde_json::json!(["Zm9v", "1970-01-01T00:00:00Z"])
+        );
+        assert!(matches!(
+            Value::from_json(&serde_json::json!({ "key": null })),
+            Err(PlistError::Json(_))
+        ));
+    }
+
+    #[test]
+    fn test_invalid_xml() {
+        for xml in [
+            "",
+            "<plist><dict><key>a</key></dict></plist>",
+            "<plist><integer>abc</integer></plist>",
+            "<plist><string>unterminated</plist>",
+            "<plist><true/><false/></plist>",
+        ] {
+            assert!(
+                matches!(Value::from_xml(xml), Err(PlistError::Xml(_))),
+                "{xml:?} should be rejected"
+            );
+        }
+    }
+}
@@ -0,0 +1,454 @@
+//! Reading and writing the XML property list encoding.
+
+use super::{Date, Dictionary, MAX_DEPTH, PlistError, Value};
+
+const HEADER: &str = concat!(
+    "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+    "<!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" ",
+    "\"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">\n",
+    "<plist version=\"1.0\">\n",
+);
+
+pub(super) fn write(value: &Value) -> String {
+    let mut out = String::from(HEADER);
+    write_value(&mut out, value, 0);
+    out.push_str("</plist>\n");
+    out
+}
+
+fn write_value(out: &mut String, value: &Value, depth: usize) {
+    let indent = "\t".repeat(depth);
+    match value {
+        Value::String(value) => {
+            out.push_str(&format!("{indent}<string>{}</string>\n", escape(value)));
+        }
+        Value::Integer(value) => out.push_str(&format!("{indent}<integer>{value}</integer>\n")),
+        Value::Real(value) => {
+            let text = if value.is_nan() {
+                "nan".to_string()
+            } else if value.is_infinite() {
+                if *value > 0.0 {
+                    "+infinity"
+                } else {
+                    "-infinity"
+                }
+                .to_string()
+            } else {
+                value.to_string()
+            };
+            out.push_str(&format!("{indent}<real>{text}</real>\n"));
+        }
+        Value::Boolean(true) => out.push_str(&format!("{indent}<true/>\n")),
+        Value::Boolean(false) => out.push_str(&format!("{indent}<false/>\n")),
+        Value::Date(value) => {
+            out.push_str(&format!("{indent}<date>{}</date>\n", value.to_iso8601()));
+        }
+        Value::Data(value) => {
+            out.push_str(&format!("{indent}<data>{}</data>\n", base64_encode(value)));
+        }
+        Value::Array(values) if values.is_empty() => out.push_str(&format!("{indent}<array/>\n")),
+        Value::Array(values) => {
+            out.push_str(&format!("{indent}<array>\n"));
+            for value in values {
+                write_value(out, value, depth + 1);
+            }
+            out.push_str(&format!("{indent}</array>\n"));
+        }
+        Value::Dictionary(dict) if dict.is_empty() => out.push_str(&format!("{indent}<dict/>\n")),
+        Value::Dictionary(dict) => {
+            out.push_str(&format!("{indent}<dict>\n"));
+            for (key, value) in dict {
+                out.push_str(&format!("{indent}\t<key>{}</key>\n", escape(key)));
+                write_value(out, value, depth + 1);
+            }
+            out.push_str(&format!("{indent}</dict>\n"));
+        }
+    }
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+pub(super) fn parse(xml: &str) -> Result<Value, PlistError> {
+    let mut parser = Parser {
+        input: xml,
+        pos: 0,
+        depth: 0,
+    };
+    parser.skip_misc()?;
+    let value = match parser.peek_open_tag() {
+        Some("plist") => {
+            let tag = parser.open_tag()?;
+            if tag.self_closing {
+                return Err(parser.error("the <plist> element is empty"));
+            }
+            let value = parser.value()?;
+            parser.skip_misc()?;
+            parser.close_tag("plist")?;
+            value
+        }
+        _ => parser.value()?,
+    };
+    parser.skip_misc()?;
+    if parser.pos != xml.len() {
+        return Err(parser.error("unexpected content after the root element"));
+    }
+    Ok(value)
+}
+
+struct Tag<'a> {
+    name: &'a str,
+    self_closing: bool,
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+    /// How many elements enclose the one being parsed.
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, message: &str) -> PlistError {
+        let line = self.input[..self.pos].matches('\n').count() + 1;
+        PlistError::Xml(format!("line {line}: {message}"))
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.input[self.pos..]
+    }
+
+    fn skip_until(&mut self, terminator: &str) -> Result<&'a str, PlistError> {
+        match self.rest().find(terminator) {
+            Some(index) => {
+                let skipped = &self.rest()[..index];
+                self.pos += index + terminator.len();
+                Ok(skipped)
+            }
+            None => Err(self.error(&format!("missing `{terminator}`"))),
+        }
+    }
+
+    /// Skips whitespace, comments, processing instructions and the doctype.
+    fn skip_misc(&mut self) -> Result<(), PlistError> {
+        loop {
+            let trimmed = self.rest().trim_start();
+            self.pos = self.input.len() - trimmed.len();
+            if trimmed.starts_with("<!--") {
+                self.skip_until("-->")?;
+            } else if trimmed.starts_with("<?") {
+                self.skip_until("?>")?;
+            } else if trimmed.starts_with("<!DOCTYPE") {
+                self.skip_until(">")?;
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    fn peek_open_tag(&self) -> Option<&'a str> {
+        let rest = self.rest().strip_prefix('<')?;
+        if rest.starts_with('/') {
+            return None;
+        }
+        let end = rest
+            .find(|c: char| c.is_whitespace() || c == '>' || c == '/')
+            .unwrap_or(rest.len());
+        Some(&rest[..end])
+    }
+
+    fn open_tag(&mut self) -> Result<Tag<'a>, PlistError> {
+        let name = self
+            .peek_open_tag()
+            .filter(|name| !name.is_empty())
+            .ok_or_else(|| self.error("expected an element"))?;
+        self.pos += 1 + name.len();
+        // Skip attributes, honoring quoted values that may contain `>`.
+        let mut quote = None;
+        for (index, c) in self.rest().char_indices() {
+            match (quote, c) {
+                (Some(q), c) if c == q => quote = None,
+                (Some(_), _) => {}
+                (None, '"' | '\'') => quote = Some(c),
+                (None, '>') => {
+                    let self_closing = self.rest()[..index].trim_end().ends_with('/');
+                    self.pos += index + 1;
+                    return Ok(Tag { name, self_closing });
+                }
+                (None, _) => {}
+            }
+        }
+        Err(self.error(&format!("unterminated <{name}> tag")))
+    }
+
+    fn close_tag(&mut self, name: &str) -> Result<(), PlistError> {
+        let rest = self.rest();
+        let matches = rest
+            .strip_prefix("</")
+            .and_then(|rest| rest.strip_prefix(name))
+            .map(str::trim_start)
+            .filter(|rest| rest.starts_with('>'));
+        match matches {
+            Some(after) => {
+                self.pos = self.input.len() - after.len() + 1;
+                Ok(())
+            }
+            None => Err(self.error(&format!("expected </{name}>"))),
+        }
+    }
+
+    /// Reads the character data of an element up to its closing tag.
+    fn text(&mut self, tag: &Tag) -> Result<String, PlistError> {
+        let mut text = String::new();
+        if tag.self_closing {
+            return Ok(text);
+        }
+        loop {
+            let rest = self.rest();
+            let index = rest
+                .find('<')
+                .ok_or_else(|| self.error(&format!("unterminated <{}> element", tag.name)))?;
+            text.push_str(&self.unescape(&rest[..index])?);
+            self.pos += index;
+            if self.rest().starts_with("<![CDATA[") {
+                self.pos += "<![CDATA[".len();
+                text.push_str(self.skip_until("]]>")?);
+            } else if self.rest().starts_with("<!--") {
+                self.skip_until("-->")?;
+            } else {
+                self.close_tag(tag.name)?;
+                return Ok(text);
+            }
+        }
+    }
+
+    fn unescape(&self, raw: &str) -> Result<String, PlistError> {
+        let mut out = String::with_capacity(raw.len());
+        let mut rest = raw;
+        while let Some(index) = rest.find('&') {
+            out.push_str(&rest[..index]);
+            rest = &rest[index + 1..];
+            let end = rest
+                .find(';')
+                .ok_or_else(|| self.error("unterminated entity reference"))?;
+            let entity = &rest[..end];
+            let c = match entity {
+                "amp" => '&',
+                "lt" => '<',
+                "gt" => '>',
+                "quot" => '"',
+                "apos" => '\'',
+                _ => {
+                    let code = if let Some(hex) = entity.strip_prefix("#x") {
+                        u32::from_str_radix(hex, 16).ok()
+                    } else if let Some(decimal) = entity.strip_prefix('#') {
+                        decimal.parse().ok()
+                    } else {
+                        None
+                    };
+                    code.and_then(char::from_u32)
+                        .ok_or_else(|| self.error(&format!("unknown entity `&{entity};`")))?
+                }
+            };
+            out.push(c);
+            rest = &rest[end + 1..];
+        }
+        out.push_str(rest);
+        Ok(out)
+    }
+
+    fn value(&mut self) -> Result<Value, PlistError> {
+        if self.depth >= MAX_DEPTH {
+            return Err(self.error("elements are nested too deeply"));
+        }
+        self.skip_misc()?;
+        let tag = self.open_tag()?;
+        self.depth += 1;
+        let value = match tag.name {
+            "array" => self.array(&tag),
+            "dict" => self.dict(&tag),
+            _ => self.scalar(&tag),
+        };
+        self.depth -= 1;
+        value
+    }
+
+    fn array(&mut self, tag: &Tag) -> Result<Value, PlistError> {
+        let mut values = Vec::new();
+        if tag.self_closing {
+            return Ok(Value::Array(values));
+        }
+        loop {
+            self.skip_misc()?;
+            if self.rest().starts_with("</") {
+                self.close_tag("array")?;
+                return Ok(Value::Array(values));
+            }
+            values.push(self.value()?);
+        }
+    }
+
+    fn dict(&mut self, tag: &Tag) -> Result<Value, PlistError> {
+        let mut dict = Dictionary::new();
+        if tag.self_closing {
+            return Ok(Value::Dictionary(dict));
+        }
+        loop {
+            self.skip_misc()?;
+            if self.rest().starts_with("</") {
+                self.close_tag("dict")?;
+                return Ok(Value::Dictionary(dict));
+            }
+            let key_tag = self.open_tag()?;
+            if key_tag.name != "key" {
+                return Err(self.error(&format!(
+                    "expected <key> in <dict>, found <{}>",
+                    key_tag.name
+                )));
+            }
+            let key = self.text(&key_tag)?;
+            self.skip_misc()?;
+            if self.rest().starts_with("</") {
+                return Err(self.error(&format!("missing value for key `{key}`")));
+            }
+            let value = self.value()?;
+            dict.insert(key, value);
+        }
+    }
+
+    // Kept out of `value`, which recurses, so its locals don't grow every level's stack frame.
+    #[inline(never)]
+    fn scalar(&mut self, tag: &Tag) -> Result<Value, PlistError> {
+        match tag.name {
+            "string" => Ok(Value::String(self.text(tag)?)),
+            "integer" => {
+                let text = self.text(tag)?;
+                let text = text.trim();
+                let parsed = match text.strip_prefix("0x") {
+                    Some(hex) => i64::from_str_radix(hex, 16).ok(),
+                    None => text.parse().ok(),
+                };
+                parsed
+                    .map(Value::Integer)
+                    .ok_or_else(|| self.error(&format!("invalid integer `{text}`")))
+            }
+            "real" => {
+                let text = self.text(tag)?;
+                let text = text.trim();
+                text.parse()
+                    .map(Value::Real)
+                    .map_err(|_| self.error(&format!("invalid real `{text}`")))
+            }
+            "true" | "false" => {
+                if !self.text(tag)?.trim().is_empty() {
+                    return Err(self.error(&format!("<{}> must be empty", tag.name)));
+                }
+                Ok(Value::Boolean(tag.name == "true"))
+            }
+            "date" => {
+                let text = self.text(tag)?;
+                Date::from_iso8601(text.trim())
+                    .map(Value::Date)
+                    .ok_or_else(|| self.error(&format!("invalid date `{}`", text.trim())))
+            }
+            "data" => {
+                let text = self.text(tag)?;
+                base64_decode(&text)
+                    .map(Value::Data)
+                    .ok_or_else(|| self.error("invalid base64 data"))
+            }
+            name => Err(self.error(&format!("unexpected element <{name}>"))),
+        }
+    }
+}
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+pub(crate) fn base64_encode(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = (u32::from(b[0]) << 16) | (u32::from(b[1]) << 8) | u32::from(b[2]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64_ALPHABET[(n >> (18 - 6 * i)) as usize & 0x3f] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+pub(crate) fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(text.len() / 4 * 3);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    let mut padding = 0;
+    for c in text.bytes().filter(|c| !c.is_ascii_whitespace()) {
+        if c == b'=' {
+            padding += 1;
+            continue;
+        }
+        if padding > 0 {
+            return None;
+        }
+        let index = BASE64_ALPHABET.iter().position(|&a| a == c)? as u32;
+        buffer = (buffer << 6) | index;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+    if padding > 2 {
+        return None;
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base64() {
+        for (bytes, encoded) in [
+            (&b""[..], ""),
+            (b"f", "Zg=="),
+            (b"fo", "Zm8="),
+            (b"foo", "Zm9v"),
+            (b"foob", "Zm9vYg=="),
+        ] {
+            assert_eq!(base64_encode(bytes), encoded);
+            assert_eq!(base64_decode(encoded).as_deref(), Some(bytes));
+        }
+        assert_eq!(base64_decode("Zm9v!"), None);
+    }
+
+    #[test]
+    fn test_nesting_limit() {
+        let nested = |depth| format!("{}{}", "<array>".repeat(depth), "</array>".repeat(depth));
+        assert!(parse(&nested(MAX_DEPTH)).is_ok());
+        assert!(matches!(
+            parse(&nested(200_000)),
+            Err(PlistError::Xml(message)) if message.contains("nested too deeply")
+        ));
+    }
+}
@@ -0,0 +1,331 @@
+//! Checks that a service's files are in place before it's registered.
+
+use std::path::{Path, PathBuf};
+
+use thiserror::Error;
+
+use crate::bundle::bundle_executable;
+use crate::{InfoPlist, LaunchdPlist, ServiceManagementError, ServiceType, ValidationReport};
+
+/// A problem found by [`preflight`] that would make registration fail.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum PreflightError {
+    /// The path isn't a directory with a `Contents` subdirectory.
+    #[error("`{0}` is not an app bundle")]
+    NotABundle(PathBuf),
+
+    /// The agent or daemon property list doesn't exist.
+    #[error("property list `{0}` does not exist")]
+    PlistNotFound(PathBuf),
+
+    /// The agent or daemon property list couldn't be parsed or has errors.
+    #[error("property list `{path}` is invalid: {reason}")]
+    InvalidPlist {
+        /// The property list.
+        path: PathBuf,
+        /// The first problem found.
+        reason: String,
+    },
+
+    /// No helper app in `Contents/Library/LoginItems` has the requested identifier.
+    #[error("no login item with identifier `{identifier}` in `{directory}`")]
+    HelperNotFound {
+        /// The identifier that was requested.
+        identifier: String,
+        /// The `LoginItems` directory that was searched.
+        directory: PathBuf,
+    },
+
+    /// A helper app named after the requested identifier declares a different identifier.
+    #[error("`{path}` has CFBundleIdentifier `{found}`, expected `{expected}`")]
+    IdentifierMismatch {
+        /// The helper app.
+        path: PathBuf,
+        /// The identifier that was requested.
+        expected: String,
+        /// The identifier in the helper's `Info.plist`.
+        found: String,
+    },
+
+    /// The program the service runs doesn't exist.
+    #[error("executable `{0}` does not exist")]
+    ExecutableNotFound(PathBuf),
+
+    /// The program the service runs isn't an executable file.
+    #[error("`{0}` is not executable")]
+    NotExecutable(PathBuf),
+}
+
+impl PreflightError {
+    /// Returns the error the ServiceManagement framework would most likely report for this
+    /// problem.
+    pub fn service_management_error(&self) -> ServiceManagementError {
+        match self {
+            PreflightError::PlistNotFound(_) => ServiceManagementError::JobPlistNotFound,
+            PreflightError::InvalidPlist { .. } => ServiceManagementError::InvalidPlist,
+            PreflightError::HelperNotFound { .. } | PreflightError::IdentifierMismatch { .. } => {
+                ServiceManagementError::JobNotFound
+            }
+            PreflightError::NotABundle(_)
+            | PreflightError::ExecutableNotFound(_)
+            | PreflightError::NotExecutable(_) => ServiceManagementError::ToolNotValid,
+        }
+    }
+}
+
+impl From<PreflightError> for ServiceManagementError {
+    fn from(error: PreflightError) -> Self {
+        error.service_management_error()
+    }
+}
+
+/// Checks that the files `service_type` refers to are in place inside the bundle at `bundle`.
+///
+/// For agents and daemons the property list must exist in `Contents/Library/LaunchAgents` or
+/// `Contents/Library/LaunchDaemons` and pass [`ValidationReport`], and its `BundleProgram` or
+/// absolute `Program` must be an executable file. For login items a helper app with a matching
+/// `CFBundleIdentifier` must exist in `Contents/Library/LoginItems` with an executable. For the
+/// main app, the bundle's own executable is checked.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use smappservice_rs::{preflight, ServiceType};
+///
+/// let service_type = ServiceType::Agent { plist_name: "com.example.myapp.agent.plist" };
+/// if let Err(error) = preflight("/Applications/MyApp.app", &service_type) {
+///     eprintln!("registration would fail with {}: {error}", error.service_management_error());
+/// }
+/// ```
+pub fn preflight(
+    bundle: impl AsRef<Path>,
+    service_type: &ServiceType,
+) -> Result<(), PreflightError> {
+    let bundle = bundle.as_ref();
+    let contents = bundle.join("Contents");
+    if !contents.is_dir() {
+        return Err(PreflightError::NotABundle(bundle.to_path_buf()));
+    }
+    match service_type {
+        ServiceType::MainApp => check_executable(&bundle_executable(bundle)),
+        ServiceType::Agent { plist_name } => check_launchd_plist(
+            bundle,
+            &contents.join("Library/LaunchAgents").join(plist_name),
+        ),
+        ServiceType::Daemon { plist_name } => check_launchd_plist(
+            bundle,
+            &contents.join("Library/LaunchDaemons").join(plist_name),
+        ),
+        ServiceType::LoginItem { identifier } => {
+            let helper = find_helper(&contents.join("Library/LoginItems"), identifier)?;
+            check_executable(&bundle_executable(&helper))
+        }
+    }
+}
+
+/// Returns the app bundle containing the running executable, if it's laid out as
+/// `<bundle>/Contents/MacOS/<executable>`.
+pub(crate) fn current_bundle() -> Option<PathBuf> {
+    let executable = std::env::current_exe().ok()?;
+    let macos = executable.parent()?;
+    let contents = macos.parent()?;
+    if macos.file_name()? != "MacOS" || contents.file_name()? != "Contents" {
+        return None;
+    }
+    contents.parent().map(Path::to_path_buf)
+}
+
+fn check_launchd_plist(bundle: &Path, path: &Path) -> Result<(), PreflightError> {
+    let report = ValidationReport::for_file(path);
+    if !report.found {
+        return Err(PreflightError::PlistNotFound(path.to_path_buf()));
+    }
+    if let Some(diagnostic) = report.errors().next() {
+        return Err(PreflightError::InvalidPlist {
+            path: path.to_path_buf(),
+            reason: diagnostic.to_string(),
+        });
+    }
+    let plist = LaunchdPlist::from_file(path).map_err(|error| PreflightError::InvalidPlist {
+        path: path.to_path_buf(),
+        reason: error.to_string(),
+    })?;
+
+    if let Some(bundle_program) = &plist.bundle_program {
+        return check_executable(&bundle.join(bundle_program));
+    }
+    let program = plist.program.as_deref().or_else(|| {
+        plist
+            .program_arguments
+            .as_ref()
+            .and_then(|arguments| arguments.first())
+            .map(String::as_str)
+    });
+    match program {
+        Some(program) if Path::new(program).is_absolute() => check_executable(Path::new(program)),
+        _ => Ok(()),
+    }
+}
+
+/// Finds the helper app declaring `identifier`. If none does but one is named after the
+/// identifier, that helper's identifier is reported as a mismatch.
+pub(crate) fn find_helper(directory: &Path, identifier: &str) -> Result<PathBuf, PreflightError> {
+    let mut helpers: Vec<PathBuf> = std::fs::read_dir(directory)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.extension().is_some_and(|ext| ext == "app"))
+                .collect()
+        })
+        .unwrap_or_default();
+    helpers.sort();
+
+    let mut mismatch = None;
+    for helper in helpers {
+        let found = bundle_identifier(&helper);
+        if found.as_deref() == Some(identifier) {
+            return Ok(helper);
+        }
+        let stem = helper.file_stem().and_then(|stem| stem.to_str());
+        if mismatch.is_none()
+            && stem
+                .is_some_and(|stem| stem == identifier || identifier.ends_with(&format!(".{stem}")))
+        {
+            mismatch = Some(PreflightError::IdentifierMismatch {
+                path: helper,
+                expected: identifier.to_string(),
+                found: found.unwrap_or_default(),
+            });
+        }
+    }
+    Err(mismatch.unwrap_or_else(|| PreflightError::HelperNotFound {
+        identifier: identifier.to_string(),
+        directory: directory.to_path_buf(),
+    }))
+}
+
+fn bundle_identifier(bundle: &Path) -> Option<String> {
+    InfoPlist::for_bundle(bundle).ok()?.bundle_identifier
+}
+
+fn check_executable(path: &Path) -> Result<(), PreflightError> {
+    let metadata = std::fs::metadata(path)
+        .map_err(|_| PreflightError::ExecutableNotFound(path.to_path_buf()))?;
+    if !metadata.is_file() || !is_executable(&metadata) {
+        return Err(PreflightError::NotExecutable(path.to_path_buf()));
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn is_executable(metadata: &std::fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o111 != 0
+}
+
+#[cfg(not(unix))]
+fn is_executable(_metadata: &std::fs::Metadata) -> bool {
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bundle::fixture::FixtureBundle;
+
+    fn write_executable(bundle: &FixtureBundle, relative: &str) {
+        let path = bundle.write_file(relative, b"#!/bin/sh\n");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_preflight_agent() {
+        let bundle = FixtureBundle::new("Agent");
+        let agent = ServiceType::Agent {
+            plist_name: "com.example.agent.plist",
+        };
+        let error = preflight(&bundle.path, &agent).unwrap_err();
+        assert!(matches!(error, PreflightError::PlistNotFound(_)));
+        assert_eq!(
+            error.service_management_error(),
+            ServiceManagementError::JobPlistNotFound
+        );
+
+        bundle.add_launchd_plist(
+            "LaunchAgents",
+            &LaunchdPlist {
+                bundle_program: Some("Contents/MacOS/agent".to_string()),
+                ..LaunchdPlist::new("com.example.agent")
+            },
+        );
+        let error = preflight(&bundle.path, &agent).unwrap_err();
+        assert!(matches!(error, PreflightError::ExecutableNotFound(_)));
+        assert_eq!(
+            ServiceManagementError::from(error),
+            ServiceManagementError::ToolNotValid
+        );
+
+        write_executable(&bundle, "Contents/MacOS/agent");
+        assert_eq!(preflight(&bundle.path, &agent), Ok(()));
+
+        bundle.add_launchd_plist("LaunchDaemons", &LaunchdPlist::new("com.example.daemon"));
+        let daemon = ServiceType::Daemon {
+            plist_name: "com.example.daemon.plist",
+        };
+        assert!(matches!(
+            preflight(&bundle.path, &daemon),
+            Err(PreflightError::InvalidPlist { .. })
+        ));
+    }
+
+    #[test]
+    fn test_preflight_login_item() {
+        let bundle = FixtureBundle::new("Main");
+        let login_item = ServiceType::LoginItem {
+            identifier: "com.example.Helper",
+        };
+        assert!(matches!(
+            preflight(&bundle.path, &login_item),
+            Err(PreflightError::HelperNotFound { .. })
+        ));
+
+        let mut info = InfoPlist {
+            bundle_identifier: Some("com.example.helper2".to_string()),
+            ..InfoPlist::default()
+        };
+        bundle.add_login_item("Helper", &info);
+        assert_eq!(
+            preflight(&bundle.path, &login_item),
+            Err(PreflightError::IdentifierMismatch {
+                path: bundle.path.join("Contents/Library/LoginItems/Helper.app"),
+                expected: "com.example.Helper".to_string(),
+                found: "com.example.helper2".to_string(),
+            })
+        );
+
+        info.bundle_identifier = Some("com.example.Helper".to_string());
+        info.executable = Some("helper".to_string());
+        bundle.add_login_item("Helper", &info);
+        bundle.write_file(
+            "Contents/Library/LoginItems/Helper.app/Contents/MacOS/helper",
+            b"",
+        );
+        #[cfg(unix)]
+        assert!(matches!(
+            preflight(&bundle.path, &login_item),
+            Err(PreflightError::NotExecutable(_))
+        ));
+        write_executable(
+            &bundle,
+            "Contents/Library/LoginItems/Helper.app/Contents/MacOS/helper",
+        );
+        assert_eq!(preflight(&bundle.path, &login_item), Ok(()));
+
+        write_executable(&bundle, "Contents/MacOS/Main");
+        assert_eq!(preflight(&bundle.path, &ServiceType::MainApp), Ok(()));
+    }
+}
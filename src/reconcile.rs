@@ -0,0 +1,402 @@
+//! Bringing services in line with a declared desired state.
+//!
+//! An installer, a settings pane and an uninstaller can share one [`DesiredState`] instead of
+//! each making their own `register` and `unregister` calls. A [`Reconciler`] compares it with the
+//! current statuses to produce a [`Plan`], which can be shown to the user and then applied.
+
+use std::fmt;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    AppService, DefaultBackend, EnsureReport, Operation, OwnedServiceType, ServiceBackend,
+    ServiceManagementError, ServiceStatus,
+};
+
+/// An error reading or writing a [`DesiredState`].
+#[derive(Debug, Error)]
+pub enum DesiredStateError {
+    /// The JSON was malformed or didn't describe a desired state.
+    #[error("invalid desired state JSON: {0}")]
+    Json(#[from] serde_json::Error),
+
+    /// The TOML was malformed or didn't describe a desired state.
+    #[cfg(feature = "toml")]
+    #[error("invalid desired state TOML: {0}")]
+    TomlDe(#[from] toml::de::Error),
+
+    /// The desired state couldn't be written as TOML.
+    #[cfg(feature = "toml")]
+    #[error("failed to write desired state TOML: {0}")]
+    TomlSer(#[from] toml::ser::Error),
+}
+
+/// Whether a service should be registered.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DesiredService {
+    /// The service.
+    #[serde(flatten)]
+    pub service_type: OwnedServiceType,
+
+    /// `true` to register the service, `false` to unregister it.
+    pub registered: bool,
+}
+
+/// The registration state wanted for a set of services.
+///
+/// Serializes as a list of services under a `service` key. In TOML, with the `toml` feature:
+///
+/// ```toml
+/// [[service]]
+/// kind = "main_app"
+/// registered = true
+///
+/// [[service]]
+/// kind = "agent"
+/// plist_name = "com.example.myapp.sync.plist"
+/// registered = true
+///
+/// [[service]]
+/// kind = "daemon"
+/// plist_name = "com.example.myapp.updater.plist"
+/// registered = false
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DesiredState {
+    /// The services, in the order they are planned and applied.
+    #[serde(rename = "service", default)]
+    pub services: Vec<DesiredService>,
+}
+
+impl DesiredState {
+    /// Parses a desired state from JSON.
+    pub fn from_json(json: &str) -> Result<Self, DesiredStateError> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// Serializes the desired state as pretty-printed JSON.
+    pub fn to_json(&self) -> Result<String, DesiredStateError> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Parses a desired state from TOML.
+    #[cfg(feature = "toml")]
+    pub fn from_toml(toml: &str) -> Result<Self, DesiredStateError> {
+        Ok(toml::from_str(toml)?)
+    }
+
+    /// Serializes the desired state as TOML.
+    #[cfg(feature = "toml")]
+    pub fn to_toml(&self) -> Result<String, DesiredStateError> {
+        Ok(toml::to_string(self)?)
+    }
+}
+
+/// What a [`Plan`] will do to one service.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlannedChange {
+    /// The service.
+    pub service_type: OwnedServiceType,
+
+    /// The status when the plan was made.
+    pub current: ServiceStatus,
+
+    /// [`Operation::Register`] or [`Operation::Unregister`], or `None` if the service is already
+    /// in the desired state.
+    pub operation: Option<Operation>,
+}
+
+/// The changes needed to reach a [`DesiredState`], made by [`Reconciler::plan`].
+///
+/// Displays as a diff, one line per service: `+` for services to register, `-` for services to
+/// unregister, and no marker for services left as they are.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Plan {
+    /// One entry per service in the desired state.
+    pub changes: Vec<PlannedChange>,
+}
+
+impl Plan {
+    /// Returns whether every service is already in the desired state.
+    pub fn is_empty(&self) -> bool {
+        self.changes.iter().all(|change| change.operation.is_none())
+    }
+}
+
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for change in &self.changes {
+            match change.operation {
+                Some(Operation::Register) => writeln!(
+                    f,
+                    "+ {} ({}, will register)",
+                    change.service_type, change.current
+                )?,
+                Some(operation) => writeln!(
+                    f,
+                    "- {} ({}, will {})",
+                    change.service_type, change.current, operation
+                )?,
+                None => writeln!(f, "  {} ({})", change.service_type, change.current)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The result of applying one [`PlannedChange`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServiceResult {
+    /// The service.
+    pub service_type: OwnedServiceType,
+
+    /// The operation that was planned, if any.
+    pub operation: Option<Operation>,
+
+    /// What the operation did, or why it failed.
+    pub result: Result<EnsureReport, ServiceManagementError>,
+}
+
+/// The per-service results of [`Reconciler::apply`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ApplyReport {
+    /// One result per change in the plan, in the same order.
+    pub results: Vec<ServiceResult>,
+}
+
+impl ApplyReport {
+    /// Returns whether every planned operation succeeded.
+    pub fn is_success(&self) -> bool {
+        self.results.iter().all(|result| result.result.is_ok())
+    }
+
+    /// Returns the services that still need the user's approval in System Settings.
+    pub fn requiring_approval(&self) -> impl Iterator<Item = &OwnedServiceType> {
+        self.results
+            .iter()
+            .filter(|result| matches!(&result.result, Ok(report) if report.requires_user_action()))
+            .map(|result| &result.service_type)
+    }
+}
+
+impl fmt::Display for ApplyReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for result in &self.results {
+            match &result.result {
+                Ok(report) if report.changed => writeln!(
+                    f,
+                    "{}: {} -> {}",
+                    result.service_type, report.before, report.after
+                )?,
+                Ok(report) => writeln!(f, "{}: unchanged ({})", result.service_type, report.after)?,
+                Err(error) => writeln!(f, "{}: failed ({})", result.service_type, error)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Plans and applies a [`DesiredState`] through a [`ServiceBackend`].
+///
+/// # Examples
+///
+/// ```rust
+/// use smappservice_rs::{DesiredService, DesiredState, OwnedServiceType, Reconciler, SimulatedBackend};
+///
+/// let desired = DesiredState {
+///     services: vec![DesiredService {
+///         service_type: OwnedServiceType::MainApp,
+///         registered: true,
+///     }],
+/// };
+/// let reconciler = Reconciler::with_backend(SimulatedBackend::new());
+///
+/// let plan = reconciler.plan(&desired);
+/// print!("{}", plan);
+/// let report = reconciler.apply(&plan);
+/// assert!(report.is_success());
+/// assert!(reconciler.plan(&desired).is_empty());
+/// ```
+pub struct Reconciler {
+    backend: Arc<dyn ServiceBackend>,
+}
+
+impl Default for Reconciler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for Reconciler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Reconciler").finish_non_exhaustive()
+    }
+}
+
+impl Reconciler {
+    /// Creates a reconciler that uses the platform's [`DefaultBackend`].
+    pub fn new() -> Self {
+        Self::with_backend(DefaultBackend::default())
+    }
+
+    /// Creates a reconciler that goes through `backend`.
+    pub fn with_backend(backend: impl ServiceBackend + 'static) -> Self {
+        Self {
+            backend: Arc::new(backend),
+        }
+    }
+
+    /// Compares `desired` with the current status of each service.
+    ///
+    /// A service that should be registered is planned for registration when it's
+    /// [`ServiceStatus::NotRegistered`] or [`ServiceStatus::NotFound`]. A service that should not
+    /// be is planned for unregistration when it's [`ServiceStatus::Enabled`] or
+    /// [`ServiceStatus::RequiresApproval`].
+    pub fn plan(&self, desired: &DesiredState) -> Plan {
+        let changes = desired
+            .services
+            .iter()
+            .map(|service| {
+                let current = self.backend.status(&service.service_type.as_service_type());
+                let registered = matches!(
+                    current,
+                    ServiceStatus::Enabled | ServiceStatus::RequiresApproval
+                );
+                let operation = match (service.registered, registered) {
+                    (true, false) => Some(Operation::Register),
+                    (false, true) => Some(Operation::Unregister),
+                    _ => None,
+                };
+                PlannedChange {
+                    service_type: service.service_type.clone(),
+                    current,
+                    operation,
+                }
+            })
+            .collect();
+        Plan { changes }
+    }
+
+    /// Applies `plan`, continuing past failures so every service gets a result.
+    ///
+    /// Each operation goes through [`AppService::ensure_registered`] or
+    /// [`AppService::ensure_unregistered`], so a plan that has gone stale since it was made
+    /// doesn't fail on services that already reached the desired state.
+    pub fn apply(&self, plan: &Plan) -> ApplyReport {
+        let results = plan
+            .changes
+            .iter()
+            .map(|change| {
+                let service = AppService::with_backend(
+                    change.service_type.as_service_type(),
+                    self.backend.clone(),
+                );
+                let result = match change.operation {
+                    Some(Operation::Register) => service.ensure_registered(),
+                    Some(Operation::Unregister) => service.ensure_unregistered(),
+                    _ => Ok(EnsureReport {
+                        changed: false,
+                        before: change.current,
+                        after: change.current,
+                    }),
+                };
+                ServiceResult {
+                    service_type: change.service_type.clone(),
+                    operation: change.operation,
+                    result,
+                }
+            })
+            .collect();
+        ApplyReport { results }
+    }
+
+    /// Plans and applies `desired` in one step.
+    pub fn reconcile(&self, desired: &DesiredState) -> ApplyReport {
+        self.apply(&self.plan(desired))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FaultInjectingBackend, ServiceType, SimulatedBackend};
+
+    const STATE: &str = r#"{
+        "service": [
+            {"kind": "main_app", "registered": true},
+            {"kind": "agent", "plist_name": "com.example.sync.plist", "registered": true},
+            {"kind": "daemon", "plist_name": "com.example.updater.plist", "registered": false},
+            {"kind": "daemon", "plist_name": "com.example.helper.plist", "registered": true}
+        ]
+    }"#;
+
+    const AGENT: ServiceType<'static> = ServiceType::Agent {
+        plist_name: "com.example.sync.plist",
+    };
+    const UPDATER: ServiceType<'static> = ServiceType::Daemon {
+        plist_name: "com.example.updater.plist",
+    };
+    const HELPER: ServiceType<'static> = ServiceType::Daemon {
+        plist_name: "com.example.helper.plist",
+    };
+
+    #[test]
+    fn test_plan_and_apply() {
+        let desired = DesiredState::from_json(STATE).unwrap();
+        assert_eq!(
+            DesiredState::from_json(&desired.to_json().unwrap()).unwrap(),
+            desired
+        );
+
+        let backend = FaultInjectingBackend::new(SimulatedBackend::new());
+        backend
+            .inner()
+            .set_status(&ServiceType::MainApp, ServiceStatus::Enabled);
+        backend.inner().set_status(&UPDATER, ServiceStatus::Enabled);
+        backend.fail_next(
+            &HELPER,
+            Operation::Register,
+            ServiceManagementError::InvalidSignature,
+        );
+        let reconciler = Reconciler::with_backend(backend.clone());
+
+        let plan = reconciler.plan(&desired);
+        assert_eq!(
+            plan.to_string(),
+            "  main app (Enabled)\n\
+             + agent com.example.sync.plist (Not Registered, will register)\n\
+             - daemon com.example.updater.plist (Enabled, will unregister)\n\
+             + daemon com.example.helper.plist (Not Registered, will register)\n"
+        );
+
+        let report = reconciler.apply(&plan);
+        assert!(!report.is_success());
+        assert_eq!(
+            report.to_string(),
+            "main app: unchanged (Enabled)\n\
+             agent com.example.sync.plist: Not Registered -> Enabled\n\
+             daemon com.example.updater.plist: Enabled -> Not Registered\n\
+             daemon com.example.helper.plist: failed (the app's code signature doesn't meet the requirements to perform the operation)\n"
+        );
+        assert_eq!(backend.inner().status(&AGENT), ServiceStatus::Enabled);
+
+        let report = reconciler.reconcile(&desired);
+        assert!(report.is_success());
+        assert_eq!(
+            report.requiring_approval().collect::<Vec<_>>(),
+            [&OwnedServiceType::from(HELPER)]
+        );
+        assert!(reconciler.plan(&desired).is_empty());
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn test_toml() {
+        let desired = DesiredState::from_json(STATE).unwrap();
+        let toml = desired.to_toml().unwrap();
+        assert!(toml.contains("[[service]]\nkind = \"main_app\"\nregistered = true\n"));
+        assert_eq!(DesiredState::from_toml(&toml).unwrap(), desired);
+    }
+}
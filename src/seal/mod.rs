@@ -0,0 +1,735 @@
+//! Verification of the resource seal recorded in `_CodeSignature/CodeResources`.
+//!
+//! Signing a bundle hashes every resource and nested piece of code in it into
+//! `Contents/_CodeSignature/CodeResources`, and hashes that file into the main executable's code
+//! directory. Any later change to the bundle, such as adding a property list to
+//! `Contents/Library/LaunchAgents` after signing, breaks the seal, and registration then fails
+//! with [`ServiceManagementError::InvalidSignature`](crate::ServiceManagementError::InvalidSignature).
+
+mod pattern;
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use thiserror::Error;
+
+use crate::bundle::bundle_executable;
+use crate::digest::{sha1, sha256};
+use crate::macho::{CodeDirectory, MachOFile};
+use crate::plist::{Dictionary, PlistError, Value};
+
+use pattern::Pattern;
+
+/// Errors that can occur while reading or verifying a resource seal.
+#[derive(Debug, Error)]
+pub enum SealError {
+    /// The bundle has no `_CodeSignature/CodeResources`, so it isn't signed or is signed as a
+    /// single file.
+    #[error("`{0}` has no _CodeSignature/CodeResources")]
+    NotSealed(PathBuf),
+
+    /// A file or directory in the bundle couldn't be read.
+    #[error("failed to read `{path}`: {source}")]
+    Io {
+        /// The path that couldn't be read.
+        path: PathBuf,
+        /// The underlying error.
+        source: std::io::Error,
+    },
+
+    /// `CodeResources` isn't a valid property list.
+    #[error("failed to parse `{path}`: {source}")]
+    Plist {
+        /// The `CodeResources` file.
+        path: PathBuf,
+        /// The underlying error.
+        source: PlistError,
+    },
+
+    /// `CodeResources` is a property list but not a valid seal.
+    #[error("`{path}` is not a valid resource seal: {reason}")]
+    Malformed {
+        /// The `CodeResources` file.
+        path: PathBuf,
+        /// What was wrong.
+        reason: String,
+    },
+}
+
+/// How a single path is sealed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SealedResource {
+    /// A regular file, sealed by the hash of its contents.
+    File {
+        /// The SHA-1 hash, recorded by every seal version.
+        sha1: Option<Vec<u8>>,
+        /// The SHA-256 hash, recorded by version 2 seals.
+        sha256: Option<Vec<u8>>,
+        /// Whether the file may be missing.
+        optional: bool,
+    },
+
+    /// A symbolic link, sealed by its target.
+    Symlink {
+        /// The link's target.
+        target: String,
+        /// Whether the link may be missing.
+        optional: bool,
+    },
+
+    /// Nested code such as a helper app or framework, sealed by its CDHash.
+    Nested {
+        /// The CDHash of the nested code's signature.
+        cdhash: Vec<u8>,
+        /// The designated requirement the nested code was signed with.
+        requirement: Option<String>,
+        /// Whether the nested code may be missing.
+        optional: bool,
+    },
+}
+
+impl SealedResource {
+    /// Returns whether the resource may be missing without breaking the seal.
+    pub fn is_optional(&self) -> bool {
+        match self {
+            SealedResource::File { optional, .. }
+            | SealedResource::Symlink { optional, .. }
+            | SealedResource::Nested { optional, .. } => *optional,
+        }
+    }
+}
+
+/// A rule deciding whether paths that match it are sealed.
+#[derive(Debug, Clone)]
+struct Rule {
+    pattern: Pattern,
+    omit: bool,
+    nested: bool,
+    weight: f64,
+}
+
+/// The contents of a bundle's `_CodeSignature/CodeResources`.
+#[derive(Debug, Clone)]
+pub struct CodeResources {
+    /// The sealed paths, relative to the bundle's `Contents` directory. For version 2 seals these
+    /// come from `files2`, otherwise from `files`.
+    pub files: BTreeMap<String, SealedResource>,
+
+    rules: Vec<Rule>,
+}
+
+impl CodeResources {
+    /// Reads the seal of the bundle at `bundle`.
+    pub fn for_bundle(bundle: impl AsRef<Path>) -> Result<Self, SealError> {
+        let path = code_resources_path(bundle.as_ref());
+        if !path.is_file() {
+            return Err(SealError::NotSealed(bundle.as_ref().to_path_buf()));
+        }
+        Self::from_file(path)
+    }
+
+    /// Reads a `CodeResources` file.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, SealError> {
+        let path = path.as_ref();
+        let value = Value::from_file(path).map_err(|source| SealError::Plist {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        Self::from_value(value).map_err(|reason| SealError::Malformed {
+            path: path.to_path_buf(),
+            reason,
+        })
+    }
+
+    fn from_value(value: Value) -> Result<Self, String> {
+        let Value::Dictionary(mut root) = value else {
+            return Err("the root is not a dictionary".to_string());
+        };
+        let (files, rules) = if root.contains_key("files2") {
+            (root.remove("files2"), root.remove("rules2"))
+        } else {
+            (root.remove("files"), root.remove("rules"))
+        };
+
+        let mut sealed = BTreeMap::new();
+        for (path, value) in dictionary(files, "files")? {
+            let resource = parse_resource(value).map_err(|reason| format!("`{path}`: {reason}"))?;
+            sealed.insert(path, resource);
+        }
+
+        let mut parsed = Vec::new();
+        for (source, value) in dictionary(rules, "rules")? {
+            let pattern =
+                Pattern::new(&source).map_err(|reason| format!("rule `{source}`: {reason}"))?;
+            let rule = match value {
+                Value::Boolean(true) => Rule {
+                    pattern,
+                    omit: false,
+                    nested: false,
+                    weight: 1.0,
+                },
+                Value::Boolean(false) => continue,
+                Value::Dictionary(options) => {
+                    let flag = |key| options.get(key).and_then(Value::as_boolean) == Some(true);
+                    Rule {
+                        omit: flag("omit"),
+                        nested: flag("nested"),
+                        weight: match options.get("weight") {
+                            Some(Value::Integer(weight)) => *weight as f64,
+                            Some(Value::Real(weight)) => *weight,
+                            _ => 1.0,
+                        },
+                        pattern,
+                    }
+                }
+                other => return Err(format!("rule `{source}` is {}", other.type_name())),
+            };
+            parsed.push(rule);
+        }
+
+        Ok(Self {
+            files: sealed,
+            rules: parsed,
+        })
+    }
+
+    /// Compares the bundle at `bundle` against the seal.
+    pub fn verify(&self, bundle: impl AsRef<Path>) -> Result<SealReport, SealError> {
+        let bundle = bundle.as_ref();
+        let contents = bundle.join("Contents");
+        let mut report = SealReport::default();
+
+        for (relative, resource) in &self.files {
+            let path = contents.join(relative);
+            let reported = Path::new("Contents").join(relative);
+            match check_resource(&path, resource)? {
+                Check::Intact => {}
+                Check::Missing if resource.is_optional() => {}
+                Check::Missing => report.missing.push(reported),
+                Check::Modified => report.modified.push(reported),
+                Check::Unverifiable => report.unverifiable.push(reported),
+            }
+        }
+
+        // The main executable and `Info.plist` are sealed by the code directory itself.
+        let mut excluded = vec!["_CodeSignature".to_string(), "Info.plist".to_string()];
+        if let Ok(executable) = bundle_executable(bundle).strip_prefix(&contents) {
+            excluded.push(executable.to_string_lossy().replace('\\', "/"));
+        }
+        self.find_added(&contents, "", &excluded, &mut report.added)?;
+
+        if let Some(code_directory) = main_code_directory(bundle) {
+            for (slot, path) in [
+                (CodeDirectory::INFO_SLOT, "Contents/Info.plist"),
+                (
+                    CodeDirectory::RESOURCES_SLOT,
+                    "Contents/_CodeSignature/CodeResources",
+                ),
+            ] {
+                match check_slot(&code_directory, slot, &bundle.join(path)) {
+                    Check::Intact | Check::Missing => {}
+                    Check::Modified => report.modified.push(PathBuf::from(path)),
+                    Check::Unverifiable => report.unverifiable.push(PathBuf::from(path)),
+                }
+            }
+        }
+
+        report.added.sort();
+        report.modified.sort();
+        report.missing.sort();
+        report.unverifiable.sort();
+        Ok(report)
+    }
+
+    /// Returns the highest-weighted rule matching `relative`, preferring the first on ties.
+    fn rule_for(&self, relative: &str) -> Option<&Rule> {
+        self.rules
+            .iter()
+            .rev()
+            .filter(|rule| rule.pattern.is_match(relative))
+            .max_by(|a, b| a.weight.total_cmp(&b.weight))
+    }
+
+    fn find_added(
+        &self,
+        contents: &Path,
+        relative: &str,
+        excluded: &[String],
+        added: &mut Vec<PathBuf>,
+    ) -> Result<(), SealError> {
+        let directory = contents.join(relative);
+        let io_error = |source| SealError::Io {
+            path: directory.clone(),
+            source,
+        };
+        let mut entries = fs::read_dir(&directory)
+            .map_err(io_error)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(io_error)?;
+        entries.sort_by_key(|entry| entry.file_name());
+
+        for entry in entries {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let path = if relative.is_empty() {
+                name.clone()
+            } else {
+                format!("{relative}/{name}")
+            };
+            if excluded.contains(&path) || self.files.contains_key(&path) {
+                continue;
+            }
+            let rule = self.rule_for(&path);
+            let is_directory = entry.file_type().map_err(io_error)?.is_dir();
+            if is_directory {
+                // A bundle in a nested code location is sealed as a unit, not file by file.
+                if rule.is_some_and(|rule| rule.nested && !rule.omit) && name.contains('.') {
+                    added.push(Path::new("Contents").join(&path));
+                } else {
+                    self.find_added(contents, &path, excluded, added)?;
+                }
+            } else if rule.is_some_and(|rule| !rule.omit) {
+                added.push(Path::new("Contents").join(&path));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The differences between a bundle and its resource seal.
+///
+/// Paths are relative to the bundle, for example `Contents/Library/LaunchAgents/agent.plist`.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use smappservice_rs::SealReport;
+///
+/// let report = SealReport::for_bundle("/Applications/MyApp.app").unwrap();
+/// for path in &report.added {
+///     eprintln!("added after signing: {}", path.display());
+/// }
+/// assert!(report.is_intact());
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SealReport {
+    /// Files that would be sealed but aren't in the seal.
+    pub added: Vec<PathBuf>,
+
+    /// Sealed files whose contents, link target or CDHash changed. Also lists `Info.plist` and
+    /// `CodeResources` itself when they no longer match the main executable's code directory.
+    pub modified: Vec<PathBuf>,
+
+    /// Sealed files that no longer exist and aren't optional.
+    pub missing: Vec<PathBuf>,
+
+    /// `Info.plist` and `CodeResources` when the main executable's code directory uses a hash
+    /// type this crate can't compute, so they couldn't be checked.
+    pub unverifiable: Vec<PathBuf>,
+}
+
+impl SealReport {
+    /// Reads the seal of the bundle at `bundle` and verifies the bundle against it.
+    pub fn for_bundle(bundle: impl AsRef<Path>) -> Result<Self, SealError> {
+        CodeResources::for_bundle(bundle.as_ref())?.verify(bundle)
+    }
+
+    /// Returns whether the bundle matches its seal. A bundle with unverifiable paths isn't known
+    /// to match, so it isn't intact.
+    pub fn is_intact(&self) -> bool {
+        self.added.is_empty()
+            && self.modified.is_empty()
+            && self.missing.is_empty()
+            && self.unverifiable.is_empty()
+    }
+}
+
+fn code_resources_path(bundle: &Path) -> PathBuf {
+    bundle.join("Contents/_CodeSignature/CodeResources")
+}
+
+fn dictionary(value: Option<Value>, name: &str) -> Result<Dictionary, String> {
+    match value {
+        None => Ok(Dictionary::new()),
+        Some(Value::Dictionary(dictionary)) => Ok(dictionary),
+        Some(other) => Err(format!("`{name}` is {}", other.type_name())),
+    }
+}
+
+fn parse_resource(value: Value) -> Result<SealedResource, String> {
+    let options = match value {
+        Value::Data(hash) => {
+            return Ok(SealedResource::File {
+                sha1: Some(hash),
+                sha256: None,
+                optional: false,
+            });
+        }
+        Value::Dictionary(options) => options,
+        other => {
+            return Err(format!(
+                "expected data or a dictionary, found {}",
+                other.type_name()
+            ));
+        }
+    };
+    let optional = options.get("optional").and_then(Value::as_boolean) == Some(true);
+    let data = |key| {
+        options
+            .get(key)
+            .and_then(Value::as_data)
+            .map(<[u8]>::to_vec)
+    };
+    if let Some(target) = options.get("symlink").and_then(Value::as_string) {
+        return Ok(SealedResource::Symlink {
+            target: target.to_string(),
+            optional,
+        });
+    }
+    if let Some(cdhash) = data("cdhash") {
+        return Ok(SealedResource::Nested {
+            cdhash,
+            requirement: options
+                .get("requirement")
+                .and_then(Value::as_string)
+                .map(str::to_string),
+            optional,
+        });
+    }
+    let (sha1, sha256) = (data("hash"), data("hash2"));
+    if sha1.is_none() && sha256.is_none() {
+        return Err("no hash, symlink or cdhash".to_string());
+    }
+    Ok(SealedResource::File {
+        sha1,
+        sha256,
+        optional,
+    })
+}
+
+enum Check {
+    Intact,
+    Modified,
+    Missing,
+    Unverifiable,
+}
+
+fn check_resource(path: &Path, resource: &SealedResource) -> Result<Check, SealError> {
+    let metadata = match fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(error) if error.kind() == ErrorKind::NotFound => return Ok(Check::Missing),
+        Err(source) => {
+            return Err(SealError::Io {
+                path: path.to_path_buf(),
+                source,
+            });
+        }
+    };
+    let intact = match resource {
+        SealedResource::File {
+            sha1: expected_sha1,
+            sha256: expected_sha256,
+            ..
+        } => {
+            if !metadata.is_file() {
+                false
+            } else {
+                let data = fs::read(path).map_err(|source| SealError::Io {
+                    path: path.to_path_buf(),
+                    source,
+                })?;
+                match (expected_sha256, expected_sha1) {
+                    (Some(expected), _) => sha256(&data).as_slice() == expected.as_slice(),
+                    (None, Some(expected)) => sha1(&data).as_slice() == expected.as_slice(),
+                    (None, None) => true,
+                }
+            }
+        }
+        SealedResource::Symlink { target, .. } => {
+            fs::read_link(path).is_ok_and(|found| found.to_string_lossy() == target.as_str())
+        }
+        SealedResource::Nested { cdhash, .. } => {
+            let file = if metadata.is_dir() {
+                MachOFile::for_bundle(path)
+            } else {
+                MachOFile::from_file(path)
+            };
+            file.is_ok_and(|file| {
+                file.slices().iter().any(|slice| {
+                    slice
+                        .code_signature()
+                        .ok()
+                        .flatten()
+                        .is_some_and(|signature| {
+                            signature
+                                .code_directories
+                                .iter()
+                                .any(|cd| cd.cdhash.as_deref() == Some(cdhash.as_slice()))
+                        })
+                })
+            })
+        }
+    };
+    Ok(if intact {
+        Check::Intact
+    } else {
+        Check::Modified
+    })
+}
+
+fn main_code_directory(bundle: &Path) -> Option<CodeDirectory> {
+    let file = MachOFile::for_bundle(bundle).ok()?;
+    let signature = file.slices().first()?.code_signature().ok()??;
+    Some(signature.code_directory().clone())
+}
+
+/// Checks the file at `path` against the hash in special slot `slot`. Slots that are absent or
+/// empty don't seal anything, so they are intact.
+fn check_slot(code_directory: &CodeDirectory, slot: usize, path: &Path) -> Check {
+    let Some(expected) = code_directory
+        .special_slot(slot)
+        .filter(|hash| hash.iter().any(|&b| b != 0))
+    else {
+        return Check::Intact;
+    };
+    let Ok(data) = fs::read(path) else {
+        return Check::Modified;
+    };
+    match code_directory.hash_type.digest(&data) {
+        Some(digest) if digest.get(..expected.len()) == Some(expected) => Check::Intact,
+        Some(_) => Check::Modified,
+        None => Check::Unverifiable,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bundle::fixture::FixtureBundle;
+    use crate::digest::sha384;
+    use crate::macho::fixture::{FixtureMachO, FixtureSignature, CPU_TYPE_ARM64};
+    use crate::InfoPlist;
+
+    fn signed(identifier: &str, special_slots: Vec<Vec<u8>>) -> Vec<u8> {
+        signed_with(identifier, 2, special_slots)
+    }
+
+    fn signed_with(identifier: &str, hash_type: u8, special_slots: Vec<Vec<u8>>) -> Vec<u8> {
+        FixtureMachO {
+            signature: Some(FixtureSignature {
+                hash_type,
+                special_slots,
+                ..FixtureSignature::new(identifier)
+            }),
+            ..FixtureMachO::new(CPU_TYPE_ARM64)
+        }
+        .build()
+    }
+
+    fn file_seal(contents: &[u8]) -> Value {
+        Value::Dictionary(Dictionary::from([
+            ("hash".to_string(), Value::Data(sha1(contents).to_vec())),
+            ("hash2".to_string(), Value::Data(sha256(contents).to_vec())),
+        ]))
+    }
+
+    fn rules() -> Value {
+        let option = |key: &str, value: Value| {
+            Value::Dictionary(Dictionary::from([(key.to_string(), value)]))
+        };
+        Value::Dictionary(Dictionary::from([
+            ("^.*".to_string(), Value::Boolean(true)),
+            (
+                "^(Frameworks|MacOS|Library/(Automator|Spotlight|LoginItems))/".to_string(),
+                Value::Dictionary(Dictionary::from([
+                    ("nested".to_string(), Value::Boolean(true)),
+                    ("weight".to_string(), Value::Real(10.0)),
+                ])),
+            ),
+            (
+                "^(.*/)?\\.DS_Store$".to_string(),
+                option("omit", Value::Boolean(true)),
+            ),
+            (
+                "^Info\\.plist$".to_string(),
+                option("omit", Value::Boolean(true)),
+            ),
+        ]))
+    }
+
+    /// A signed bundle with a resource, a login item helper and a seal covering both.
+    fn sealed_bundle() -> FixtureBundle {
+        sealed_bundle_with(2)
+    }
+
+    /// Like [`sealed_bundle`], with the main executable's code directory using `hash_type`.
+    fn sealed_bundle_with(hash_type: u8) -> FixtureBundle {
+        let bundle = FixtureBundle::new("Main");
+        InfoPlist {
+            bundle_identifier: Some("com.example.main".to_string()),
+            executable: Some("Main".to_string()),
+            ..InfoPlist::default()
+        }
+        .write_to_file(bundle.contents().join("Info.plist"))
+        .unwrap();
+        bundle.write_file("Contents/Resources/icon.icns", b"icon");
+        let helper = bundle.add_login_item(
+            "Helper",
+            &InfoPlist {
+                bundle_identifier: Some("com.example.helper".to_string()),
+                ..InfoPlist::default()
+            },
+        );
+        let helper_executable = signed("com.example.helper", Vec::new());
+        std::fs::write(helper.join("Contents/MacOS/Helper"), &helper_executable).unwrap();
+        let helper_cdhash = MachOFile::parse(&helper_executable).unwrap().slices()[0]
+            .code_signature()
+            .unwrap()
+            .unwrap()
+            .cdhash()
+            .unwrap()
+            .to_vec();
+
+        let files = Dictionary::from([
+            ("Resources/icon.icns".to_string(), file_seal(b"icon")),
+            (
+                "Resources/optional.txt".to_string(),
+                Value::Dictionary(Dictionary::from([
+                    ("hash2".to_string(), Value::Data(sha256(b"x").to_vec())),
+                    ("optional".to_string(), Value::Boolean(true)),
+                ])),
+            ),
+            (
+                "Library/LoginItems/Helper.app".to_string(),
+                Value::Dictionary(Dictionary::from([
+                    ("cdhash".to_string(), Value::Data(helper_cdhash)),
+                    (
+                        "requirement".to_string(),
+                        Value::String("identifier \"com.example.helper\"".to_string()),
+                    ),
+                ])),
+            ),
+        ]);
+        let seal = Value::Dictionary(Dictionary::from([
+            ("files".to_string(), Value::Dictionary(Dictionary::new())),
+            ("files2".to_string(), Value::Dictionary(files)),
+            ("rules2".to_string(), rules()),
+        ]));
+        let seal_path = bundle.write_file("Contents/_CodeSignature/CodeResources", b"");
+        seal.write_to_file(&seal_path).unwrap();
+
+        let info = std::fs::read(bundle.contents().join("Info.plist")).unwrap();
+        let seal = std::fs::read(&seal_path).unwrap();
+        let hash = |data: &[u8]| match hash_type {
+            4 => sha384(data).to_vec(),
+            _ => sha256(data).to_vec(),
+        };
+        bundle.write_file(
+            "Contents/MacOS/Main",
+            &signed_with(
+                "com.example.main",
+                hash_type,
+                vec![hash(&info), vec![0; hash(b"").len()], hash(&seal)],
+            ),
+        );
+        bundle
+    }
+
+    #[test]
+    fn test_intact_bundle() {
+        let bundle = sealed_bundle();
+        bundle.write_file("Contents/Resources/.DS_Store", b"finder");
+        let resources = CodeResources::for_bundle(&bundle.path).unwrap();
+        assert_eq!(resources.files.len(), 3);
+        assert!(resources.files["Resources/optional.txt"].is_optional());
+        let report = resources.verify(&bundle.path).unwrap();
+        assert_eq!(report, SealReport::default());
+        assert!(report.is_intact());
+    }
+
+    #[test]
+    fn test_changes_after_signing() {
+        let bundle = sealed_bundle();
+        bundle.write_file("Contents/Resources/icon.icns", b"new icon");
+        bundle.write_file(
+            "Contents/Library/LaunchAgents/com.example.agent.plist",
+            b"<plist/>",
+        );
+        bundle.write_file(
+            "Contents/Library/LoginItems/Helper.app/Contents/MacOS/Helper",
+            &signed("com.example.other", Vec::new()),
+        );
+        bundle.write_file(
+            "Contents/Library/LoginItems/Other.app/Contents/Info.plist",
+            b"",
+        );
+        bundle.write_file("Contents/Info.plist", b"<plist><dict/></plist>");
+
+        let report = SealReport::for_bundle(&bundle.path).unwrap();
+        assert_eq!(
+            report.added,
+            vec![
+                PathBuf::from("Contents/Library/LaunchAgents/com.example.agent.plist"),
+                PathBuf::from("Contents/Library/LoginItems/Other.app"),
+            ]
+        );
+        assert_eq!(
+            report.modified,
+            vec![
+                PathBuf::from("Contents/Info.plist"),
+                PathBuf::from("Contents/Library/LoginItems/Helper.app"),
+                PathBuf::from("Contents/Resources/icon.icns"),
+            ]
+        );
+        assert!(report.missing.is_empty());
+
+        std::fs::remove_file(bundle.contents().join("Resources/icon.icns")).unwrap();
+        let report = SealReport::for_bundle(&bundle.path).unwrap();
+        assert_eq!(
+            report.missing,
+            vec![PathBuf::from("Contents/Resources/icon.icns")]
+        );
+    }
+
+    #[test]
+    fn test_code_directory_hash_types() {
+        let bundle = sealed_bundle_with(4);
+        assert!(SealReport::for_bundle(&bundle.path).unwrap().is_intact());
+        bundle.write_file("Contents/Info.plist", b"<plist><dict/></plist>");
+        assert_eq!(
+            SealReport::for_bundle(&bundle.path).unwrap().modified,
+            vec![PathBuf::from("Contents/Info.plist")]
+        );
+
+        // A hash type that can't be computed can't vouch for anything.
+        let bundle = sealed_bundle_with(9);
+        let report = SealReport::for_bundle(&bundle.path).unwrap();
+        assert!(report.modified.is_empty());
+        assert_eq!(
+            report.unverifiable,
+            vec![
+                PathBuf::from("Contents/Info.plist"),
+                PathBuf::from("Contents/_CodeSignature/CodeResources"),
+            ]
+        );
+        assert!(!report.is_intact());
+    }
+
+    #[test]
+    fn test_unsealed_and_malformed() {
+        let bundle = FixtureBundle::new("Main");
+        assert!(matches!(
+            SealReport::for_bundle(&bundle.path),
+            Err(SealError::NotSealed(_))
+        ));
+        bundle.write_file(
+            "Contents/_CodeSignature/CodeResources",
+            b"<plist><dict><key>files2</key><dict><key>a</key><string>x</string></dict></dict></plist>",
+        );
+        assert!(matches!(
+            SealReport::for_bundle(&bundle.path),
+            Err(SealError::Malformed { .. })
+        ));
+    }
+}
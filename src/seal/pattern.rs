@@ -0,0 +1,249 @@
+//! The subset of POSIX extended regular expressions used by `CodeResources` rules.
+//!
+//! Supported: literals, `.`, `\` escapes, bracket expressions with ranges and negation, groups
+//! with alternation, the `*`, `+` and `?` quantifiers and the `^` and `$` anchors. Matching
+//! backtracks, which is fine for the short patterns and paths it's used on.
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Literal(char),
+    Any,
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+    Group(Vec<Vec<Node>>),
+    Repeat {
+        node: Box<Node>,
+        min: usize,
+        max: Option<usize>,
+    },
+    Start,
+    End,
+}
+
+impl Node {
+    fn matches_char(&self, c: char) -> bool {
+        match self {
+            Node::Literal(literal) => *literal == c,
+            Node::Any => true,
+            Node::Class { negated, ranges } => {
+                ranges.iter().any(|&(low, high)| (low..=high).contains(&c)) != *negated
+            }
+            Node::Group(_) | Node::Repeat { .. } | Node::Start | Node::End => false,
+        }
+    }
+}
+
+/// A compiled pattern.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Pattern {
+    alternatives: Vec<Vec<Node>>,
+}
+
+impl Pattern {
+    /// Compiles `source`, returning a description of the problem if it isn't supported.
+    pub(crate) fn new(source: &str) -> Result<Self, String> {
+        let chars: Vec<char> = source.chars().collect();
+        let mut position = 0;
+        let alternatives = parse_alternatives(&chars, &mut position)?;
+        if position < chars.len() {
+            return Err(format!("unbalanced `)` at {position}"));
+        }
+        Ok(Self { alternatives })
+    }
+
+    /// Returns whether the pattern matches anywhere in `text`.
+    pub(crate) fn is_match(&self, text: &str) -> bool {
+        let text: Vec<char> = text.chars().collect();
+        let group = [Node::Group(self.alternatives.clone())];
+        (0..=text.len()).any(|start| match_nodes(&group, &text, start, &mut |_| true))
+    }
+}
+
+fn parse_alternatives(chars: &[char], position: &mut usize) -> Result<Vec<Vec<Node>>, String> {
+    let mut alternatives = vec![Vec::new()];
+    while let Some(&c) = chars.get(*position) {
+        let node = match c {
+            ')' => break,
+            '|' => {
+                *position += 1;
+                alternatives.push(Vec::new());
+                continue;
+            }
+            '*' | '+' | '?' => {
+                let node = alternatives
+                    .last_mut()
+                    .and_then(Vec::pop)
+                    .filter(|node| !matches!(node, Node::Start | Node::End))
+                    .ok_or_else(|| format!("`{c}` at {} has nothing to repeat", *position))?;
+                *position += 1;
+                let (min, max) = match c {
+                    '*' => (0, None),
+                    '+' => (1, None),
+                    _ => (0, Some(1)),
+                };
+                Node::Repeat {
+                    node: Box::new(node),
+                    min,
+                    max,
+                }
+            }
+            '(' => {
+                *position += 1;
+                let group = parse_alternatives(chars, position)?;
+                if chars.get(*position) != Some(&')') {
+                    return Err("unterminated group".to_string());
+                }
+                *position += 1;
+                Node::Group(group)
+            }
+            '[' => parse_class(chars, position)?,
+            '\\' => {
+                let escaped = *chars
+                    .get(*position + 1)
+                    .ok_or_else(|| "trailing `\\`".to_string())?;
+                *position += 2;
+                match escaped {
+                    'd' => Node::Class {
+                        negated: false,
+                        ranges: vec![('0', '9')],
+                    },
+                    other => Node::Literal(other),
+                }
+            }
+            _ => {
+                *position += 1;
+                match c {
+                    '^' => Node::Start,
+                    '$' => Node::End,
+                    '.' => Node::Any,
+                    other => Node::Literal(other),
+                }
+            }
+        };
+        alternatives.last_mut().unwrap().push(node);
+    }
+    Ok(alternatives)
+}
+
+fn parse_class(chars: &[char], position: &mut usize) -> Result<Node, String> {
+    let start = *position;
+    *position += 1;
+    let negated = chars.get(*position) == Some(&'^');
+    if negated {
+        *position += 1;
+    }
+    let mut ranges = Vec::new();
+    let mut first = true;
+    loop {
+        let c = *chars
+            .get(*position)
+            .ok_or_else(|| format!("unterminated bracket expression at {start}"))?;
+        // A `]` right after the opening bracket is a literal, as in POSIX.
+        if c == ']' && !first {
+            *position += 1;
+            break;
+        }
+        first = false;
+        if chars.get(*position + 1) == Some(&'-')
+            && let Some(&high) = chars.get(*position + 2).filter(|&&high| high != ']')
+        {
+            ranges.push((c, high));
+            *position += 3;
+        } else {
+            ranges.push((c, c));
+            *position += 1;
+        }
+    }
+    Ok(Node::Class { negated, ranges })
+}
+
+/// Matches `nodes` at `position` and calls `next` with each position a match ends at, until it
+/// returns true.
+fn match_nodes(
+    nodes: &[Node],
+    text: &[char],
+    position: usize,
+    next: &mut dyn FnMut(usize) -> bool,
+) -> bool {
+    let Some((node, rest)) = nodes.split_first() else {
+        return next(position);
+    };
+    match node {
+        Node::Start => position == 0 && match_nodes(rest, text, position, next),
+        Node::End => position == text.len() && match_nodes(rest, text, position, next),
+        Node::Group(alternatives) => alternatives.iter().any(|alternative| {
+            match_nodes(alternative, text, position, &mut |end| {
+                match_nodes(rest, text, end, next)
+            })
+        }),
+        Node::Repeat { node, min, max } => {
+            repeat(node, (*min, *max), 0, rest, text, position, next)
+        }
+        single => {
+            text.get(position).is_some_and(|&c| single.matches_char(c))
+                && match_nodes(rest, text, position + 1, next)
+        }
+    }
+}
+
+/// Greedily matches `node` as many times as `bounds` allow, then the rest of the sequence.
+fn repeat(
+    node: &Node,
+    bounds: (usize, Option<usize>),
+    count: usize,
+    rest: &[Node],
+    text: &[char],
+    position: usize,
+    next: &mut dyn FnMut(usize) -> bool,
+) -> bool {
+    let (min, max) = bounds;
+    if max.is_none_or(|max| count < max)
+        && match_nodes(std::slice::from_ref(node), text, position, &mut |end| {
+            // An iteration that consumes nothing can't make progress.
+            end != position && repeat(node, bounds, count + 1, rest, text, end, next)
+        })
+    {
+        return true;
+    }
+    count >= min && match_nodes(rest, text, position, next)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_rules() {
+        let matches = |pattern: &str, text: &str| Pattern::new(pattern).unwrap().is_match(text);
+        assert!(matches("^.*", "Resources/a.png"));
+        assert!(matches("^[^/]+$", "embedded.provisionprofile"));
+        assert!(!matches("^[^/]+$", "Resources/a.png"));
+        let nested = "^(Frameworks|SharedFrameworks|PlugIns|Plug-ins|XPCServices|Helpers|MacOS|Library/(Automator|Spotlight|LoginItems))/";
+        assert!(matches(nested, "Library/LoginItems/Helper.app"));
+        assert!(matches(nested, "MacOS/helper"));
+        assert!(!matches(nested, "Library/LaunchAgents/agent.plist"));
+        assert!(matches("^(.*/)?\\.DS_Store$", "Resources/.DS_Store"));
+        assert!(matches("^(.*/)?\\.DS_Store$", ".DS_Store"));
+        assert!(!matches("^(.*/)?\\.DS_Store$", "Resources/x.DS_Store"));
+        assert!(matches(".*\\.dSYM($|/)", "MacOS/app.dSYM/Contents"));
+        assert!(matches(
+            "^Resources/.*\\.lproj/locversion.plist$",
+            "Resources/en.lproj/locversion.plist"
+        ));
+        assert!(matches("^Info\\.plist$", "Info.plist"));
+        assert!(!matches("^Info\\.plist$", "Info_plist"));
+        assert!(matches("a+b?c*$", "xaa"));
+        assert!(matches("[]a-c]x", "]x"));
+    }
+
+    #[test]
+    fn test_invalid_patterns() {
+        assert!(Pattern::new("(a").is_err());
+        assert!(Pattern::new("a)").is_err());
+        assert!(Pattern::new("[a").is_err());
+        assert!(Pattern::new("*a").is_err());
+        assert!(Pattern::new("a\\").is_err());
+    }
+}
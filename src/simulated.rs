@@ -0,0 +1,262 @@
+//! An in-memory model of the ServiceManagement framework.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::{OwnedServiceType, ServiceBackend, ServiceManagementError, ServiceStatus, ServiceType};
+
+/// A [`ServiceBackend`] that simulates the documented `SMAppService` state transitions in memory.
+///
+/// - Services start as [`ServiceStatus::NotRegistered`].
+/// - Registering a `MainApp`, `Agent` or `LoginItem` makes it [`ServiceStatus::Enabled`].
+/// - Registering a `Daemon` leaves it in [`ServiceStatus::RequiresApproval`] until
+///   [`approve`](#method.approve) is called.
+/// - Registering a service that is already registered fails with
+///   [`ServiceManagementError::AlreadyRegistered`].
+/// - Unregistering a service that is not registered fails with
+///   [`ServiceManagementError::JobNotFound`].
+/// - Registering a service marked as [`ServiceStatus::NotFound`] fails with
+///   [`ServiceManagementError::JobPlistNotFound`].
+///
+/// Clones share the same state, so a test can keep one handle to drive approvals while
+/// another is owned by an `AppService`.
+///
+/// # Examples
+///
+/// ```rust
+/// use smappservice_rs::{AppService, ServiceStatus, ServiceType, SimulatedBackend};
+///
+/// let backend = SimulatedBackend::new();
+/// let daemon_type = ServiceType::Daemon {
+///     plist_name: "com.example.myapp.daemon.plist",
+/// };
+/// let daemon = AppService::with_backend(daemon_type, backend.clone());
+///
+/// daemon.register().unwrap();
+/// assert_eq!(daemon.status(), ServiceStatus::RequiresApproval);
+///
+/// backend.approve(&daemon_type);
+/// assert_eq!(daemon.status(), ServiceStatus::Enabled);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct SimulatedBackend {
+    state: Arc<Mutex<SimulatedState>>,
+}
+
+#[derive(Debug, Default)]
+struct SimulatedState {
+    services: HashMap<OwnedServiceType, ServiceStatus>,
+    settings_opened: usize,
+}
+
+impl SimulatedBackend {
+    /// Creates a simulator in which no service is registered.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Approves a service that is waiting in [`ServiceStatus::RequiresApproval`], as if the user
+    /// enabled it in System Settings.
+    ///
+    /// Returns `false` if the service was not waiting for approval.
+    pub fn approve(&self, service_type: &ServiceType) -> bool {
+        self.transition(
+            service_type,
+            ServiceStatus::RequiresApproval,
+            ServiceStatus::Enabled,
+        )
+    }
+
+    /// Revokes consent for an enabled service, as if the user disabled it in System Settings.
+    ///
+    /// The service moves back to [`ServiceStatus::RequiresApproval`]. Returns `false` if the
+    /// service was not enabled.
+    pub fn revoke(&self, service_type: &ServiceType) -> bool {
+        self.transition(
+            service_type,
+            ServiceStatus::Enabled,
+            ServiceStatus::RequiresApproval,
+        )
+    }
+
+    /// Forces the status of a service, bypassing the usual transitions.
+    ///
+    /// Useful to seed the simulator with an existing registration or a missing service.
+    pub fn set_status(&self, service_type: &ServiceType, status: ServiceStatus) {
+        self.lock()
+            .services
+            .insert(OwnedServiceType::from(service_type), status);
+    }
+
+    /// Returns how many times System Settings was opened through this backend.
+    pub fn settings_opened(&self) -> usize {
+        self.lock().settings_opened
+    }
+
+    fn transition(
+        &self,
+        service_type: &ServiceType,
+        from: ServiceStatus,
+        to: ServiceStatus,
+    ) -> bool {
+        let mut state = self.lock();
+        match state
+            .services
+            .get_mut(&OwnedServiceType::from(service_type))
+        {
+            Some(status) if *status == from => {
+                *status = to;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, SimulatedState> {
+        // A panic while holding the lock cannot leave the map half-updated.
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl ServiceBackend for SimulatedBackend {
+    fn register(&self, service_type: &ServiceType) -> Result<(), ServiceManagementError> {
+        let mut state = self.lock();
+        let status = state
+            .services
+            .entry(OwnedServiceType::from(service_type))
+            .or_insert(ServiceStatus::NotRegistered);
+        match *status {
+            ServiceStatus::Enabled | ServiceStatus::RequiresApproval => {
+                Err(ServiceManagementError::AlreadyRegistered)
+            }
+            ServiceStatus::NotFound => Err(ServiceManagementError::JobPlistNotFound),
+            ServiceStatus::NotRegistered => {
+                *status = match service_type {
+                    ServiceType::Daemon { .. } => ServiceStatus::RequiresApproval,
+                    _ => ServiceStatus::Enabled,
+                };
+                Ok(())
+            }
+        }
+    }
+
+    fn unregister(&self, service_type: &ServiceType) -> Result<(), ServiceManagementError> {
+        let mut state = self.lock();
+        match state
+            .services
+            .get_mut(&OwnedServiceType::from(service_type))
+        {
+            Some(status)
+                if *status == ServiceStatus::Enabled
+                    || *status == ServiceStatus::RequiresApproval =>
+            {
+                *status = ServiceStatus::NotRegistered;
+                Ok(())
+            }
+            _ => Err(ServiceManagementError::JobNotFound),
+        }
+    }
+
+    fn status(&self, service_type: &ServiceType) -> ServiceStatus {
+        self.lock()
+            .services
+            .get(&OwnedServiceType::from(service_type))
+            .copied()
+            .unwrap_or(ServiceStatus::NotRegistered)
+    }
+
+    fn open_system_settings_login_items(&self) {
+        self.lock().settings_opened += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AppService;
+
+    const PLIST_NAME: &str = "com.example.smappservice-test-app.plist";
+
+    fn assert_register_cycle(service_type: ServiceType) {
+        let service = AppService::with_backend(service_type, SimulatedBackend::new());
+
+        assert_eq!(service.status(), ServiceStatus::NotRegistered);
+        assert_eq!(service.register(), Ok(()));
+        assert_eq!(service.status(), ServiceStatus::Enabled);
+        assert_eq!(service.unregister(), Ok(()));
+        assert_eq!(service.status(), ServiceStatus::NotRegistered);
+    }
+
+    #[test]
+    fn test_main_app() {
+        assert_register_cycle(ServiceType::MainApp);
+    }
+
+    #[test]
+    fn test_agent() {
+        assert_register_cycle(ServiceType::Agent {
+            plist_name: PLIST_NAME,
+        });
+    }
+
+    #[test]
+    fn test_login_item() {
+        assert_register_cycle(ServiceType::LoginItem {
+            identifier: "com.example.smappservice-test-app",
+        });
+    }
+
+    #[test]
+    fn test_daemon_requires_approval() {
+        let backend = SimulatedBackend::new();
+        let service_type = ServiceType::Daemon {
+            plist_name: PLIST_NAME,
+        };
+        let service = AppService::with_backend(service_type, backend.clone());
+
+        assert_eq!(service.register(), Ok(()));
+        assert_eq!(service.status(), ServiceStatus::RequiresApproval);
+
+        service.open_system_settings();
+        assert_eq!(backend.settings_opened(), 1);
+
+        assert!(backend.approve(&service_type));
+        assert!(!backend.approve(&service_type));
+        assert_eq!(service.status(), ServiceStatus::Enabled);
+
+        assert!(backend.revoke(&service_type));
+        assert_eq!(service.status(), ServiceStatus::RequiresApproval);
+
+        assert_eq!(service.unregister(), Ok(()));
+        assert_eq!(service.status(), ServiceStatus::NotRegistered);
+    }
+
+    #[test]
+    fn test_error_transitions() {
+        let backend = SimulatedBackend::new();
+        let service = AppService::with_backend(ServiceType::MainApp, backend.clone());
+
+        assert_eq!(
+            service.unregister(),
+            Err(ServiceManagementError::JobNotFound)
+        );
+        assert_eq!(service.register(), Ok(()));
+        assert_eq!(
+            service.register(),
+            Err(ServiceManagementError::AlreadyRegistered)
+        );
+
+        let missing = ServiceType::Agent {
+            plist_name: "com.example.missing.plist",
+        };
+        backend.set_status(&missing, ServiceStatus::NotFound);
+        let service = AppService::with_backend(missing, backend);
+        assert_eq!(
+            service.register(),
+            Err(ServiceManagementError::JobPlistNotFound)
+        );
+        assert_eq!(service.status(), ServiceStatus::NotFound);
+    }
+}
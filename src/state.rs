@@ -0,0 +1,155 @@
+//! JSON files that keep state across launches in the app's Application Support directory.
+
+use std::path::{Path, PathBuf};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::preflight;
+use crate::InfoPlist;
+
+/// Errors that can occur while locating, reading or writing a state file, such as a
+/// [`FingerprintStore`](crate::FingerprintStore) or a [`MigrationLog`](crate::MigrationLog).
+#[derive(Debug, Error)]
+pub enum StateError {
+    /// The state file couldn't be read or written.
+    #[error("failed to access `{path}`: {source}")]
+    Io {
+        /// The state file.
+        path: PathBuf,
+        /// The underlying error.
+        source: std::io::Error,
+    },
+
+    /// The state file isn't valid JSON or doesn't match the expected format.
+    #[error("invalid state file `{path}`: {source}")]
+    Json {
+        /// The state file.
+        path: PathBuf,
+        /// The underlying error.
+        source: serde_json::Error,
+    },
+
+    /// The home directory, which contains Application Support, isn't known.
+    #[error("the home directory is not set")]
+    NoHomeDirectory,
+
+    /// The running executable isn't inside an app bundle, or the bundle has no
+    /// `CFBundleIdentifier` to name its Application Support directory.
+    #[error("the running executable is not inside an identified app bundle")]
+    NoCurrentBundle,
+}
+
+/// Returns the path of the state file `name` in `~/Library/Application Support/<identifier>`.
+pub(crate) fn application_support(identifier: &str, name: &str) -> Result<PathBuf, StateError> {
+    let home = std::env::var_os("HOME").ok_or(StateError::NoHomeDirectory)?;
+    Ok(Path::new(&home)
+        .join("Library/Application Support")
+        .join(identifier)
+        .join(name))
+}
+
+/// Returns the running app's bundle and its `CFBundleIdentifier`.
+pub(crate) fn current_app() -> Result<(PathBuf, String), StateError> {
+    let bundle = preflight::current_bundle().ok_or(StateError::NoCurrentBundle)?;
+    let identifier = InfoPlist::for_bundle(&bundle)
+        .ok()
+        .and_then(|info| info.bundle_identifier)
+        .ok_or(StateError::NoCurrentBundle)?;
+    Ok((bundle, identifier))
+}
+
+/// Reads the state file at `path`. A missing file is the default state.
+pub(crate) fn read<T: DeserializeOwned + Default>(path: &Path) -> Result<T, StateError> {
+    match std::fs::read_to_string(path) {
+        Ok(json) => serde_json::from_str(&json).map_err(|source| StateError::Json {
+            path: path.to_path_buf(),
+            source,
+        }),
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(T::default()),
+        Err(source) => Err(StateError::Io {
+            path: path.to_path_buf(),
+            source,
+        }),
+    }
+}
+
+/// Writes `state` to the file at `path`, creating the directory if needed.
+///
+/// The file is replaced through a temporary file, so a crash while writing leaves the previous
+/// version.
+pub(crate) fn write<T: Serialize>(path: &Path, state: &T) -> Result<(), StateError> {
+    let json = serde_json::to_string_pretty(state).map_err(|source| StateError::Json {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    write_atomically(path, json.as_bytes()).map_err(|source| StateError::Io {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+fn write_atomically(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    if let Some(directory) = path.parent() {
+        std::fs::create_dir_all(directory)?;
+    }
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    std::fs::write(&temporary, contents)?;
+    std::fs::rename(&temporary, path)
+}
+
+/// Scratch directories for tests that keep state files.
+#[cfg(test)]
+pub(crate) mod fixture {
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// An empty directory under the system temporary directory, removed on drop.
+    pub(crate) struct TempDir {
+        pub(crate) path: PathBuf,
+    }
+
+    impl TempDir {
+        pub(crate) fn new() -> Self {
+            static COUNTER: AtomicUsize = AtomicUsize::new(0);
+            let path = std::env::temp_dir().join(format!(
+                "smappservice-rs-state-{}-{}",
+                std::process::id(),
+                COUNTER.fetch_add(1, Ordering::Relaxed)
+            ));
+            std::fs::create_dir_all(&path).unwrap();
+            Self { path }
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::fixture::TempDir;
+    use super::*;
+
+    #[test]
+    fn test_read_and_write() {
+        let directory = TempDir::new();
+        let path = directory.path.join("nested/state.json");
+        assert_eq!(read::<Vec<String>>(&path).unwrap(), Vec::<String>::new());
+
+        write(&path, &vec!["a".to_string()]).unwrap();
+        assert_eq!(read::<Vec<String>>(&path).unwrap(), ["a"]);
+        assert!(!directory.path.join("nested/state.json.tmp").exists());
+
+        std::fs::write(&path, "not json").unwrap();
+        assert!(matches!(
+            read::<Vec<String>>(&path),
+            Err(StateError::Json { .. })
+        ));
+    }
+}
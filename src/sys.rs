@@ -0,0 +1,28 @@
+//! Mirrors of the ServiceManagement constants for targets where `objc2-service-management`
+//! is unavailable.
+//!
+//! The values match the ones declared in `SMErrors.h` and `SMAppService.h`.
+
+#![allow(non_upper_case_globals)]
+
+pub const kSMErrorInternalFailure: u32 = 2;
+pub const kSMErrorInvalidSignature: u32 = 3;
+pub const kSMErrorAuthorizationFailure: u32 = 4;
+pub const kSMErrorToolNotValid: u32 = 5;
+pub const kSMErrorJobNotFound: u32 = 6;
+pub const kSMErrorServiceUnavailable: u32 = 7;
+pub const kSMErrorJobPlistNotFound: u32 = 8;
+pub const kSMErrorJobMustBeEnabled: u32 = 9;
+pub const kSMErrorInvalidPlist: u32 = 10;
+pub const kSMErrorLaunchDeniedByUser: u32 = 11;
+pub const kSMErrorAlreadyRegistered: u32 = 12;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SMAppServiceStatus(pub isize);
+
+impl SMAppServiceStatus {
+    pub const NotRegistered: Self = Self(0);
+    pub const Enabled: Self = Self(1);
+    pub const RequiresApproval: Self = Self(2);
+    pub const NotFound: Self = Self(3);
+}
@@ -0,0 +1,407 @@
+//! Recording and replaying the calls made to a [`ServiceBackend`].
+
+use std::collections::{HashMap, VecDeque};
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    DefaultBackend, Operation, OwnedServiceType, ServiceBackend, ServiceManagementError,
+    ServiceStatus, ServiceType,
+};
+
+/// Errors that can occur while saving or loading a [`Trace`].
+#[derive(Debug, Error)]
+pub enum TraceError {
+    /// The trace file couldn't be read or written.
+    #[error("failed to access the trace file: {0}")]
+    Io(#[from] std::io::Error),
+
+    /// The trace isn't valid JSON or doesn't match the trace format.
+    #[error("invalid trace: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+/// A single call recorded by a [`RecordingBackend`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TraceEvent {
+    /// Milliseconds since the Unix epoch at which the call returned.
+    pub timestamp_ms: u64,
+
+    /// The service the call was made for, `None` for [`Operation::OpenSystemSettings`].
+    pub service_type: Option<OwnedServiceType>,
+
+    /// The operation that was called.
+    pub operation: Operation,
+
+    /// The [`ServiceManagementError::code`] returned by a failed register or unregister call.
+    pub error_code: Option<u32>,
+
+    /// The status returned by a status call.
+    pub status: Option<ServiceStatus>,
+}
+
+impl TraceEvent {
+    /// Returns the error recorded for this event, if any.
+    pub fn error(&self) -> Option<ServiceManagementError> {
+        self.error_code.map(|code| {
+            ServiceManagementError::try_from(code).unwrap_or(ServiceManagementError::Unknown(code))
+        })
+    }
+
+    /// Returns the result recorded for a register or unregister call.
+    pub fn result(&self) -> Result<(), ServiceManagementError> {
+        match self.error() {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+}
+
+/// An ordered list of recorded calls that can be stored as JSON.
+///
+/// # Examples
+///
+/// ```rust
+/// use smappservice_rs::{
+///     AppService, RecordingBackend, ReplayBackend, ServiceType, SimulatedBackend, Trace,
+/// };
+///
+/// let recorder = RecordingBackend::new(SimulatedBackend::new());
+/// let service = AppService::with_backend(ServiceType::MainApp, recorder.clone());
+/// service.register().unwrap();
+/// let recorded_status = service.status();
+///
+/// let json = recorder.trace().to_json().unwrap();
+///
+/// let replay = ReplayBackend::new(Trace::from_json(&json).unwrap());
+/// let service = AppService::with_backend(ServiceType::MainApp, replay.clone());
+/// assert_eq!(service.register(), Ok(()));
+/// assert_eq!(service.status(), recorded_status);
+/// assert!(replay.is_exhausted());
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Trace {
+    /// The recorded calls, in the order they returned.
+    pub events: Vec<TraceEvent>,
+}
+
+impl Trace {
+    /// Serializes the trace as pretty-printed JSON.
+    pub fn to_json(&self) -> Result<String, TraceError> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Parses a trace from JSON.
+    pub fn from_json(json: &str) -> Result<Self, TraceError> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// Writes the trace as JSON to `path`.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), TraceError> {
+        std::fs::write(path, self.to_json()?)?;
+        Ok(())
+    }
+
+    /// Reads a JSON trace from `path`.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, TraceError> {
+        Self::from_json(&std::fs::read_to_string(path)?)
+    }
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// A [`ServiceBackend`] that forwards every call to another backend and records it in a [`Trace`].
+///
+/// Wrap the [`DefaultBackend`] on a customer's Mac to capture what the framework returned, then
+/// serve the saved trace with a [`ReplayBackend`]. Clones share the same trace.
+pub struct RecordingBackend<B = DefaultBackend> {
+    inner: Arc<B>,
+    trace: Arc<Mutex<Trace>>,
+}
+
+impl<B> Clone for RecordingBackend<B> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: Arc::clone(&self.inner),
+            trace: Arc::clone(&self.trace),
+        }
+    }
+}
+
+impl<B: ServiceBackend> RecordingBackend<B> {
+    /// Wraps `inner` with an empty trace.
+    pub fn new(inner: B) -> Self {
+        Self {
+            inner: Arc::new(inner),
+            trace: Arc::default(),
+        }
+    }
+
+    /// Returns a copy of the calls recorded so far.
+    pub fn trace(&self) -> Trace {
+        lock(&self.trace).clone()
+    }
+
+    /// Writes the calls recorded so far as JSON to `path`.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), TraceError> {
+        self.trace().save(path)
+    }
+
+    fn record(
+        &self,
+        service_type: Option<&ServiceType>,
+        operation: Operation,
+        error: Option<ServiceManagementError>,
+        status: Option<ServiceStatus>,
+    ) {
+        lock(&self.trace).events.push(TraceEvent {
+            timestamp_ms: now_ms(),
+            service_type: service_type.map(OwnedServiceType::from),
+            operation,
+            error_code: error.map(|error| error.code()),
+            status,
+        });
+    }
+}
+
+impl<B: ServiceBackend> ServiceBackend for RecordingBackend<B> {
+    fn register(&self, service_type: &ServiceType) -> Result<(), ServiceManagementError> {
+        let result = self.inner.register(service_type);
+        self.record(Some(service_type), Operation::Register, result.err(), None);
+        result
+    }
+
+    fn unregister(&self, service_type: &ServiceType) -> Result<(), ServiceManagementError> {
+        let result = self.inner.unregister(service_type);
+        self.record(
+            Some(service_type),
+            Operation::Unregister,
+            result.err(),
+            None,
+        );
+        result
+    }
+
+    fn status(&self, service_type: &ServiceType) -> ServiceStatus {
+        let status = self.inner.status(service_type);
+        self.record(Some(service_type), Operation::Status, None, Some(status));
+        status
+    }
+
+    fn open_system_settings_login_items(&self) {
+        self.inner.open_system_settings_login_items();
+        self.record(None, Operation::OpenSystemSettings, None, None);
+    }
+}
+
+#[derive(Debug, Default)]
+struct ReplayState {
+    pending: HashMap<(Option<OwnedServiceType>, Operation), VecDeque<TraceEvent>>,
+    unexpected: Vec<(Option<OwnedServiceType>, Operation)>,
+}
+
+/// A [`ServiceBackend`] that answers calls from a recorded [`Trace`].
+///
+/// Recorded events are grouped by service and operation, and each call consumes the oldest
+/// remaining event of its group, so the replay is deterministic even when independent services
+/// are queried in a different interleaving than during recording.
+///
+/// A call with no remaining event is logged as unexpected: register and unregister fail with
+/// [`ServiceManagementError::JobNotFound`] and status returns [`ServiceStatus::NotFound`].
+/// Clones share the same replay position.
+#[derive(Debug, Clone)]
+pub struct ReplayBackend {
+    state: Arc<Mutex<ReplayState>>,
+}
+
+impl ReplayBackend {
+    /// Creates a backend that serves the events of `trace`.
+    pub fn new(trace: Trace) -> Self {
+        let mut state = ReplayState::default();
+        for event in trace.events {
+            state
+                .pending
+                .entry((event.service_type.clone(), event.operation))
+                .or_default()
+                .push_back(event);
+        }
+        Self {
+            state: Arc::new(Mutex::new(state)),
+        }
+    }
+
+    /// Loads a JSON trace from `path` and creates a backend that serves it.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, TraceError> {
+        Ok(Self::new(Trace::load(path)?))
+    }
+
+    /// Returns how many recorded events haven't been served yet.
+    pub fn remaining(&self) -> usize {
+        lock(&self.state).pending.values().map(VecDeque::len).sum()
+    }
+
+    /// Returns `true` once every recorded event has been served.
+    pub fn is_exhausted(&self) -> bool {
+        self.remaining() == 0
+    }
+
+    /// Returns the calls that had no matching recorded event, in order.
+    pub fn unexpected_calls(&self) -> Vec<(Option<OwnedServiceType>, Operation)> {
+        lock(&self.state).unexpected.clone()
+    }
+
+    fn next(&self, service_type: Option<&ServiceType>, operation: Operation) -> Option<TraceEvent> {
+        let key = (service_type.map(OwnedServiceType::from), operation);
+        let mut state = lock(&self.state);
+        let event = state.pending.get_mut(&key).and_then(VecDeque::pop_front);
+        if event.is_none() {
+            state.unexpected.push(key);
+        }
+        event
+    }
+}
+
+impl ServiceBackend for ReplayBackend {
+    fn register(&self, service_type: &ServiceType) -> Result<(), ServiceManagementError> {
+        self.next(Some(service_type), Operation::Register)
+            .map_or(Err(ServiceManagementError::JobNotFound), |event| {
+                event.result()
+            })
+    }
+
+    fn unregister(&self, service_type: &ServiceType) -> Result<(), ServiceManagementError> {
+        self.next(Some(service_type), Operation::Unregister)
+            .map_or(Err(ServiceManagementError::JobNotFound), |event| {
+                event.result()
+            })
+    }
+
+    fn status(&self, service_type: &ServiceType) -> ServiceStatus {
+        self.next(Some(service_type), Operation::Status)
+            .and_then(|event| event.status)
+            .unwrap_or(ServiceStatus::NotFound)
+    }
+
+    fn open_system_settings_login_items(&self) {
+        self.next(None, Operation::OpenSystemSettings);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AppService, FaultInjectingBackend, SimulatedBackend};
+
+    const AGENT: ServiceType<'static> = ServiceType::Agent {
+        plist_name: "com.example.agent.plist",
+    };
+
+    #[test]
+    fn test_record_and_replay() {
+        let faults = FaultInjectingBackend::new(SimulatedBackend::new());
+        faults.fail_next(
+            &AGENT,
+            Operation::Register,
+            ServiceManagementError::InvalidSignature,
+        );
+        let recorder = RecordingBackend::new(faults);
+        let agent = AppService::with_backend(AGENT, recorder.clone());
+        let main_app = AppService::with_backend(ServiceType::MainApp, recorder.clone());
+
+        assert_eq!(
+            agent.register(),
+            Err(ServiceManagementError::InvalidSignature)
+        );
+        assert_eq!(agent.register(), Ok(()));
+        assert_eq!(agent.status(), ServiceStatus::Enabled);
+        assert_eq!(main_app.status(), ServiceStatus::NotRegistered);
+        agent.open_system_settings();
+
+        let trace = recorder.trace();
+        assert_eq!(trace.events.len(), 5);
+        assert_eq!(
+            trace.events[0].error_code,
+            Some(ServiceManagementError::InvalidSignature.code())
+        );
+        assert_eq!(trace.events[2].status, Some(ServiceStatus::Enabled));
+
+        let trace = Trace::from_json(&trace.to_json().unwrap()).unwrap();
+        let replay = ReplayBackend::new(trace);
+        let agent = AppService::with_backend(AGENT, replay.clone());
+        let main_app = AppService::with_backend(ServiceType::MainApp, replay.clone());
+
+        // Independent services can be queried in a different order than recorded.
+        assert_eq!(main_app.status(), ServiceStatus::NotRegistered);
+        assert_eq!(
+            agent.register(),
+            Err(ServiceManagementError::InvalidSignature)
+        );
+        assert_eq!(agent.register(), Ok(()));
+        assert_eq!(agent.status(), ServiceStatus::Enabled);
+        agent.open_system_settings();
+        assert!(replay.is_exhausted());
+        assert!(replay.unexpected_calls().is_empty());
+
+        assert_eq!(agent.unregister(), Err(ServiceManagementError::JobNotFound));
+        assert_eq!(
+            replay.unexpected_calls(),
+            [(Some(OwnedServiceType::from(AGENT)), Operation::Unregister)]
+        );
+    }
+
+    #[test]
+    fn test_trace_json_format() {
+        let json = r#"{
+            "events": [
+                {
+                    "timestamp_ms": 1700000000000,
+                    "service_type": { "kind": "daemon", "plist_name": "com.example.daemon.plist" },
+                    "operation": "register",
+                    "error_code": 4,
+                    "status": null
+                },
+                {
+                    "timestamp_ms": 1700000000100,
+                    "service_type": { "kind": "main_app" },
+                    "operation": "status",
+                    "error_code": null,
+                    "status": "requires_approval"
+                }
+            ]
+        }"#;
+        let trace = Trace::from_json(json).unwrap();
+        assert_eq!(
+            trace.events[0].error(),
+            Some(ServiceManagementError::AuthorizationFailure)
+        );
+        assert_eq!(
+            trace.events[1].service_type,
+            Some(OwnedServiceType::MainApp)
+        );
+        assert_eq!(
+            trace.events[1].status,
+            Some(ServiceStatus::RequiresApproval)
+        );
+        assert!(
+            trace
+                .to_json()
+                .unwrap()
+                .contains("\"status\": \"requires_approval\"")
+        );
+    }
+}
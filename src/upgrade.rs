@@ -0,0 +1,570 @@
+//! Re-registering services whose definition changed in an app update.
+//!
+//! launchd keeps the configuration a job was registered with. When an update ships a changed
+//! plist, the old configuration stays in effect until the service is unregistered and registered
+//! again. [`Upgrader`] remembers a [`Fingerprint`] of each registered service across launches and
+//! re-registers the ones whose fingerprint changed.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::digest::{hex, sha256};
+use crate::preflight::{self, find_helper};
+use crate::state::{self, StateError};
+use crate::{
+    AppService, DefaultBackend, InfoPlist, OwnedServiceType, PreflightError, ServiceBackend,
+    ServiceManagementError, ServiceStatus, ServiceType,
+};
+
+/// The name of the fingerprint file inside the app's Application Support directory.
+const STORE_FILE_NAME: &str = "service-fingerprints.json";
+
+/// Errors that can occur while fingerprinting or re-registering services.
+#[derive(Debug, Error)]
+pub enum UpgradeError {
+    /// The file defining a service couldn't be read.
+    #[error("failed to access `{path}`: {source}")]
+    Io {
+        /// The file that couldn't be read.
+        path: PathBuf,
+        /// The underlying error.
+        source: std::io::Error,
+    },
+
+    /// The fingerprint store couldn't be located, read or written.
+    #[error(transparent)]
+    State(#[from] StateError),
+
+    /// The service's files aren't in place, so it can't be fingerprinted or registered.
+    #[error(transparent)]
+    Preflight(#[from] PreflightError),
+
+    /// Unregistering or registering the service failed.
+    #[error("failed to re-register the service: {0}")]
+    Register(#[from] ServiceManagementError),
+}
+
+/// What identifies the registered version of a service.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Fingerprint {
+    /// The SHA-256 of the file defining the service, in hexadecimal: the launchd plist of an
+    /// agent or daemon, or the `Info.plist` of the app or login item helper.
+    pub plist_sha256: String,
+
+    /// The app's `CFBundleVersion`.
+    pub bundle_version: Option<String>,
+
+    /// Where the app bundle was.
+    pub bundle_path: PathBuf,
+}
+
+impl Fingerprint {
+    /// Fingerprints `service_type` as defined by the app bundle at `bundle`.
+    pub fn for_service(
+        bundle: impl AsRef<Path>,
+        service_type: &ServiceType,
+    ) -> Result<Self, UpgradeError> {
+        let bundle = bundle.as_ref();
+        let contents = bundle.join("Contents");
+        if !contents.is_dir() {
+            return Err(PreflightError::NotABundle(bundle.to_path_buf()).into());
+        }
+        let definition = match service_type {
+            ServiceType::MainApp => contents.join("Info.plist"),
+            ServiceType::Agent { plist_name } => {
+                contents.join("Library/LaunchAgents").join(plist_name)
+            }
+            ServiceType::Daemon { plist_name } => {
+                contents.join("Library/LaunchDaemons").join(plist_name)
+            }
+            ServiceType::LoginItem { identifier } => {
+                find_helper(&contents.join("Library/LoginItems"), identifier)?
+                    .join("Contents/Info.plist")
+            }
+        };
+        let data = std::fs::read(&definition).map_err(|source| UpgradeError::Io {
+            path: definition,
+            source,
+        })?;
+        Ok(Self {
+            plist_sha256: hex(&sha256(&data)),
+            bundle_version: InfoPlist::for_bundle(bundle)
+                .ok()
+                .and_then(|info| info.bundle_version),
+            bundle_path: bundle.to_path_buf(),
+        })
+    }
+
+    /// Lists what differs between `self`, the stored fingerprint, and `new`.
+    pub fn changes(&self, new: &Fingerprint) -> Vec<FingerprintChange> {
+        let mut changes = Vec::new();
+        if self.plist_sha256 != new.plist_sha256 {
+            changes.push(FingerprintChange::Plist);
+        }
+        if self.bundle_version != new.bundle_version {
+            changes.push(FingerprintChange::BundleVersion {
+                old: self.bundle_version.clone(),
+                new: new.bundle_version.clone(),
+            });
+        }
+        if self.bundle_path != new.bundle_path {
+            changes.push(FingerprintChange::BundlePath {
+                old: self.bundle_path.clone(),
+                new: new.bundle_path.clone(),
+            });
+        }
+        changes
+    }
+}
+
+/// A difference between two [`Fingerprint`]s.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FingerprintChange {
+    /// The file defining the service changed.
+    Plist,
+
+    /// The app's `CFBundleVersion` changed.
+    BundleVersion {
+        old: Option<String>,
+        new: Option<String>,
+    },
+
+    /// The app bundle moved.
+    BundlePath { old: PathBuf, new: PathBuf },
+}
+
+impl fmt::Display for FingerprintChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FingerprintChange::Plist => write!(f, "plist changed"),
+            FingerprintChange::BundleVersion { old, new } => write!(
+                f,
+                "version {} -> {}",
+                old.as_deref().unwrap_or("none"),
+                new.as_deref().unwrap_or("none")
+            ),
+            FingerprintChange::BundlePath { old, new } => {
+                write!(f, "moved {} -> {}", old.display(), new.display())
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct FingerprintRecord {
+    service_type: OwnedServiceType,
+    fingerprint: Fingerprint,
+}
+
+/// The fingerprints of the registered services, kept in a JSON file across launches.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FingerprintStore {
+    #[serde(skip)]
+    path: PathBuf,
+    services: Vec<FingerprintRecord>,
+}
+
+impl FingerprintStore {
+    /// Reads the store at `path`. A missing file is an empty store.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, StateError> {
+        let path = path.as_ref().to_path_buf();
+        Ok(Self {
+            path: path.clone(),
+            ..state::read(&path)?
+        })
+    }
+
+    /// Reads the store in `~/Library/Application Support/<identifier>`, where `identifier` is
+    /// the app's bundle identifier.
+    pub fn for_app(identifier: &str) -> Result<Self, StateError> {
+        Self::open(state::application_support(identifier, STORE_FILE_NAME)?)
+    }
+
+    /// Returns the path of the store file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the stored fingerprint of `service_type`.
+    pub fn get(&self, service_type: &ServiceType) -> Option<&Fingerprint> {
+        let service_type = OwnedServiceType::from(service_type);
+        self.services
+            .iter()
+            .find(|record| record.service_type == service_type)
+            .map(|record| &record.fingerprint)
+    }
+
+    /// Stores the fingerprint of `service_type`, replacing any previous one.
+    pub fn insert(&mut self, service_type: &ServiceType, fingerprint: Fingerprint) {
+        let service_type = OwnedServiceType::from(service_type);
+        match self
+            .services
+            .iter_mut()
+            .find(|record| record.service_type == service_type)
+        {
+            Some(record) => record.fingerprint = fingerprint,
+            None => self.services.push(FingerprintRecord {
+                service_type,
+                fingerprint,
+            }),
+        }
+    }
+
+    /// Forgets the fingerprint of `service_type` and returns it.
+    pub fn remove(&mut self, service_type: &ServiceType) -> Option<Fingerprint> {
+        let service_type = OwnedServiceType::from(service_type);
+        let index = self
+            .services
+            .iter()
+            .position(|record| record.service_type == service_type)?;
+        Some(self.services.remove(index).fingerprint)
+    }
+
+    /// Writes the store to its file, creating the directory if needed.
+    ///
+    /// The file is replaced atomically, so a crash while saving leaves the previous version.
+    pub fn save(&self) -> Result<(), StateError> {
+        state::write(&self.path, self)
+    }
+}
+
+/// What [`Upgrader::run`] did with one service.
+#[derive(Debug)]
+pub enum UpgradeOutcome {
+    /// The service isn't registered, so there was nothing to refresh. Any stored fingerprint
+    /// was dropped.
+    NotRegistered,
+
+    /// The service is registered but had no stored fingerprint, for example on the first launch
+    /// that uses the upgrader. Its current fingerprint was stored without re-registering it.
+    Recorded,
+
+    /// The service is registered and its fingerprint hasn't changed.
+    Unchanged,
+
+    /// The fingerprint changed, so the service was unregistered and registered again.
+    Reregistered {
+        /// What changed since the service was last registered.
+        changes: Vec<FingerprintChange>,
+        /// The status after registering again. Daemons may need approval again.
+        status: ServiceStatus,
+    },
+
+    /// The service couldn't be refreshed.
+    ///
+    /// A [`UpgradeError::Preflight`] failure is detected before unregistering, so the old
+    /// registration is kept. A [`UpgradeError::Register`] failure can leave the service
+    /// unregistered; its status tells.
+    Failed(UpgradeError),
+}
+
+impl fmt::Display for UpgradeOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UpgradeOutcome::NotRegistered => write!(f, "not registered"),
+            UpgradeOutcome::Recorded => write!(f, "fingerprint recorded"),
+            UpgradeOutcome::Unchanged => write!(f, "unchanged"),
+            UpgradeOutcome::Reregistered { changes, status } => {
+                let changes: Vec<String> = changes.iter().map(ToString::to_string).collect();
+                write!(f, "re-registered ({}), now {}", changes.join(", "), status)
+            }
+            UpgradeOutcome::Failed(error) => write!(f, "failed: {}", error),
+        }
+    }
+}
+
+/// The per-service results of [`Upgrader::run`].
+#[derive(Debug, Default)]
+pub struct UpgradeReport {
+    /// Each service with what was done to it, in the order given to `run`.
+    pub services: Vec<(OwnedServiceType, UpgradeOutcome)>,
+}
+
+impl UpgradeReport {
+    /// Returns the services that were re-registered.
+    pub fn reregistered(&self) -> impl Iterator<Item = &OwnedServiceType> {
+        self.services
+            .iter()
+            .filter(|(_, outcome)| matches!(outcome, UpgradeOutcome::Reregistered { .. }))
+            .map(|(service_type, _)| service_type)
+    }
+
+    /// Returns whether no service failed.
+    pub fn is_success(&self) -> bool {
+        !self
+            .services
+            .iter()
+            .any(|(_, outcome)| matches!(outcome, UpgradeOutcome::Failed(_)))
+    }
+}
+
+impl fmt::Display for UpgradeReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (service_type, outcome) in &self.services {
+            writeln!(f, "{}: {}", service_type, outcome)?;
+        }
+        Ok(())
+    }
+}
+
+/// Re-registers services whose definition changed since they were registered.
+///
+/// Call [`run`](#method.run) on every launch with the services the app manages. For each
+/// registered service it compares the stored [`Fingerprint`] with the current one and, if they
+/// differ, checks the new files with [`preflight`](crate::preflight) before unregistering the
+/// service and registering it again. The store is saved at the end of the run.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use smappservice_rs::{ServiceType, Upgrader};
+///
+/// let mut upgrader = Upgrader::for_current_app().unwrap();
+/// let report = upgrader
+///     .run([ServiceType::Agent { plist_name: "com.example.myapp.agent.plist" }])
+///     .unwrap();
+/// print!("{}", report);
+/// ```
+pub struct Upgrader {
+    bundle: PathBuf,
+    store: FingerprintStore,
+    backend: Arc<dyn ServiceBackend>,
+}
+
+impl fmt::Debug for Upgrader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Upgrader")
+            .field("bundle", &self.bundle)
+            .field("store", &self.store)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Upgrader {
+    /// Creates an upgrader for the app at `bundle` that uses the platform's [`DefaultBackend`].
+    pub fn new(bundle: impl Into<PathBuf>, store: FingerprintStore) -> Self {
+        Self::with_backend(bundle, store, DefaultBackend::default())
+    }
+
+    /// Creates an upgrader for the app at `bundle` that goes through `backend`.
+    pub fn with_backend(
+        bundle: impl Into<PathBuf>,
+        store: FingerprintStore,
+        backend: impl ServiceBackend + 'static,
+    ) -> Self {
+        Self {
+            bundle: bundle.into(),
+            store,
+            backend: Arc::new(backend),
+        }
+    }
+
+    /// Creates an upgrader for the running app, with its store in the app's Application Support
+    /// directory.
+    pub fn for_current_app() -> Result<Self, UpgradeError> {
+        let (bundle, identifier) = state::current_app()?;
+        Ok(Self::new(bundle, FingerprintStore::for_app(&identifier)?))
+    }
+
+    /// Returns the fingerprint store.
+    pub fn store(&self) -> &FingerprintStore {
+        &self.store
+    }
+
+    /// Checks `services` for drift, re-registers the ones that changed and saves the store.
+    ///
+    /// Fails only if the store can't be saved; per-service failures are in the report.
+    pub fn run<S: Into<OwnedServiceType>>(
+        &mut self,
+        services: impl IntoIterator<Item = S>,
+    ) -> Result<UpgradeReport, UpgradeError> {
+        let services = services
+            .into_iter()
+            .map(|service_type| {
+                let service_type = service_type.into();
+                let outcome = self.refresh(&service_type.as_service_type());
+                (service_type, outcome)
+            })
+            .collect();
+        self.store.save()?;
+        Ok(UpgradeReport { services })
+    }
+
+    fn refresh(&mut self, service_type: &ServiceType) -> UpgradeOutcome {
+        let service = AppService::with_backend(*service_type, self.backend.clone());
+        if matches!(
+            service.status(),
+            ServiceStatus::NotRegistered | ServiceStatus::NotFound
+        ) {
+            self.store.remove(service_type);
+            return UpgradeOutcome::NotRegistered;
+        }
+
+        let current = match Fingerprint::for_service(&self.bundle, service_type) {
+            Ok(current) => current,
+            Err(error) => return UpgradeOutcome::Failed(error),
+        };
+        let changes = match self.store.get(service_type) {
+            None => {
+                self.store.insert(service_type, current);
+                return UpgradeOutcome::Recorded;
+            }
+            Some(stored) => stored.changes(&current),
+        };
+        if changes.is_empty() {
+            return UpgradeOutcome::Unchanged;
+        }
+
+        // Check the new files before dropping the working registration.
+        if let Err(error) = preflight::preflight(&self.bundle, service_type) {
+            return UpgradeOutcome::Failed(error.into());
+        }
+        if let Err(error) = service.ensure_unregistered() {
+            return UpgradeOutcome::Failed(error.into());
+        }
+        match service.ensure_registered() {
+            Ok(report) => {
+                self.store.insert(service_type, current);
+                UpgradeOutcome::Reregistered {
+                    changes,
+                    status: report.after,
+                }
+            }
+            Err(error) => {
+                self.store.remove(service_type);
+                UpgradeOutcome::Failed(error.into())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bundle::fixture::FixtureBundle;
+    use crate::{FaultInjectingBackend, LaunchdPlist, Operation, SimulatedBackend};
+
+    const AGENT: ServiceType<'static> = ServiceType::Agent {
+        plist_name: "com.example.agent.plist",
+    };
+
+    fn app(version: &str) -> FixtureBundle {
+        let bundle = FixtureBundle::new("Upgrade");
+        let info = InfoPlist {
+            bundle_identifier: Some("com.example.app".to_string()),
+            bundle_version: Some(version.to_string()),
+            ..InfoPlist::default()
+        };
+        info.write_to_file(bundle.contents().join("Info.plist"))
+            .unwrap();
+        let agent = bundle.write_file("Contents/MacOS/agent", b"#!/bin/sh\n");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(agent, std::fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        write_agent(&bundle, false);
+        bundle
+    }
+
+    fn write_agent(bundle: &FixtureBundle, run_at_load: bool) {
+        bundle.add_launchd_plist(
+            "LaunchAgents",
+            &LaunchdPlist {
+                bundle_program: Some("Contents/MacOS/agent".to_string()),
+                run_at_load: Some(run_at_load),
+                ..LaunchdPlist::new("com.example.agent")
+            },
+        );
+    }
+
+    fn outcome(report: &UpgradeReport) -> &UpgradeOutcome {
+        &report.services[0].1
+    }
+
+    #[test]
+    fn test_reregister_on_drift() {
+        let bundle = app("1");
+        let store_path = bundle
+            .path
+            .with_file_name("Application Support/com.example.app")
+            .join(STORE_FILE_NAME);
+        let backend = FaultInjectingBackend::new(SimulatedBackend::new());
+        let upgrader = |backend: &FaultInjectingBackend| {
+            Upgrader::with_backend(
+                &bundle.path,
+                FingerprintStore::open(&store_path).unwrap(),
+                backend.clone(),
+            )
+        };
+
+        let report = upgrader(&backend).run([AGENT]).unwrap();
+        assert!(matches!(outcome(&report), UpgradeOutcome::NotRegistered));
+
+        AppService::with_backend(AGENT, backend.clone())
+            .register()
+            .unwrap();
+        let report = upgrader(&backend).run([AGENT]).unwrap();
+        assert!(matches!(outcome(&report), UpgradeOutcome::Recorded));
+        let report = upgrader(&backend).run([AGENT]).unwrap();
+        assert!(matches!(outcome(&report), UpgradeOutcome::Unchanged));
+
+        // The update changes the plist and the version.
+        write_agent(&bundle, true);
+        InfoPlist {
+            bundle_version: Some("2".to_string()),
+            ..InfoPlist::for_bundle(&bundle.path).unwrap()
+        }
+        .write_to_file(bundle.contents().join("Info.plist"))
+        .unwrap();
+        let report = upgrader(&backend).run([AGENT]).unwrap();
+        assert_eq!(
+            report.to_string(),
+            "agent com.example.agent.plist: re-registered (plist changed, version 1 -> 2), \
+             now Enabled\n"
+        );
+        assert_eq!(backend.call_count(&AGENT, Operation::Register), 2);
+        assert_eq!(backend.call_count(&AGENT, Operation::Unregister), 1);
+
+        let report = upgrader(&backend).run([AGENT]).unwrap();
+        assert!(matches!(outcome(&report), UpgradeOutcome::Unchanged));
+        assert_eq!(report.reregistered().count(), 0);
+    }
+
+    #[test]
+    fn test_broken_update_keeps_registration() {
+        let bundle = app("1");
+        let backend = SimulatedBackend::new();
+        backend.set_status(&AGENT, ServiceStatus::Enabled);
+        let mut upgrader = Upgrader::with_backend(
+            &bundle.path,
+            FingerprintStore::open(bundle.path.with_file_name(STORE_FILE_NAME)).unwrap(),
+            backend.clone(),
+        );
+        upgrader.run([AGENT]).unwrap();
+
+        // The new agent plist points at an executable that isn't in the bundle.
+        bundle.add_launchd_plist(
+            "LaunchAgents",
+            &LaunchdPlist {
+                bundle_program: Some("Contents/MacOS/missing".to_string()),
+                ..LaunchdPlist::new("com.example.agent")
+            },
+        );
+        let report = upgrader.run([AGENT]).unwrap();
+        assert!(!report.is_success());
+        assert!(matches!(
+            outcome(&report),
+            UpgradeOutcome::Failed(UpgradeError::Preflight(_))
+        ));
+        assert_eq!(backend.status(&AGENT), ServiceStatus::Enabled);
+
+        let saved = FingerprintStore::open(upgrader.store().path()).unwrap();
+        assert_eq!(saved.get(&AGENT), upgrader.store().get(&AGENT));
+        assert!(saved.get(&AGENT).is_some());
+    }
+}
@@ -0,0 +1,262 @@
+//! Blocking until a service reaches a status, for example after the user approves it.
+
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use thiserror::Error;
+
+use crate::{AppService, ServiceStatus};
+
+/// How often [`AppService::wait_for`] polls the status.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Why [`AppService::wait_for`] stopped waiting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum WaitError {
+    /// The timeout passed before the status matched.
+    #[error("timed out waiting for the service (last status: {last})")]
+    TimedOut {
+        /// The status when the timeout passed.
+        last: ServiceStatus,
+    },
+
+    /// The [`CancellationToken`] was cancelled before the status matched.
+    #[error("stopped waiting for the service (last status: {last})")]
+    Cancelled {
+        /// The status when the wait was cancelled.
+        last: ServiceStatus,
+    },
+}
+
+impl WaitError {
+    /// Returns the last status seen before waiting stopped.
+    pub fn last_status(&self) -> ServiceStatus {
+        match *self {
+            WaitError::TimedOut { last } | WaitError::Cancelled { last } => last,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Token {
+    cancelled: Mutex<bool>,
+    wake: Condvar,
+}
+
+/// Cancels a wait from another thread, for example when the user closes an onboarding window.
+///
+/// Clones share the same state: cancelling any of them cancels them all. A token stays cancelled,
+/// so a fresh one is needed for each wait that should be cancellable on its own.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    token: Arc<Token>,
+}
+
+impl CancellationToken {
+    /// Creates a token that isn't cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancels the token, waking every wait that uses it.
+    pub fn cancel(&self) {
+        *self.lock() = true;
+        self.token.wake.notify_all();
+    }
+
+    /// Returns whether [`cancel`](#method.cancel) has been called.
+    pub fn is_cancelled(&self) -> bool {
+        *self.lock()
+    }
+
+    /// Sleeps for `timeout` or until the token is cancelled. Returns whether it was cancelled.
+    ///
+    /// A `timeout` too long to represent, such as [`Duration::MAX`], sleeps until cancelled.
+    pub(crate) fn sleep(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now().checked_add(timeout);
+        let mut cancelled = self.lock();
+        while !*cancelled {
+            let Some(deadline) = deadline else {
+                cancelled = self
+                    .token
+                    .wake
+                    .wait(cancelled)
+                    .unwrap_or_else(|poisoned| poisoned.into_inner());
+                continue;
+            };
+            let Some(remaining) = deadline.checked_duration_since(Instant::now()) else {
+                break;
+            };
+            cancelled = self
+                .token
+                .wake
+                .wait_timeout(cancelled, remaining)
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .0;
+        }
+        *cancelled
+    }
+
+    fn lock(&self) -> MutexGuard<'_, bool> {
+        self.token
+            .cancelled
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl AppService {
+    /// Blocks until the service's status satisfies `predicate` and returns that status.
+    ///
+    /// The status is checked immediately and then polled until `timeout` passes or `cancel` is
+    /// cancelled, which wakes the wait right away. Pass [`Duration::MAX`] to wait until the status
+    /// matches or the wait is cancelled.
+    ///
+    /// # Examples
+    ///
+    /// Waiting for the user to approve a daemon:
+    ///
+    /// ```rust,no_run
+    /// use std::time::Duration;
+    /// use smappservice_rs::{AppService, CancellationToken, ServiceStatus, ServiceType};
+    ///
+    /// let daemon = AppService::new(ServiceType::Daemon {
+    ///     plist_name: "com.example.myapp.daemon.plist",
+    /// });
+    /// daemon.register().unwrap();
+    ///
+    /// let cancel = CancellationToken::new();
+    /// // Hand a clone of `cancel` to the UI so closing the window stops the wait.
+    /// match daemon.wait_for(
+    ///     |status| status != ServiceStatus::RequiresApproval,
+    ///     Duration::from_secs(300),
+    ///     &cancel,
+    /// ) {
+    ///     Ok(status) => println!("Daemon is now {}", status),
+    ///     Err(e) => eprintln!("{}", e),
+    /// }
+    /// ```
+    pub fn wait_for(
+        &self,
+        mut predicate: impl FnMut(ServiceStatus) -> bool,
+        timeout: Duration,
+        cancel: &CancellationToken,
+    ) -> Result<ServiceStatus, WaitError> {
+        // A timeout too long to represent never passes.
+        let deadline = Instant::now().checked_add(timeout);
+        loop {
+            let status = self.status();
+            if predicate(status) {
+                return Ok(status);
+            }
+            if cancel.is_cancelled() {
+                return Err(WaitError::Cancelled { last: status });
+            }
+            let remaining = match deadline {
+                Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                    Some(remaining) if !remaining.is_zero() => remaining,
+                    _ => return Err(WaitError::TimedOut { last: status }),
+                },
+                None => POLL_INTERVAL,
+            };
+            if cancel.sleep(POLL_INTERVAL.min(remaining)) {
+                return Err(WaitError::Cancelled { last: status });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+    use crate::{ServiceType, SimulatedBackend};
+
+    const DAEMON: ServiceType<'static> = ServiceType::Daemon {
+        plist_name: "com.example.daemon.plist",
+    };
+
+    fn not_pending(status: ServiceStatus) -> bool {
+        status != ServiceStatus::RequiresApproval
+    }
+
+    #[test]
+    fn test_wait_for_approval() {
+        let backend = SimulatedBackend::new();
+        let daemon = AppService::with_backend(DAEMON, backend.clone());
+        daemon.register().unwrap();
+
+        let approver = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            backend.approve(&DAEMON);
+        });
+        let status = daemon.wait_for(
+            not_pending,
+            Duration::from_secs(10),
+            &CancellationToken::new(),
+        );
+        approver.join().unwrap();
+        assert_eq!(status, Ok(ServiceStatus::Enabled));
+    }
+
+    #[test]
+    fn test_wait_for_timeout() {
+        let daemon = AppService::with_backend(DAEMON, SimulatedBackend::new());
+        daemon.register().unwrap();
+
+        let start = Instant::now();
+        let result = daemon.wait_for(
+            not_pending,
+            Duration::from_millis(30),
+            &CancellationToken::new(),
+        );
+        assert!(start.elapsed() >= Duration::from_millis(30));
+        assert_eq!(
+            result,
+            Err(WaitError::TimedOut {
+                last: ServiceStatus::RequiresApproval
+            })
+        );
+    }
+
+    #[test]
+    fn test_wait_for_cancelled() {
+        let daemon = AppService::with_backend(DAEMON, SimulatedBackend::new());
+        daemon.register().unwrap();
+
+        let cancel = CancellationToken::new();
+        let canceller = thread::spawn({
+            let cancel = cancel.clone();
+            move || {
+                thread::sleep(Duration::from_millis(20));
+                cancel.cancel();
+            }
+        });
+        let start = Instant::now();
+        let result = daemon.wait_for(not_pending, Duration::MAX, &cancel);
+        canceller.join().unwrap();
+        assert!(start.elapsed() < Duration::from_secs(10));
+        assert_eq!(
+            result,
+            Err(WaitError::Cancelled {
+                last: ServiceStatus::RequiresApproval
+            })
+        );
+        assert!(cancel.is_cancelled());
+    }
+
+    #[test]
+    fn test_sleep_without_deadline() {
+        let cancel = CancellationToken::new();
+        let canceller = thread::spawn({
+            let cancel = cancel.clone();
+            move || {
+                thread::sleep(Duration::from_millis(20));
+                cancel.cancel();
+            }
+        });
+        assert!(cancel.sleep(Duration::MAX));
+        canceller.join().unwrap();
+    }
+}
@@ -0,0 +1,497 @@
+//! Background polling of service statuses.
+
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::{OwnedServiceType, ServiceBackend, ServiceStatus, ServiceType};
+
+/// The shortest delay between polls, so a zero interval doesn't keep a core busy.
+const MIN_INTERVAL: Duration = Duration::from_millis(1);
+
+/// A change in a watched service's status.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatusChange {
+    /// The service whose status changed.
+    pub service_type: OwnedServiceType,
+
+    /// The status before the change.
+    pub old: ServiceStatus,
+
+    /// The status after the change.
+    pub new: ServiceStatus,
+}
+
+/// How often a [`StatusWatcher`] polls.
+///
+/// The watcher starts at `interval`. Each poll that finds no change multiplies the interval by
+/// `backoff`, up to `max_interval`. A change, or a call to [`StatusWatcher::refresh`], resets it to
+/// `interval`. A `backoff` of 1.0 polls at a fixed rate.
+///
+/// An `interval` shorter than a millisecond is treated as one millisecond, and a `max_interval`
+/// shorter than `interval` as `interval`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WatchOptions {
+    /// The delay between polls after a change.
+    pub interval: Duration,
+
+    /// The longest delay between polls.
+    pub max_interval: Duration,
+
+    /// The factor the delay grows by after each poll without a change.
+    pub backoff: f64,
+}
+
+impl Default for WatchOptions {
+    /// Polls every second, backing off to every 8 seconds while nothing changes.
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(1),
+            max_interval: Duration::from_secs(8),
+            backoff: 2.0,
+        }
+    }
+}
+
+impl WatchOptions {
+    fn clamped(self) -> Self {
+        let interval = self.interval.max(MIN_INTERVAL);
+        Self {
+            interval,
+            max_interval: self.max_interval.max(interval),
+            ..self
+        }
+    }
+
+    fn next_interval(&self, current: Duration) -> Duration {
+        // `max` also replaces NaN. A product too large for a `Duration` is capped like any other.
+        Duration::try_from_secs_f64(current.as_secs_f64() * self.backoff.max(1.0))
+            .unwrap_or(self.max_interval)
+            .min(self.max_interval)
+            .max(self.interval)
+    }
+}
+
+#[derive(Debug, Default)]
+struct WatchState {
+    stopped: bool,
+    refresh: bool,
+    statuses: Vec<(OwnedServiceType, Option<ServiceStatus>)>,
+}
+
+#[derive(Debug, Default)]
+struct Shared {
+    state: Mutex<WatchState>,
+    wake: Condvar,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, WatchState> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Watches a set of services on a background thread and reports their status changes.
+///
+/// The first poll records each service's initial status without reporting it. Every later poll
+/// reports the services whose status differs from the previous poll, for example when the user
+/// turns a login item off in System Settings. Dropping the watcher, or calling
+/// [`stop`](#method.stop), stops the thread.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::time::Duration;
+/// use smappservice_rs::{ServiceStatus, ServiceType, SimulatedBackend, StatusWatcher, WatchOptions};
+///
+/// let backend = SimulatedBackend::new();
+/// let options = WatchOptions {
+///     interval: Duration::from_millis(10),
+///     ..WatchOptions::default()
+/// };
+/// let (watcher, changes) =
+///     StatusWatcher::channel(backend.clone(), [ServiceType::MainApp], options);
+///
+/// // Wait for the initial poll, then change the status behind the watcher's back.
+/// while watcher.status(&ServiceType::MainApp).is_none() {
+///     std::thread::sleep(Duration::from_millis(1));
+/// }
+/// backend.set_status(&ServiceType::MainApp, ServiceStatus::Enabled);
+///
+/// let change = changes.recv_timeout(Duration::from_secs(5)).unwrap();
+/// assert_eq!(change.old, ServiceStatus::NotRegistered);
+/// assert_eq!(change.new, ServiceStatus::Enabled);
+/// ```
+#[derive(Debug)]
+pub struct StatusWatcher {
+    shared: Arc<Shared>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl StatusWatcher {
+    /// Starts watching `services` through `backend`, calling `on_change` on the watcher thread for
+    /// every change.
+    pub fn spawn<B, S, F>(
+        backend: B,
+        services: impl IntoIterator<Item = S>,
+        options: WatchOptions,
+        mut on_change: F,
+    ) -> Self
+    where
+        B: ServiceBackend + 'static,
+        S: Into<OwnedServiceType>,
+        F: FnMut(StatusChange) + Send + 'static,
+    {
+        Self::start(backend, services, options, move |change| {
+            on_change(change);
+            true
+        })
+    }
+
+    /// Starts watching `services` through `backend` and returns a receiver for the changes.
+    ///
+    /// Dropping the receiver doesn't wake the watcher: it notices when it next tries to send a
+    /// change and stops then. Call [`stop`](#method.stop) to end it right away.
+    pub fn channel<B, S>(
+        backend: B,
+        services: impl IntoIterator<Item = S>,
+        options: WatchOptions,
+    ) -> (Self, Receiver<StatusChange>)
+    where
+        B: ServiceBackend + 'static,
+        S: Into<OwnedServiceType>,
+    {
+        let (sender, receiver) = mpsc::channel();
+        let watcher = Self::start(backend, services, options, move |change| {
+            sender.send(change).is_ok()
+        });
+        (watcher, receiver)
+    }
+
+    fn start<B, S, F>(
+        backend: B,
+        services: impl IntoIterator<Item = S>,
+        options: WatchOptions,
+        on_change: F,
+    ) -> Self
+    where
+        B: ServiceBackend + 'static,
+        S: Into<OwnedServiceType>,
+        F: FnMut(StatusChange) -> bool + Send + 'static,
+    {
+        let shared = Arc::new(Shared::default());
+        shared.lock().statuses = services
+            .into_iter()
+            .map(|service| (service.into(), None))
+            .collect();
+        let thread = thread::Builder::new()
+            .name("smappservice-status-watcher".to_string())
+            .spawn({
+                let shared = shared.clone();
+                move || watch(&backend, &shared, options, on_change)
+            })
+            .expect("failed to spawn the status watcher thread");
+        Self {
+            shared,
+            thread: Some(thread),
+        }
+    }
+
+    /// Returns the status of `service_type` as of the last poll, or `None` if it isn't watched or
+    /// hasn't been polled yet.
+    pub fn status(&self, service_type: &ServiceType) -> Option<ServiceStatus> {
+        let service_type = OwnedServiceType::from(*service_type);
+        self.shared
+            .lock()
+            .statuses
+            .iter()
+            .find(|(candidate, _)| *candidate == service_type)
+            .and_then(|(_, status)| *status)
+    }
+
+    /// Polls immediately and resets the interval, for example when the app becomes active.
+    pub fn refresh(&self) {
+        self.shared.lock().refresh = true;
+        self.shared.wake.notify_all();
+    }
+
+    /// Returns whether the watcher thread is still running. It exits after [`stop`](#method.stop)
+    /// or at the first change after a channel's receiver is dropped.
+    pub fn is_running(&self) -> bool {
+        self.thread
+            .as_ref()
+            .is_some_and(|thread| !thread.is_finished())
+    }
+
+    /// Stops the watcher and waits for its thread to exit.
+    pub fn stop(mut self) {
+        self.shutdown();
+    }
+
+    fn shutdown(&mut self) {
+        self.shared.lock().stopped = true;
+        self.shared.wake.notify_all();
+        // The callback may drop the watcher from the watcher thread, which can't join itself.
+        if let Some(thread) = self.thread.take()
+            && thread.thread().id() != thread::current().id()
+        {
+            // A panic in the callback has already been reported by the thread itself.
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for StatusWatcher {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+fn watch<B: ServiceBackend>(
+    backend: &B,
+    shared: &Shared,
+    options: WatchOptions,
+    mut on_change: impl FnMut(StatusChange) -> bool,
+) {
+    let options = options.clamped();
+    let services: Vec<OwnedServiceType> = shared
+        .lock()
+        .statuses
+        .iter()
+        .map(|(service, _)| service.clone())
+        .collect();
+    let mut interval = options.interval;
+    let mut first = true;
+    loop {
+        if !first {
+            // An interval too long to represent waits for a refresh or stop.
+            let deadline = Instant::now().checked_add(interval);
+            let mut state = shared.lock();
+            while !state.stopped && !state.refresh {
+                let Some(deadline) = deadline else {
+                    state = shared
+                        .wake
+                        .wait(state)
+                        .unwrap_or_else(|poisoned| poisoned.into_inner());
+                    continue;
+                };
+                let Some(remaining) = deadline.checked_duration_since(Instant::now()) else {
+                    break;
+                };
+                state = shared
+                    .wake
+                    .wait_timeout(state, remaining)
+                    .unwrap_or_else(|poisoned| poisoned.into_inner())
+                    .0;
+            }
+            if state.stopped {
+                return;
+            }
+            if std::mem::take(&mut state.refresh) {
+                interval = options.interval;
+            }
+        }
+
+        // Query the backend without holding the lock, since status calls can be slow.
+        let current: Vec<ServiceStatus> = services
+            .iter()
+            .map(|service| backend.status(&service.as_service_type()))
+            .collect();
+        let mut changes = Vec::new();
+        {
+            let mut state = shared.lock();
+            if state.stopped {
+                return;
+            }
+            for ((service, previous), new) in state.statuses.iter_mut().zip(current) {
+                if let Some(old) = previous.replace(new)
+                    && old != new
+                {
+                    changes.push(StatusChange {
+                        service_type: service.clone(),
+                        old,
+                        new,
+                    });
+                }
+            }
+        }
+
+        interval = if changes.is_empty() && !first {
+            options.next_interval(interval)
+        } else {
+            options.interval
+        };
+        first = false;
+        for change in changes {
+            if !on_change(change) {
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SimulatedBackend;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    fn fast() -> WatchOptions {
+        WatchOptions {
+            interval: Duration::from_millis(5),
+            max_interval: Duration::from_millis(20),
+            backoff: 2.0,
+        }
+    }
+
+    fn wait_for_first_poll(watcher: &StatusWatcher, service_type: &ServiceType) {
+        let deadline = Instant::now() + TIMEOUT;
+        while watcher.status(service_type).is_none() {
+            assert!(Instant::now() < deadline, "the watcher never polled");
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn test_reports_transitions() {
+        let backend = SimulatedBackend::new();
+        let daemon = ServiceType::Daemon {
+            plist_name: "com.example.daemon.plist",
+        };
+        let (watcher, changes) =
+            StatusWatcher::channel(backend.clone(), [ServiceType::MainApp, daemon], fast());
+        wait_for_first_poll(&watcher, &daemon);
+        assert_eq!(watcher.status(&daemon), Some(ServiceStatus::NotRegistered));
+
+        backend.register(&daemon).unwrap();
+        let change = changes.recv_timeout(TIMEOUT).unwrap();
+        assert_eq!(
+            change,
+            StatusChange {
+                service_type: daemon.into(),
+                old: ServiceStatus::NotRegistered,
+                new: ServiceStatus::RequiresApproval,
+            }
+        );
+
+        backend.approve(&daemon);
+        let change = changes.recv_timeout(TIMEOUT).unwrap();
+        assert_eq!(
+            (change.old, change.new),
+            (ServiceStatus::RequiresApproval, ServiceStatus::Enabled)
+        );
+        assert_eq!(watcher.status(&daemon), Some(ServiceStatus::Enabled));
+        assert_eq!(
+            watcher.status(&ServiceType::LoginItem { identifier: "x" }),
+            None
+        );
+
+        watcher.stop();
+        assert!(changes.recv_timeout(Duration::from_millis(50)).is_err());
+    }
+
+    #[test]
+    fn test_callback_and_refresh() {
+        let backend = SimulatedBackend::new();
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let options = WatchOptions {
+            // Long enough that only `refresh` can trigger the second poll within the test.
+            interval: Duration::from_secs(60),
+            max_interval: Duration::from_secs(60),
+            backoff: 1.0,
+        };
+        let watcher = StatusWatcher::spawn(backend.clone(), [ServiceType::MainApp], options, {
+            let seen = seen.clone();
+            move |change| seen.lock().unwrap().push(change)
+        });
+        wait_for_first_poll(&watcher, &ServiceType::MainApp);
+
+        backend.set_status(&ServiceType::MainApp, ServiceStatus::Enabled);
+        watcher.refresh();
+        let deadline = Instant::now() + TIMEOUT;
+        while seen.lock().unwrap().is_empty() {
+            assert!(Instant::now() < deadline, "refresh didn't poll");
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(seen.lock().unwrap()[0].new, ServiceStatus::Enabled);
+
+        // Dropping must not wait for the 60 second interval.
+        let started = Instant::now();
+        drop(watcher);
+        assert!(started.elapsed() < TIMEOUT);
+    }
+
+    #[test]
+    fn test_backoff() {
+        let options = fast();
+        let mut interval = options.interval;
+        let mut intervals = Vec::new();
+        for _ in 0..4 {
+            interval = options.next_interval(interval);
+            intervals.push(interval.as_millis());
+        }
+        assert_eq!(intervals, vec![10, 20, 20, 20]);
+
+        let fixed = WatchOptions {
+            backoff: 0.5,
+            ..options
+        };
+        assert_eq!(fixed.next_interval(fixed.interval), fixed.interval);
+
+        for backoff in [f64::INFINITY, f64::NAN, 1e300] {
+            let options = WatchOptions { backoff, ..options };
+            assert!(options.next_interval(options.max_interval) <= options.max_interval);
+        }
+
+        let zero = WatchOptions {
+            interval: Duration::ZERO,
+            max_interval: Duration::ZERO,
+            backoff: 1.0,
+        }
+        .clamped();
+        assert_eq!(zero.interval, MIN_INTERVAL);
+        assert_eq!(zero.next_interval(zero.interval), MIN_INTERVAL);
+    }
+
+    #[test]
+    fn test_unbounded_interval() {
+        let backend = SimulatedBackend::new();
+        let (watcher, changes) = StatusWatcher::channel(
+            backend.clone(),
+            [ServiceType::MainApp],
+            WatchOptions {
+                interval: Duration::MAX,
+                max_interval: Duration::MAX,
+                backoff: f64::INFINITY,
+            },
+        );
+        wait_for_first_poll(&watcher, &ServiceType::MainApp);
+
+        // Only a refresh polls again.
+        backend.set_status(&ServiceType::MainApp, ServiceStatus::Enabled);
+        watcher.refresh();
+        let change = changes.recv_timeout(TIMEOUT).unwrap();
+        assert_eq!(change.new, ServiceStatus::Enabled);
+        assert!(watcher.is_running());
+        watcher.stop();
+    }
+
+    #[test]
+    fn test_stops_when_receiver_dropped() {
+        let backend = SimulatedBackend::new();
+        let (watcher, changes) =
+            StatusWatcher::channel(backend.clone(), [ServiceType::MainApp], fast());
+        wait_for_first_poll(&watcher, &ServiceType::MainApp);
+        drop(changes);
+        backend.set_status(&ServiceType::MainApp, ServiceStatus::Enabled);
+        let deadline = Instant::now() + TIMEOUT;
+        while watcher.is_running() {
+            assert!(Instant::now() < deadline, "the watcher kept running");
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+}